@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use time::Date;
+use tokio::sync::OnceCell;
+
+/// A service date with its data, which is empty until the first request for the date loaded it
+type Entry<T> = (Date, Arc<OnceCell<Arc<T>>>);
+
+/// Keeps the data of the service dates that were requested last. Each date is loaded once, while
+/// requests for other dates don't wait for it. Dates that were not requested for the longest time
+/// are dropped when more than the capacity are cached, so arbitrary dates don't fill the memory.
+pub(crate) struct DateCache<T> {
+    capacity: usize,
+    /// The most recently requested date comes first
+    entries: Mutex<VecDeque<Entry<T>>>,
+}
+
+impl<T> DateCache<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Gets the data for the date or loads it if it is not cached. Concurrent requests for the same
+    /// date wait for the same load. Failed loads are not cached, so the next request tries again
+    pub(crate) async fn get_or_load<E, F, Fut>(&self, date: Date, load: F) -> Result<Arc<T>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        // The lock is only held to find the entry and not while loading
+        let cell = self.entry(date);
+        cell.get_or_try_init(|| async { load().await.map(Arc::new) })
+            .await
+            .cloned()
+    }
+
+    /// The entry for the date, which becomes the most recently requested one
+    fn entry(&self, date: Date) -> Arc<OnceCell<Arc<T>>> {
+        // The entries stay consistent even if another request panicked while holding the lock
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let cell = entries
+            .iter()
+            .position(|(cached_date, _)| *cached_date == date)
+            .and_then(|index| entries.remove(index))
+            .map(|(_, cell)| cell)
+            .unwrap_or_default();
+
+        entries.push_front((date, cell.clone()));
+        // Requests still using a dropped date keep its data until they are done
+        entries.truncate(self.capacity);
+        cell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::DateCache;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use time::macros::date;
+
+    #[tokio::test]
+    async fn drops_least_recently_requested_date() {
+        // Arrange
+        let cache = DateCache::new(2);
+        let loads = AtomicUsize::new(0);
+        let load = |value: u32| {
+            loads.fetch_add(1, Ordering::Relaxed);
+            async move { Ok::<_, Infallible>(value) }
+        };
+        cache
+            .get_or_load(date!(2024 - 09 - 10), || load(10))
+            .await
+            .unwrap();
+        cache
+            .get_or_load(date!(2024 - 09 - 11), || load(11))
+            .await
+            .unwrap();
+        cache
+            .get_or_load(date!(2024 - 09 - 10), || load(0))
+            .await
+            .unwrap();
+
+        // Act
+        cache
+            .get_or_load(date!(2024 - 09 - 12), || load(12))
+            .await
+            .unwrap();
+        let kept = cache.get_or_load(date!(2024 - 09 - 10), || load(0)).await;
+        let dropped = cache.get_or_load(date!(2024 - 09 - 11), || load(111)).await;
+
+        // Assert
+        assert_eq!(10, *kept.unwrap());
+        assert_eq!(111, *dropped.unwrap());
+        assert_eq!(4, loads.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn loads_date_again_after_failure() {
+        // Arrange
+        let cache = DateCache::new(1);
+        let service_date = date!(2024 - 09 - 10);
+        let failed = cache
+            .get_or_load(service_date, || async { Err::<u32, _>("unavailable") })
+            .await;
+
+        // Act
+        let loaded = cache
+            .get_or_load(service_date, || async { Ok::<_, &str>(10) })
+            .await;
+
+        // Assert
+        assert!(failed.is_err());
+        assert_eq!(10, *loaded.unwrap());
+    }
+}
@@ -0,0 +1,74 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use sql2raptor::snapshot::SnapshotError;
+use sql2raptor::LoadError;
+
+/// Errors while answering a request. Users only see a message and status code for them, the details
+/// are logged where they occur
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ApiError {
+    #[error("Error loading timetable: {0}")]
+    Load(#[from] LoadError),
+    #[error("Error querying database: {0}")]
+    Database(#[from] libsql::Error),
+    #[error("Error archiving timetable: {0}")]
+    Snapshot(#[from] SnapshotError),
+}
+
+impl ApiError {
+    pub(crate) fn status_code(&self) -> StatusCode {
+        match self {
+            // Retrying won't help as the feed itself is inconsistent
+            ApiError::Load(LoadError::UnknownStop { .. }) | ApiError::Snapshot(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ApiError::Load(LoadError::Database(_)) | ApiError::Database(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }
+    }
+
+    /// What went wrong in words users can act on
+    pub(crate) fn message(&self) -> &'static str {
+        match self {
+            ApiError::Load(LoadError::UnknownStop { .. }) => {
+                "Sorry, the timetable for this date contains errors. Could not search for connections."
+            }
+            ApiError::Load(LoadError::Database(_)) | ApiError::Database(_) => {
+                "Sorry, something on our side went wrong. Could not search for connections. Please try again later."
+            }
+            ApiError::Snapshot(_) => {
+                "Sorry, something on our side went wrong. Could not search for connections."
+            }
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status_code(), self.message()).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ApiError;
+    use axum::http::StatusCode;
+    use sql2raptor::LoadError;
+
+    #[test]
+    fn reports_unknown_stop_as_server_error() {
+        // Arrange
+        let error = ApiError::from(LoadError::UnknownStop {
+            trip_id: "trip".to_string(),
+            stop_id: "stop".to_string(),
+        });
+
+        // Act
+        let status_code = error.status_code();
+
+        // Assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status_code);
+        assert!(!error.message().contains("stop"));
+    }
+}
@@ -0,0 +1,100 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use raptor::one_to_all::one_to_all_raptor;
+use raptor::{QueryOptions, Time};
+use serde_json::{json, Value};
+use tracing::error;
+use crate::error::ApiError;
+use crate::request::DateTimeLocal;
+use crate::{get_location, get_walking_stops, AppState};
+
+const DEFAULT_BAND_MINUTES: u64 = 10;
+const DEFAULT_MAXIMUM_MINUTES: u64 = 60;
+
+#[derive(serde::Deserialize)]
+pub(crate) struct IsochroneRequest {
+    /// Stop name or coordinates as "latitude, longitude"
+    start: String,
+    departure: DateTimeLocal,
+    /// Size of the travel time bands in minutes
+    band_minutes: Option<u64>,
+    /// Stops reached later are not included
+    maximum_minutes: Option<u64>,
+    /// Set when only wheelchair accessible trips and foot-paths may be taken
+    wheelchair_accessible: Option<String>,
+}
+
+/// Returns the stops reachable from the start within the maximum travel time as GeoJSON points.
+/// Each point has the travel time in seconds, the upper bound of its travel time band in minutes
+/// and the amount of trips needed to reach it.
+pub(crate) async fn isochrone(State(state): State<AppState>, Query(request): Query<IsochroneRequest>) -> Response {
+    let band_minutes = request.band_minutes.unwrap_or(DEFAULT_BAND_MINUTES).max(1);
+    let maximum_minutes = request.maximum_minutes.unwrap_or(DEFAULT_MAXIMUM_MINUTES);
+
+    let start_location = match get_location(&state.connection, &request.start).await {
+        Ok(Some(start_location)) => start_location,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Start not found").into_response(),
+        Err(error) => {
+            error!("Error searching for start: {error}");
+            return ApiError::from(error).into_response();
+        }
+    };
+
+    let search_data = match state.get_search_data(request.departure.date()).await {
+        Ok(search_data) => search_data,
+        Err(error) => {
+            error!("{error}");
+            return error.into_response();
+        }
+    };
+
+    let access = get_walking_stops(&search_data, &start_location);
+    if access.is_empty() {
+        return (StatusCode::NOT_FOUND, "No stops near the start").into_response();
+    }
+
+    let departure_seconds = request.departure.to_seconds();
+    let raptor_data = search_data.snapshot.archived();
+    let options = QueryOptions { wheelchair_accessible: request.wheelchair_accessible.is_some() };
+    let arrivals = one_to_all_raptor(&access, &Time::from(departure_seconds), &raptor_data.timetable, &options);
+
+    let features: Vec<Value> = arrivals
+        .iter()
+        .enumerate()
+        .filter_map(|(stop_index, arrival)| {
+            let arrival = arrival.as_ref()?;
+            let arrival_seconds = arrival.time.seconds()?;
+            let travel_time = arrival_seconds - departure_seconds;
+            if travel_time > maximum_minutes * 60 {
+                return None;
+            }
+
+            let coordinates = search_data.stop_coordinates[stop_index].as_ref()?;
+            // Rounded up so a band includes all stops reached within its minutes
+            let band = travel_time.div_ceil(band_minutes * 60).max(1) * band_minutes;
+
+            Some(json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    // GeoJSON positions are longitude first
+                    "coordinates": [coordinates.longitude, coordinates.latitude],
+                },
+                "properties": {
+                    "stop_id": raptor_data.timetable.stops_data.stops[stop_index].id.as_str(),
+                    "travel_time": travel_time,
+                    "band": band,
+                    "trips": arrival.trips,
+                },
+            }))
+        })
+        .collect();
+
+    Json(json!({
+        "type": "FeatureCollection",
+        "features": features,
+    }))
+    .into_response()
+}
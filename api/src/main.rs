@@ -1,37 +1,113 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::convert::Infallible;
 use std::env;
-use std::env::{current_dir, current_exe};
 use std::net::Ipv4Addr;
+use std::path::Path;
 use std::sync::Arc;
-use askama_axum::{Response, Template};
-use axum::response::{Html, IntoResponse, Redirect};
-use axum::{async_trait, Form, Router};
-use axum::extract::{FromRef, FromRequestParts, Query, RawQuery, State};
-use axum::http::request::Parts;
+use askama_axum::Template;
+use axum::response::{Html, IntoResponse};
+use axum::{Form, Router};
+use axum::extract::{Query, State};
 use axum::http::StatusCode;
 use axum::routing::{get, post};
-use libsql::{named_params, Connection};
-use raptor::{raptor, Time};
+use raptor::journey::Leg;
+use raptor::reverse::reverse_raptor;
+use raptor::workspace::RaptorWorkspace;
+use raptor::{QueryOptions, Time};
 use tower_http::services::ServeDir;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use serde::Deserialize;
-use time::format_description::well_known::{Iso8601, Rfc3339};
-use time::{OffsetDateTime, PrimitiveDateTime};
-use time::format_description::well_known;
-use tracing::{debug, error};
-use raptor::shared::{RoutesData, StopsData};
-use sql2raptor::{assemble_raptor_data, get_routes, get_stops, setup_raptor, GetStopsReturn, PartialStop, RaptorDataSet};
-use crate::request::{DateTimeLocal, SearchConnectionRequest};
-
+use tracing::{debug, error, warn};
+use sql2raptor::setup_raptor_with_foot_paths;
+use sql2raptor::details::ArchivedGtfsDetails;
+use sql2raptor::snapshot::{snapshot_path, Snapshot};
+use sql2raptor::footpaths::{get_nearby_stops, get_stop_coordinates, Coordinates, FootPathSettings};
+use time::Date;
+use crate::cache::DateCache;
+use crate::error::ApiError;
+use crate::request::{parse_coordinates, DateTimeLocal, SearchConnectionRequest};
+
+mod cache;
+mod error;
+mod isochrone;
 mod request;
 
 
 #[derive(Clone)]
 struct AppState {
     connection: libsql::Connection,
-    raptor_data: Arc<RaptorDataSet>,
+    /// The RAPTOR data only contains the trips running on a service date, so it is loaded for each
+    /// date on first use
+    raptor_data_by_date: Arc<DateCache<SearchData>>,
+}
+
+/// How many service dates are kept in memory. Most requests are for today and the next days
+const CACHED_DATES: usize = 4;
+
+/// The data to search for connections on a service date. Requests share it and query the archived
+/// timetable in place
+struct SearchData {
+    snapshot: Snapshot,
+    /// To find the stops close to origins and destinations entered as coordinates
+    stop_coordinates: Vec<Option<Coordinates>>,
+}
+
+thread_local! {
+    /// Each worker thread answers its queries with its own workspace, so queries don't allocate the
+    /// labels again
+    static WORKSPACE: RefCell<RaptorWorkspace> = RefCell::default();
+}
+
+/// How far users walk from their origin to the first stop and from the last stop to their destination
+const ACCESS_EGRESS_SETTINGS: FootPathSettings = FootPathSettings {
+    radius: 800.0,
+    walking_speed: 1.2,
+    max_walking_time: 900,
+};
+
+impl AppState {
+    async fn get_search_data(&self, service_date: Date) -> Result<Arc<SearchData>, ApiError> {
+        self.raptor_data_by_date.get_or_load(service_date, || self.load_search_data(service_date)).await
+    }
+
+    async fn load_search_data(&self, service_date: Date) -> Result<SearchData, ApiError> {
+        debug!("Loading RAPTOR data for {service_date}");
+        let snapshot = match load_snapshot(&service_date) {
+            Some(snapshot) => snapshot,
+            None => {
+                // Same data as in a snapshot, so results don't depend on whether there is one
+                warn!("No snapshot for {service_date}, generating foot-paths while loading");
+                let raptor_data = setup_raptor_with_foot_paths(&self.connection, &service_date, &FootPathSettings::default()).await?;
+                Snapshot::archive(&raptor_data)?
+            }
+        };
+        let raptor_data = snapshot.archived();
+        debug!("Split overtaking trips into another route {} times", raptor_data.overtaking_splits);
+        let index_by_stop_id = &raptor_data.index_by_stop_id;
+        let stop_coordinates = get_stop_coordinates(&self.connection, index_by_stop_id.len(), |stop_id| index_by_stop_id.get(stop_id).map(|stop_index| stop_index.to_native() as usize)).await?;
+        Ok(SearchData { snapshot, stop_coordinates })
+    }
+}
+
+/// Where snapshots written by sql2raptor are looked for. Relative to where the app is run like the
+/// database
+const SNAPSHOT_DIRECTORY: &str = "snapshots";
+
+/// Maps the snapshot for the service date. Returns none if there is no usable snapshot, so the data
+/// needs to be loaded from the database
+fn load_snapshot(service_date: &Date) -> Option<Snapshot> {
+    let path = snapshot_path(Path::new(SNAPSHOT_DIRECTORY), service_date);
+    if !path.exists() {
+        return None;
+    }
+
+    match Snapshot::open(&path, service_date) {
+        Ok(snapshot) => Some(snapshot),
+        Err(error) => {
+            warn!("Ignoring snapshot {}: {error}", path.display());
+            None
+        }
+    }
 }
 
 #[tokio::main]
@@ -47,12 +123,11 @@ async fn main() {
     let database = libsql::Builder::new_local("gtfs.db").build().await.unwrap();
     let connection = database.connect().unwrap();
 
-    let raptor_data = setup_raptor(&connection).await.unwrap();
-
-    let state = AppState { connection, raptor_data: Arc::new(raptor_data) };
+    let state = AppState { connection, raptor_data_by_date: Arc::new(DateCache::new(CACHED_DATES)) };
 
     let app = Router::new()
         .route("/", get(index))
+        .route("/isochrone", get(isochrone::isochrone))
         .route("/stops/start", post(search_start_stops))
         .route("/stops/end", post(search_end_stops))
         // If the route could not be matched it might be a file
@@ -71,12 +146,20 @@ async fn main() {
 }
 
 #[derive(serde::Serialize)]
-struct ResultRow {
-    stop_name: String,
-    route: usize,
-    trip_number: usize,
-    boarded: String,
-    exited: String,
+struct LegRow {
+    means: String,
+    from: String,
+    to: String,
+    departure: String,
+    arrival: String,
+}
+
+#[derive(serde::Serialize)]
+struct JourneyRow {
+    departure: String,
+    arrival: String,
+    transfers: usize,
+    legs: Vec<LegRow>,
 }
 
 #[derive(Template, Default)]
@@ -88,7 +171,11 @@ struct IndexTemplate {
     start_error: Option<String>,
     end_error: Option<String>,
     departure: Option<String>,
-    results: Option<Vec<Vec<ResultRow>>>,
+    /// Whether the departure is the latest arrival instead
+    arrive_by: bool,
+    /// Whether journeys need to be wheelchair accessible
+    wheelchair_accessible: bool,
+    results: Option<Vec<JourneyRow>>,
 }
 
 async fn get_stop_id(connection: &libsql::Connection, stop_name: &str) -> Result<Option<String>, libsql::Error> {
@@ -98,6 +185,74 @@ async fn get_stop_id(connection: &libsql::Connection, stop_name: &str) -> Result
     let row = rows.next().await?;
     row.map(|row| row.get::<String>(0)).transpose()
 }
+
+/// Where a journey starts or ends
+#[derive(Debug)]
+enum Location {
+    /// The id of a stop entered by its name
+    Stop(String),
+    /// Entered as "latitude, longitude"
+    Coordinates(Coordinates),
+}
+
+async fn get_location(connection: &libsql::Connection, input: &str) -> Result<Option<Location>, libsql::Error> {
+    if let Some(coordinates) = parse_coordinates(input) {
+        return Ok(Some(Location::Coordinates(coordinates)));
+    }
+
+    let stop_id = get_stop_id(connection, input).await?;
+    Ok(stop_id.map(Location::Stop))
+}
+
+/// The stops to walk to or from at a location with their walking time
+fn get_walking_stops(search_data: &SearchData, location: &Location) -> Vec<(usize, Time)> {
+    match location {
+        Location::Stop(stop_id) => search_data
+            .snapshot
+            .archived()
+            .index_by_stop_id
+            .get(stop_id.as_str())
+            .map(|stop_index| vec![(stop_index.to_native() as usize, Time::from(0))])
+            .unwrap_or_default(),
+        Location::Coordinates(coordinates) => get_nearby_stops(&search_data.stop_coordinates, coordinates, &ACCESS_EGRESS_SETTINGS),
+    }
+}
+/// The name of the GTFS route type. Includes the extended route types many European feeds use
+fn route_type_name(route_type: Option<u32>) -> &'static str {
+    match route_type {
+        Some(0 | 900..=999) => "Tram",
+        Some(1 | 400..=499) => "Subway",
+        Some(2 | 100..=199) => "Train",
+        Some(3 | 200..=299 | 700..=799) => "Bus",
+        Some(4 | 1000..=1099 | 1200..=1299) => "Ferry",
+        Some(5) => "Cable tram",
+        Some(6 | 1300..=1399) => "Aerial lift",
+        Some(7 | 1400..=1499) => "Funicular",
+        Some(11 | 800..=899) => "Trolleybus",
+        Some(12) => "Monorail",
+        _ => "Route",
+    }
+}
+
+/// How users recognize the vehicle of a trip, like "Bus 42 towards Central"
+fn describe_trip(details: &ArchivedGtfsDetails, route: usize, trip_number: usize) -> String {
+    let route_details = details.get_route(route);
+    let trip = details.get_trip(route, trip_number);
+    // Trains are often known by their number while buses are known by their line
+    let name = trip
+        .short_name
+        .as_ref()
+        .or(route_details.short_name.as_ref())
+        .or(route_details.long_name.as_ref())
+        .unwrap_or(&route_details.id);
+    let means = route_type_name(route_details.route_type.as_ref().map(|route_type| route_type.to_native()));
+
+    match trip.headsign.as_ref() {
+        Some(headsign) => format!("{means} {name} towards {headsign}"),
+        None => format!("{means} {name}"),
+    }
+}
+
 fn try_format(departure: &DateTimeLocal) -> Option<String> {
     match departure.format() {
         Ok(departure) => Some(departure),
@@ -107,7 +262,25 @@ fn try_format(departure: &DateTimeLocal) -> Option<String> {
         }
     }
 }
-async fn index(State(state): State<AppState>, Query(request): Query<SearchConnectionRequest>) -> impl IntoResponse {
+/// Shows the message of the error with the search entered by the user
+fn error_page(error: ApiError, start: String, end: String, departure: &DateTimeLocal, arrive_by: bool, wheelchair_accessible: bool) -> (StatusCode, IndexTemplate) {
+    let template = IndexTemplate {
+        error: Some(error.message().to_string()),
+        start: Some(start),
+        end: Some(end),
+        departure: try_format(departure),
+        arrive_by,
+        wheelchair_accessible,
+        ..Default::default()
+    };
+
+    (error.status_code(), template)
+}
+
+async fn index(State(state): State<AppState>, Query(request): Query<SearchConnectionRequest>) -> (StatusCode, IndexTemplate) {
+    // Checkboxes are only submitted when checked
+    let arrive_by = request.arrive_by.is_some();
+    let wheelchair_accessible = request.wheelchair_accessible.is_some();
     match request {
         SearchConnectionRequest {
             start: Some(start),
@@ -115,7 +288,7 @@ async fn index(State(state): State<AppState>, Query(request): Query<SearchConnec
             departure: Some(departure),
             ..
         } => {
-            let start_result = get_stop_id(&state.connection, &start).await;
+            let start_result = get_location(&state.connection, &start).await;
             // let formatted = departure.format(&well_known::Rfc2822::);
             // let formatted = departure.format()
             // debug!("Departure: {formatted:?}");
@@ -123,100 +296,99 @@ async fn index(State(state): State<AppState>, Query(request): Query<SearchConnec
             // debug!("Departure: {departure}");
 
             // Don't proceed if the first already failed
-            let Ok(start_id) = start_result else {
-                error!("Error searching for start stop: {start_result:?}");
-                let template = IndexTemplate {
-                    error: Some("Sorry, something on our side went wrong. Could not search for connections.".to_string()),
-                    start: Some(start),
-                    end: Some(end),
-                    departure: try_format(&departure),
-
-                    ..Default::default()
-                };
-                return template;
+            let start_location = match start_result {
+                Ok(start_location) => start_location,
+                Err(error) => {
+                    error!("Error searching for start stop: {error}");
+                    return error_page(ApiError::from(error), start, end, &departure, arrive_by, wheelchair_accessible);
+                }
             };
 
-            let end_result = get_stop_id(&state.connection, &end).await;
-            let Ok(end_id) = end_result else {
-                error!("Error searching for end stop: {end_result:?}");
-
-                let template = IndexTemplate {
-                    error: Some("Sorry, something on our side went wrong. Could not search for connections.".to_string()),
-                    start: Some(start),
-                    end: Some(end),
-                    departure: try_format(&departure),
-                    ..Default::default()
-                };
-                return template;
+            let end_result = get_location(&state.connection, &end).await;
+            let end_location = match end_result {
+                Ok(end_location) => end_location,
+                Err(error) => {
+                    error!("Error searching for end stop: {error}");
+                    return error_page(ApiError::from(error), start, end, &departure, arrive_by, wheelchair_accessible);
+                }
             };
 
-            match (start_id, end_id) {
-                (Some(start_id), Some(end_id)) => {
-                    //TODO search for connections
-                    debug!("Searching for connection from start id {} to end id {}", &start_id, &end_id);
+            match (start_location, end_location) {
+                (Some(start_location), Some(end_location)) => {
+                    debug!("Searching for connection from {start} to {end}");
 
-                    let source_index = *state.raptor_data.index_by_stop_id.get(&start_id).unwrap();
-                    let target_index = *state.raptor_data.index_by_stop_id.get(&end_id).unwrap();
-                    // let (hours, minutes, seconds) = departure.time().as_hms();
-                    let raptor_departure = Time::from(departure.to_seconds());
-                    dbg!(&source_index, &target_index);
-                    let rounds = raptor(
-                        source_index,
-                        target_index,
-                        &raptor_departure,
-                        //TODO remove clone. These should be read only by reference
-                        state.raptor_data.routes_data.clone(),
-                        state.raptor_data.stops_data.clone(),
-                    );
-
-                    dbg!(&rounds[0].len());
-
-                    let with_id = |(stop_index, connection): (usize, raptor::Connection)| {
-                        let stop_id = state.raptor_data.stops_data.stops[stop_index].id.clone();
-                        (stop_id, connection)
+                    let search_data = match state.get_search_data(departure.date()).await {
+                        Ok(search_data) => search_data,
+                        Err(error) => {
+                            error!("{error}");
+                            return error_page(error, start, end, &departure, arrive_by, wheelchair_accessible);
+                        }
                     };
 
-                    let collect_ids = |(stop_id, connection): &(String, raptor::Connection)| {
-                        let mut ids = vec![stop_id.clone()];
-
-                        if let raptor::Connection::Connection { boarded_at_stop, exited_at_stop, .. } = connection {
-                            let boarded_id = state.raptor_data.stops_data.stops[*boarded_at_stop].id.clone();
-                            let exited_id = state.raptor_data.stops_data.stops[*exited_at_stop].id.clone();
-                            ids.push(boarded_id);
-                            ids.push(exited_id);
-                        }
+                    let access = get_walking_stops(&search_data, &start_location);
+                    let egress = get_walking_stops(&search_data, &end_location);
+                    if access.is_empty() || egress.is_empty() {
+                        let no_stops = |stops: &[(usize, Time)]| stops.is_empty().then(|| "No stops nearby. Please try another one".to_string());
+                        return (StatusCode::OK, IndexTemplate {
+                            start_error: no_stops(&access),
+                            end_error: no_stops(&egress),
+                            start: Some(start),
+                            end: Some(end),
+                            departure: try_format(&departure),
+                            arrive_by,
+                            wheelchair_accessible,
+                            ..Default::default()
+                        });
+                    }
 
-                        ids
+                    let raptor_data = search_data.snapshot.archived();
+                    // let (hours, minutes, seconds) = departure.time().as_hms();
+                    let raptor_departure = Time::from(departure.to_seconds());
+                    let options = QueryOptions { wheelchair_accessible };
+                    let journeys = if arrive_by {
+                        reverse_raptor(
+                            &access,
+                            &egress,
+                            &raptor_departure,
+                            &raptor_data.timetable,
+                            &options,
+                        )
+                    } else {
+                        WORKSPACE.with_borrow_mut(|workspace| {
+                            workspace.run_access_egress(&access, &raptor_departure, &egress, &raptor_data.timetable, &options);
+                            workspace.access_egress_journeys(&access, &egress, &raptor_departure, &raptor_data.timetable)
+                        })
                     };
-                    // let with_ids = | mut round: HashMap<usize, Connection>| round.into_iter().map(with_id).collect();
-                    let rounds: Vec<Vec<(String, raptor::Connection)>> = rounds.into_iter().map(|round| round.into_iter().map(with_id).collect()).collect();
+
+                    let stop_id = |stop_index: usize| raptor_data.timetable.stops_data.stops[stop_index].id.to_string();
 
                     // Collect all distinct stop ids for a batched SQL query
-                    let mut ids: Vec<String> = rounds.iter().flatten().map(collect_ids).flatten().collect();
+                    let mut ids: Vec<String> = journeys
+                        .iter()
+                        .flat_map(|journey| &journey.legs)
+                        .flat_map(|leg| match leg {
+                            Leg::Transit { boarded_at_stop, exited_at_stop, .. } => [*boarded_at_stop, *exited_at_stop],
+                            Leg::Continuation { from_stop, exited_at_stop, .. } => [*from_stop, *exited_at_stop],
+                            Leg::FootPath { from_stop, to_stop, .. } => [*from_stop, *to_stop],
+                            Leg::Access { to_stop: stop, .. } | Leg::Egress { from_stop: stop, .. } => [*stop, *stop],
+                        })
+                        .map(stop_id)
+                        .collect();
 
                     // Remove duplicates
                     // Nor sure if this is faster than collecting to a hashset TODO measure
                     ids.sort();
                     ids.dedup();
-                    /// Count of ids has to be less than SQLITE_MAX_VARIABLE_NUMBER.
-                    /// See https://www.sqlite.org/lang_expr.html#parameters
+                    // Count of ids has to be less than SQLITE_MAX_VARIABLE_NUMBER.
+                    // See https://www.sqlite.org/lang_expr.html#parameters
                     let query_parameters = ids.iter().enumerate().map(|(index, _)| format!("?{}", index + 1)).collect::<Vec<String>>().join(", ");
                     let query = format!("SELECT id, name FROM stops WHERE id IN ({query_parameters})");
-                    // dbg!(&query, libsql::ffi::SQLITE_VAR);
                     let result = state.connection.query(&query, ids).await;
                     let mut rows = match result {
                         Ok(rows) => rows,
                         Err(error) => {
                             error!("Error looking up stop names: {error}");
-                            let template = IndexTemplate {
-                                error: Some("Sorry, something on our side went wrong. Could not search for connections.".to_string()),
-                                start: Some(start),
-                                end: Some(end),
-                                departure: try_format(&departure),
-                                ..Default::default()
-                            };
-
-                            return template;
+                            return error_page(ApiError::from(error), start, end, &departure, arrive_by, wheelchair_accessible);
                         }
                     };
 
@@ -227,15 +399,7 @@ async fn index(State(state): State<AppState>, Query(request): Query<SearchConnec
                             Ok(None) => break,
                             Err(error) => {
                                 error!("Error reading stop names rows: {error}");
-                                let template = IndexTemplate {
-                                    error: Some("Sorry, something on our side went wrong. Could not search for connections.".to_string()),
-                                    start: Some(start),
-                                    end: Some(end),
-                                    departure: try_format(&departure),
-                                    ..Default::default()
-                                };
-
-                                return template;
+                                return error_page(ApiError::from(error), start, end, &departure, arrive_by, wheelchair_accessible);
                             }
                         };
 
@@ -243,15 +407,7 @@ async fn index(State(state): State<AppState>, Query(request): Query<SearchConnec
                             Ok(id) => id,
                             Err(error) => {
                                 error!("Error reading id from stop names row: {error}");
-                                let template = IndexTemplate {
-                                    error: Some("Sorry, something on our side went wrong. Could not search for connections.".to_string()),
-                                    start: Some(start),
-                                    end: Some(end),
-                                    departure: try_format(&departure),
-                                    ..Default::default()
-                                };
-
-                                return template;
+                                return error_page(ApiError::from(error), start, end, &departure, arrive_by, wheelchair_accessible);
                             }
                         };
 
@@ -259,63 +415,95 @@ async fn index(State(state): State<AppState>, Query(request): Query<SearchConnec
                             Ok(name) => name,
                             Err(error) => {
                                 error!("Error reading name from stop names row: {error}");
-                                let template = IndexTemplate {
-                                    error: Some("Sorry, something on our side went wrong. Could not search for connections.".to_string()),
-                                    start: Some(start),
-                                    end: Some(end),
-                                    departure: try_format(&departure),
-                                    ..Default::default()
-                                };
-
-                                return template;
+                                return error_page(ApiError::from(error), start, end, &departure, arrive_by, wheelchair_accessible);
                             }
                         };
 
                         names_by_id.insert(id, name);
                     }
 
-                    let mut results = Vec::new();
-
-                    for round in rounds.into_iter() {
-                        let mut output = Vec::new();
-                        for (stop_id, connection) in round {
-                            let raptor::Connection::Connection { route, trip_number, boarded_at_stop, exited_at_stop } = connection else {
-                                error!("Unexpected connection type while constructing output");
-                                continue;
-                            };
-
-                            let stop_name = names_by_id.get(&stop_id).unwrap();
-                            let id = state.raptor_data.stops_data.stops[boarded_at_stop].id.clone();
-                            let boarded = names_by_id.get(&id).unwrap();
-                            let id = state.raptor_data.stops_data.stops[exited_at_stop].id.clone();
-                            let exited = names_by_id.get(&id).unwrap();
-                            output.push(ResultRow { stop_name: stop_name.to_string(), route, trip_number, boarded: boarded.to_string(), exited: exited.to_string() });
-                        }
+                    let stop_name = |stop_index: usize| {
+                        names_by_id.get(&stop_id(stop_index)).cloned().unwrap_or_default()
+                    };
 
-                        results.push(output);
-                    }
+                    let results = journeys
+                        .iter()
+                        .map(|journey| {
+                            let legs = journey
+                                .legs
+                                .iter()
+                                .map(|leg| match leg {
+                                    Leg::Transit { route, trip_number, boarded_at_stop, exited_at_stop, departure, arrival } => LegRow {
+                                        means: describe_trip(&raptor_data.details, *route, *trip_number),
+                                        from: stop_name(*boarded_at_stop),
+                                        to: stop_name(*exited_at_stop),
+                                        departure: departure.to_string(),
+                                        arrival: arrival.to_string(),
+                                    },
+                                    // The vehicle changes its line, so riders need to know it continues as the next one
+                                    Leg::Continuation { route, trip_number, from_stop, exited_at_stop, departure, arrival } => LegRow {
+                                        means: format!("Stay seated: {}", describe_trip(&raptor_data.details, *route, *trip_number)),
+                                        from: stop_name(*from_stop),
+                                        to: stop_name(*exited_at_stop),
+                                        departure: departure.to_string(),
+                                        arrival: arrival.to_string(),
+                                    },
+                                    Leg::FootPath { from_stop, to_stop, departure, arrival } => LegRow {
+                                        means: "Walk".to_string(),
+                                        from: stop_name(*from_stop),
+                                        to: stop_name(*to_stop),
+                                        departure: departure.to_string(),
+                                        arrival: arrival.to_string(),
+                                    },
+                                    Leg::Access { to_stop, departure, arrival } => LegRow {
+                                        means: "Walk".to_string(),
+                                        from: start.clone(),
+                                        to: stop_name(*to_stop),
+                                        departure: departure.to_string(),
+                                        arrival: arrival.to_string(),
+                                    },
+                                    Leg::Egress { from_stop, departure, arrival } => LegRow {
+                                        means: "Walk".to_string(),
+                                        from: stop_name(*from_stop),
+                                        to: end.clone(),
+                                        departure: departure.to_string(),
+                                        arrival: arrival.to_string(),
+                                    },
+                                })
+                                .collect();
+
+                            JourneyRow {
+                                departure: journey.departure().to_string(),
+                                arrival: journey.arrival().to_string(),
+                                transfers: journey.transfers(),
+                                legs,
+                            }
+                        })
+                        .collect();
 
-                    let template = IndexTemplate {
+                    (StatusCode::OK, IndexTemplate {
                         start: Some(start),
                         end: Some(end),
                         departure: try_format(&departure),
+                        arrive_by,
+                        wheelchair_accessible,
                         results: Some(results),
                         ..Default::default()
-                    };
-                    template
+                    })
                 }
-                (start_id, end_id) => {
-                    let start_error = start_id.map_or(Some("Not found. Please try another one".to_string()), |_| None);;
-                    let end_error = end_id.map_or(Some("Not found. Please try another one".to_string()), |_| None);;
-                    let template = IndexTemplate {
+                (start_location, end_location) => {
+                    let start_error = start_location.map_or(Some("Not found. Please try another one".to_string()), |_| None);
+                    let end_error = end_location.map_or(Some("Not found. Please try another one".to_string()), |_| None);
+                    (StatusCode::OK, IndexTemplate {
                         start_error,
                         end_error,
                         start: Some(start),
                         end: Some(end),
                         departure: try_format(&departure),
+                        arrive_by,
+                        wheelchair_accessible,
                         ..Default::default()
-                    };
-                    template
+                    })
                 }
             }
         }
@@ -323,7 +511,8 @@ async fn index(State(state): State<AppState>, Query(request): Query<SearchConnec
             start,
             end,
             departure,
-        } => IndexTemplate { start, end, departure: departure.as_ref().and_then(try_format), ..Default::default() },
+            ..
+        } => (StatusCode::OK, IndexTemplate { start, end, departure: departure.as_ref().and_then(try_format), arrive_by, wheelchair_accessible, ..Default::default() }),
     }
 }
 
@@ -390,3 +579,35 @@ async fn search_start_stops(State(state): State<AppState>, Form(request): Form<S
 
     search_stops(&state.connection, &request.start).await
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::describe_trip;
+    use sql2raptor::details::{ArchivedGtfsDetails, GtfsDetails, RouteDetails, TripDetails};
+
+    #[test]
+    fn describes_trip_by_route_and_headsign() {
+        // Arrange
+        let details = GtfsDetails {
+            routes: vec![RouteDetails {
+                id: "route".to_string(),
+                short_name: Some("42".to_string()),
+                long_name: Some("Ring".to_string()),
+                route_type: Some(3),
+            }],
+            trips: vec![vec![TripDetails {
+                id: "trip".to_string(),
+                headsign: Some("Central".to_string()),
+                short_name: None,
+            }]],
+        };
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&details).unwrap();
+        let details = rkyv::access::<ArchivedGtfsDetails, rkyv::rancor::Error>(&bytes).unwrap();
+
+        // Act
+        let description = describe_trip(details, 0, 0);
+
+        // Assert
+        assert_eq!("Bus 42 towards Central", description);
+    }
+}
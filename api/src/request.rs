@@ -1,16 +1,16 @@
-use std::fmt::{Display, Formatter};
 use time::format_description::well_known::{iso8601, Iso8601};
 use time::format_description::well_known::iso8601::TimePrecision;
-use time::{error, PrimitiveDateTime};
+use time::{error, Date, PrimitiveDateTime};
 use time::macros::format_description;
+use sql2raptor::footpaths::Coordinates;
 
-pub(self) const CONFIGURATION: iso8601::EncodedConfig = iso8601::Config::DEFAULT
+const CONFIGURATION: iso8601::EncodedConfig = iso8601::Config::DEFAULT
     .set_time_precision(TimePrecision::Second {
         decimal_digits: None,
     })
     .encode();
 
-pub(self) const FORMAT: Iso8601<CONFIGURATION> = Iso8601::<CONFIGURATION>;
+const FORMAT: Iso8601<CONFIGURATION> = Iso8601::<CONFIGURATION>;
 
 time::serde::format_description!(date_time_local, PrimitiveDateTime, FORMAT);
 
@@ -27,6 +27,13 @@ impl DateTimeLocal {
         self.0.format(format)
     }
 
+    /// The date the user wants to travel on which determines the services running
+    pub(crate) fn date(&self) -> Date {
+        self.0.date()
+    }
+
+    /// Seconds since the start of the date which is how times are represented in the RAPTOR data
+    /// loaded for that date
     pub(crate) fn to_seconds(&self) -> u64 {
         let (hours, minutes, seconds) = self.0.as_hms();
         hours as u64 * 60 * 60 + minutes as u64 * 60 + seconds as u64
@@ -38,4 +45,18 @@ pub(crate) struct SearchConnectionRequest {
     pub(crate) start: Option<String>,
     pub(crate) end: Option<String>,
     pub(crate) departure: Option<DateTimeLocal>,
-}
\ No newline at end of file
+    /// Set when the departure is the time to arrive by
+    pub(crate) arrive_by: Option<String>,
+    /// Set when journeys need to be wheelchair accessible
+    pub(crate) wheelchair_accessible: Option<String>,
+}
+
+/// Parses coordinates entered as "latitude, longitude" in degrees
+pub(crate) fn parse_coordinates(input: &str) -> Option<Coordinates> {
+    let (latitude, longitude) = input.split_once(',')?;
+    let latitude: f64 = latitude.trim().parse().ok()?;
+    let longitude: f64 = longitude.trim().parse().ok()?;
+
+    let is_valid = (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude);
+    is_valid.then_some(Coordinates { latitude, longitude })
+}
@@ -1,105 +1,143 @@
-use benchmark::fibonacci;
-use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
-use raptor::shared::{RoutesData, StopsData};
-use raptor::{raptor, raptor_bugged, Time};
-use sql2raptor::{setup_raptor, RaptorDataSet};
-use std::time::Duration;
+use criterion::{criterion_group, criterion_main, Criterion};
+use raptor::shared::{ArchivedStopTime, ArchivedTimetable};
+use raptor::workspace::RaptorWorkspace;
+use raptor::{raptor, raptor_bugged, QueryOptions, Time};
+use sql2raptor::footpaths::FootPathSettings;
+use sql2raptor::setup_raptor_with_foot_paths;
+use sql2raptor::snapshot::{snapshot_path, Snapshot};
+use std::path::Path;
+use time::macros::date;
 
-fn setup(start: &str, end: &str) -> (usize, usize, Time, RoutesData, StopsData) {
-    // Quick and dirty
-    let runtime = tokio::runtime::Builder::new_current_thread()
-        .build()
-        .unwrap();
-    let data = runtime.block_on(async {
-        let database = libsql::Builder::new_local("gtfs.db").build().await.unwrap();
-        let connection = database.connect().unwrap();
+fn setup(start: &str, end: &str) -> (usize, usize, Time, Snapshot) {
+    // A weekday to have the regular service
+    let service_date = date!(2024 - 09 - 10);
 
-        setup_raptor(&connection).await.unwrap()
-    });
+    // Reading a snapshot written by sql2raptor is faster than loading from the database. Queries
+    // run on the archived timetable either way like in the api
+    let path = snapshot_path(Path::new("snapshots"), &service_date);
+    let snapshot = if path.exists() {
+        Snapshot::open(&path, &service_date).unwrap()
+    } else {
+        // Quick and dirty
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let data = runtime.block_on(async {
+            let database = libsql::Builder::new_local("gtfs.db").build().await.unwrap();
+            let connection = database.connect().unwrap();
+
+            setup_raptor_with_foot_paths(&connection, &service_date, &FootPathSettings::default())
+                .await
+                .unwrap()
+        });
+        Snapshot::archive(&data).unwrap()
+    };
 
-    let RaptorDataSet {
-        index_by_stop_id,
-        routes_data,
-        stops_data,
-    } = data;
+    let index_by_stop_id = &snapshot.archived().index_by_stop_id;
     let departure = Time::from(12 * 60 * 60);
-    let source_index = *index_by_stop_id.get(start).unwrap();
-    let target_index = *index_by_stop_id.get(end).unwrap();
+    let source_index = index_by_stop_id.get(start).unwrap().to_native() as usize;
+    let target_index = index_by_stop_id.get(end).unwrap().to_native() as usize;
 
-    (
-        source_index,
-        target_index,
-        departure,
-        routes_data,
-        stops_data,
-    )
+    (source_index, target_index, departure, snapshot)
+}
+
+/// Prints how much memory the arrays of the timetable take up
+fn report_memory(timetable: &ArchivedTimetable) {
+    let routes_data = &timetable.routes_data;
+    let stops_data = &timetable.stops_data;
+    let stop_times = size_of_val(routes_data.stop_times.as_slice());
+    let transfers = size_of_val(stops_data.transfers.as_slice());
+    let routes = size_of_val(routes_data.routes.as_slice())
+        + size_of_val(routes_data.route_stops.as_slice());
+    let stops = size_of_val(stops_data.stops.as_slice())
+        + size_of_val(stops_data.stop_routes.as_slice())
+        + size_of_val(stops_data.stop_route_positions.as_slice());
+
+    println!(
+        "{} stop times take {stop_times} bytes ({} bytes each), {} transfers take \
+        {transfers} bytes, routes take {routes} bytes and stops take {stops} bytes",
+        routes_data.stop_times.len(),
+        size_of::<ArchivedStopTime>(),
+        stops_data.transfers.len(),
+    );
 }
 
 pub fn benchmark(criterion: &mut Criterion) {
     // Prepare data
     // Haven't found a better solution than to create a temporary async runtime
     // to call an async setup function
+    // Queries only borrow the timetable, so it is loaded once per case and not for every iteration
+    let (first_source, first_target, first_departure, first_snapshot) = setup("1808", "1811");
+    let (second_source, second_target, second_departure, second_snapshot) = setup("687", "2");
+    let first_timetable = &first_snapshot.archived().timetable;
+    let second_timetable = &second_snapshot.archived().timetable;
+    // Both cases load the same service date
+    report_memory(first_timetable);
 
     let mut group = criterion.benchmark_group("raptor");
     group.bench_function("first case", |bencher| {
-        bencher.iter_batched(
-            || setup("1808", "1811"),
-            |(source_index, target_index, departure, routes_data, stops_data)| {
-                raptor(
-                    source_index,
-                    target_index,
-                    &departure,
-                    routes_data,
-                    stops_data,
-                )
-            },
-            BatchSize::SmallInput,
-        )
+        bencher.iter(|| {
+            raptor(
+                first_source,
+                first_target,
+                &first_departure,
+                first_timetable,
+            )
+        })
     });
     group.bench_function("first case (bugged)", |bencher| {
-        bencher.iter_batched(
-            || setup("1808", "1811"),
-            |(source_index, target_index, departure, routes_data, stops_data)| {
-                raptor_bugged(
-                    source_index,
-                    target_index,
-                    &departure,
-                    routes_data,
-                    stops_data,
-                )
-            },
-            BatchSize::SmallInput,
-        )
+        bencher.iter(|| {
+            raptor_bugged(
+                first_source,
+                first_target,
+                &first_departure,
+                first_timetable,
+            )
+        })
+    });
+    // Kept between iterations like a server thread would keep it between requests
+    let mut workspace = RaptorWorkspace::default();
+    group.bench_function("first case (workspace)", |bencher| {
+        bencher.iter(|| {
+            workspace.run(
+                first_source,
+                first_target,
+                &first_departure,
+                first_timetable,
+                &QueryOptions::default(),
+            )
+        })
     });
     group.bench_function("second case", |bencher| {
-        bencher.iter_batched(
-            || setup("687", "2"),
-            |(source_index, target_index, departure, routes_data, stops_data)| {
-                raptor(
-                    source_index,
-                    target_index,
-                    &departure,
-                    routes_data,
-                    stops_data,
-                )
-            },
-            BatchSize::SmallInput,
-        )
+        bencher.iter(|| {
+            raptor(
+                second_source,
+                second_target,
+                &second_departure,
+                second_timetable,
+            )
+        })
     });
     group.bench_function("second case (bugged)", |bencher| {
-        bencher.iter_batched(
-            || setup("687", "2"),
-            |(source_index, target_index, departure, routes_data, stops_data)| {
-                raptor_bugged(
-                    source_index,
-                    target_index,
-                    &departure,
-                    routes_data,
-                    stops_data,
-                )
-            },
-            BatchSize::SmallInput,
-        )
+        bencher.iter(|| {
+            raptor_bugged(
+                second_source,
+                second_target,
+                &second_departure,
+                second_timetable,
+            )
+        })
+    });
+    group.bench_function("second case (workspace)", |bencher| {
+        bencher.iter(|| {
+            workspace.run(
+                second_source,
+                second_target,
+                &second_departure,
+                second_timetable,
+                &QueryOptions::default(),
+            )
+        })
     });
     group.finish();
 }
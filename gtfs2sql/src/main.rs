@@ -4,17 +4,18 @@ use csv::Reader;
 use serde::Deserialize;
 use sql::*;
 use std::env;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
 use std::io::{stdout, Write};
 use std::path::Path;
 
-use std::time::SystemTime;
+use std::time::Instant;
 use zip::read::ZipFile;
+use zip::result::ZipError;
 use zip::result::ZipError::FileNotFound;
 use zip::ZipArchive;
 
-const REQUIRED_FILES: [&'static str; 5] = [
+const REQUIRED_FILES: [&str; 5] = [
     "agency.txt",
     "stops.txt",
     "routes.txt",
@@ -22,9 +23,9 @@ const REQUIRED_FILES: [&'static str; 5] = [
     "stop_times.txt",
 ];
 
-const CONDITIONALLY_REQUIRED_FILES: [&'static str; 2] = ["calendar.txt", "calendar_dates.txt"];
+const CONDITIONALLY_REQUIRED_FILES: [&str; 2] = ["calendar.txt", "calendar_dates.txt"];
 
-const OPTIONAL_FILES: [&'static str; 17] = [
+const OPTIONAL_FILES: [&str; 17] = [
     "fare_attributes.txt",
     "fare_rules.txt",
     "timeframes.txt",
@@ -44,25 +45,53 @@ const OPTIONAL_FILES: [&'static str; 17] = [
     "attributions.txt",
 ];
 
-trait MapInto<T> {
-    fn map_into<U>(self) -> Option<U>
-    where
-        U: From<T>;
+#[derive(Debug)]
+enum Error {
+    /// The path to the GTFS zip file was not passed as argument
+    MissingArgument,
+    Io(std::io::Error),
+    Zip(ZipError),
+    /// A file the GTFS specification requires is not in the zip file
+    MissingFile { file_name: &'static str },
+    Csv(csv::Error),
+    Sql(rusqlite::Error),
+    /// Reading the entries of a file or inserting them failed
+    File {
+        file_name: &'static str,
+        error: Box<Error>,
+    },
 }
 
-impl<T> MapInto<T> for Option<T> {
-    fn map_into<U>(self) -> Option<U>
-    where
-        U: From<T>,
-    {
-        self.map(U::from)
+impl Display for Error {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::MissingArgument => {
+                write!(formatter, "Expected file path to GTFS zip file to be passed")
+            }
+            Error::Io(error) => write!(formatter, "Error accessing file: {error}"),
+            Error::Zip(error) => write!(formatter, "Error reading zip file: {error}"),
+            Error::MissingFile { file_name } => {
+                write!(formatter, "Required file {file_name} is missing")
+            }
+            Error::Csv(error) => write!(formatter, "Error reading CSV: {error}"),
+            Error::Sql(error) => write!(formatter, "Error inserting into database: {error}"),
+            Error::File { file_name, error } => write!(formatter, "{file_name}: {error}"),
+        }
     }
 }
 
-#[derive(Debug)]
-enum Error {
-    Csv(csv::Error),
-    Sql(rusqlite::Error),
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(value: ZipError) -> Self {
+        Error::Zip(value)
+    }
 }
 
 impl From<csv::Error> for Error {
@@ -99,15 +128,15 @@ fn insert_csv<T: for<'a> Deserialize<'a>, TInsert: Insert<T>>(
     insertee: &TInsert,
 ) -> Result<(), Error> {
     let mut count = 0f64;
-    let now = SystemTime::now();
+    let now = Instant::now();
 
     for result in reader.deserialize() {
         let item: T = result?;
         insertee.insert(item)?;
         count += 1.0;
-        let count_per_second = count / now.elapsed().unwrap().as_secs_f64();
+        let count_per_second = count / now.elapsed().as_secs_f64();
         print!("\rCompleted {count} {count_per_second:.2}\t entries/s");
-        stdout().flush().unwrap();
+        stdout().flush()?;
     }
 
     println!();
@@ -115,93 +144,106 @@ fn insert_csv<T: for<'a> Deserialize<'a>, TInsert: Insert<T>>(
     Ok(())
 }
 
+/// Adds the file name to errors of inserting the file, as they don't tell which file they are from
+fn in_file(file_name: &'static str) -> impl FnOnce(Error) -> Error {
+    move |error| Error::File {
+        file_name,
+        error: Box::new(error),
+    }
+}
+
 fn main() {
-    let connection = sql::create_database().unwrap();
+    if let Err(error) = import() {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
 
-    let file_path = env::args()
-        .nth(1)
-        .expect("Expected file path to GTFS zip file to be passed");
+fn import() -> Result<(), Error> {
+    let connection = sql::create_database()?;
+
+    let file_path = env::args().nth(1).ok_or(Error::MissingArgument)?;
 
     let file_path = Path::new(&*file_path);
-    let file = File::open(file_path).expect("Could not open file");
+    let file = File::open(file_path)?;
 
-    let mut archive = ZipArchive::new(file).expect("Could read file as zip");
+    let mut archive = ZipArchive::new(file)?;
 
     for file_name in REQUIRED_FILES {
         println!("Reading {file_name}");
-        let file = archive
-            .by_name(file_name)
-            .expect("Required file is missing");
+        let file = match archive.by_name(file_name) {
+            Err(FileNotFound) => return Err(Error::MissingFile { file_name }),
+            result => result?,
+        };
         let mut reader = Reader::from_reader(file);
 
-        match file_name {
-            "agency.txt" => insert_csv::<Agency, _>(&mut reader, &connection).unwrap(),
-            "stops.txt" => insert_csv::<Stop, _>(&mut reader, &connection).unwrap(),
-            "routes.txt" => insert_csv::<Route, _>(&mut reader, &connection).unwrap(),
-            "trips.txt" => insert_csv::<Trip, _>(&mut reader, &connection).unwrap(),
-            "stop_times.txt" => insert_csv::<StopTime, _>(&mut reader, &connection).unwrap(),
-            _ => (),
-        }
+        let result = match file_name {
+            "agency.txt" => insert_csv::<Agency, _>(&mut reader, &connection),
+            "stops.txt" => insert_csv::<Stop, _>(&mut reader, &connection),
+            "routes.txt" => insert_csv::<Route, _>(&mut reader, &connection),
+            "trips.txt" => insert_csv::<Trip, _>(&mut reader, &connection),
+            "stop_times.txt" => insert_csv::<StopTime, _>(&mut reader, &connection),
+            _ => Ok(()),
+        };
+        result.map_err(in_file(file_name))?;
     }
 
     for file_name in CONDITIONALLY_REQUIRED_FILES {
         println!("Reading {file_name}");
-        let result = archive
-            .by_name(file_name);
+        let file = match archive.by_name(file_name) {
+            Err(FileNotFound) => continue,
+            result => result?,
+        };
 
-        if let Err(FileNotFound) = result {
-            continue;
-        }
-
-        let file = result.expect("Unhandled error");
         let mut reader = Reader::from_reader(file);
-        match file_name {
-            "calendar.txt" => insert_csv::<Calendar, _>(&mut reader, &connection).unwrap(),
-            "calendar_dates.txt" => {
-                insert_csv::<CalendarDate, _>(&mut reader, &connection).unwrap()
-            }
-            _ => (),
-        }
+        let result = match file_name {
+            "calendar.txt" => insert_csv::<Calendar, _>(&mut reader, &connection),
+            "calendar_dates.txt" => insert_csv::<CalendarDate, _>(&mut reader, &connection),
+            _ => Ok(()),
+        };
+        result.map_err(in_file(file_name))?;
     }
 
     for file_name in OPTIONAL_FILES {
-        let result = archive
-            .by_name(file_name);
-
-        if let Err(FileNotFound) = result {
-
-            println!("Not provided {file_name}");
-            continue;
-        }
+        let file = match archive.by_name(file_name) {
+            Err(FileNotFound) => {
+                println!("Not provided {file_name}");
+                continue;
+            }
+            result => result?,
+        };
 
         println!("Reading {file_name}");
-        let file = result.expect("Unhandled error");
         let mut reader = Reader::from_reader(file);
-        match file_name {
-            "fare_attributes.txt" => {
-                insert_csv::<FareAttribute, _>(&mut reader, &connection).unwrap()
-            }
-            "fare_rules.txt" => insert_csv::<FareRule, _>(&mut reader, &connection).unwrap(),
-            "timeframes.txt" => insert_csv::<Timeframe, _>(&mut reader, &connection).unwrap(),
-            "fare_media.txt" => insert_csv::<FareMedia, _>(&mut reader, &connection).unwrap(),
-            "fare_products.txt" => insert_csv::<FareProduct, _>(&mut reader, &connection).unwrap(),
-            "fare_leg_rules.txt" => insert_csv::<FareLegRule, _>(&mut reader, &connection).unwrap(),
+        let result = match file_name {
+            "fare_attributes.txt" => insert_csv::<FareAttribute, _>(&mut reader, &connection),
+            "fare_rules.txt" => insert_csv::<FareRule, _>(&mut reader, &connection),
+            "timeframes.txt" => insert_csv::<Timeframe, _>(&mut reader, &connection),
+            "fare_media.txt" => insert_csv::<FareMedia, _>(&mut reader, &connection),
+            "fare_products.txt" => insert_csv::<FareProduct, _>(&mut reader, &connection),
+            "fare_leg_rules.txt" => insert_csv::<FareLegRule, _>(&mut reader, &connection),
             "fare_transfer_rules.txt" => {
-                insert_csv::<FareTransferRule, _>(&mut reader, &connection).unwrap()
+                insert_csv::<FareTransferRule, _>(&mut reader, &connection)
             }
-            "areas.txt" => insert_csv::<Area, _>(&mut reader, &connection).unwrap(),
-            "stop_ares.txt" => insert_csv::<StopArea, _>(&mut reader, &connection).unwrap(),
-            "shapes.txt" => insert_csv::<Shape, _>(&mut reader, &connection).unwrap(),
-            "frequencies.txt" => insert_csv::<Frequency, _>(&mut reader, &connection).unwrap(),
-            "transfers.txt" => insert_csv::<Transfer, _>(&mut reader, &connection).unwrap(),
-            "pathways.txt" => insert_csv::<Pathway, _>(&mut reader, &connection).unwrap(),
-            "levels.txt" => insert_csv::<Level, _>(&mut reader, &connection).unwrap(),
-            "translations.txt" => insert_csv::<Translation, _>(&mut reader, &connection).unwrap(),
-            "feed_info.txt" => insert_csv::<FeedInfo, _>(&mut reader, &connection).unwrap(),
-            "attributions.txt" => insert_csv::<Attribution, _>(&mut reader, &connection).unwrap(),
+            "areas.txt" => insert_csv::<Area, _>(&mut reader, &connection),
+            "stop_ares.txt" => insert_csv::<StopArea, _>(&mut reader, &connection),
+            "shapes.txt" => insert_csv::<Shape, _>(&mut reader, &connection),
+            "frequencies.txt" => insert_csv::<Frequency, _>(&mut reader, &connection),
+            "transfers.txt" => insert_csv::<Transfer, _>(&mut reader, &connection),
+            "pathways.txt" => insert_csv::<Pathway, _>(&mut reader, &connection),
+            "levels.txt" => insert_csv::<Level, _>(&mut reader, &connection),
+            "translations.txt" => insert_csv::<Translation, _>(&mut reader, &connection),
+            "feed_info.txt" => insert_csv::<FeedInfo, _>(&mut reader, &connection),
+            "attributions.txt" => insert_csv::<Attribution, _>(&mut reader, &connection),
 
             // _ => (),
-            other => println!("Not read: {other}")
-        }
+            other => {
+                println!("Not read: {other}");
+                Ok(())
+            }
+        };
+        result.map_err(in_file(file_name))?;
     }
+
+    Ok(())
 }
@@ -210,7 +210,9 @@ CREATE TABLE IF NOT EXISTS shapes (
 CREATE TABLE IF NOT EXISTS frequencies (
     trip_id TEXT NOT NULL,
     start_time TEXT NOT NULL,
+    start_time_seconds INTEGER NOT NULL,
     end_time TEXT NOT NULL,
+    end_time_seconds INTEGER NOT NULL,
     headway_seconds INTEGER NOT NULL,
     exact_times INTEGER,
     PRIMARY KEY (trip_id, start_time),
@@ -225,7 +227,7 @@ CREATE TABLE IF NOT EXISTS transfers (
     from_trip_id TEXT,
     to_trip_id TEXT,
     type INTEGER NOT NULL,
-    minimum_transfer_time INTEGER NOT NULL,
+    minimum_transfer_time INTEGER,
     FOREIGN KEY (from_stop_id) REFERENCES stops(id),
     FOREIGN KEY (to_stop_id) REFERENCES stops(id),
     FOREIGN KEY (from_route_id) REFERENCES routes(id),
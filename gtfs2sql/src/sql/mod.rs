@@ -308,7 +308,9 @@ impl Insert<Frequency> for Connection {
         statement.execute(named_params! {
             ":trip_id": frequency.trip_id,
             ":start_time": frequency.start_time,
+            ":start_time_seconds": frequency.start_time.total_seconds(),
             ":end_time": frequency.end_time,
+            ":end_time_seconds": frequency.end_time.total_seconds(),
             ":headway_seconds": frequency.headway_seconds,
             ":exact_times": frequency.exact_times,
         })?;
@@ -189,7 +189,9 @@ pub(super) const INSERT_FREQUENCY_QUERY: &str =
     VALUES (
         :trip_id,
         :start_time,
+        :start_time_seconds,
         :end_time,
+        :end_time_seconds,
         :headway_seconds,
         :exact_times);";
 
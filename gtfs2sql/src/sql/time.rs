@@ -4,7 +4,6 @@ use serde::{de, Deserialize, Deserializer};
 use std::fmt::{Display, Formatter};
 use std::num::ParseIntError;
 use std::str::FromStr;
-use std::u64;
 
 /// Represents GTFS Time stored in seconds for simplicity.
 /// (Not the HH:MM:SS format)
@@ -20,10 +19,6 @@ use std::u64;
 pub(crate) struct Time(u64);
 
 impl Time {
-    pub(super) fn new(value: u64) -> Time {
-        Time(value)
-    }
-
     pub(super) fn total_seconds(self) -> u64 {
         self.0
     }
@@ -39,7 +34,7 @@ impl Eq for Time {}
 
 /// Represents a section in GTFS time stamp in the format of HH:MM:SS
 #[derive(Debug)]
-enum TimestampPart {
+pub(crate) enum TimestampPart {
     Hours,
     Minutes,
     Seconds,
@@ -47,12 +42,12 @@ enum TimestampPart {
 
 /// Describes which value of the timestamp was out of range. Hours can not be out of range
 #[derive(Debug)]
-enum TimeFraction {
+pub(crate) enum TimeFraction {
     Minutes,
     Seconds,
 }
 #[derive(Debug)]
-pub enum ParseTimeError {
+pub(crate) enum ParseTimeError {
     /// The provided string was not 7 or 8 in length.
     /// The value represents the provided invalid string length.
     InvalidLength(usize),
@@ -71,6 +66,26 @@ pub enum ParseTimeError {
     /// next day
     ValueOutOfRange(TimeFraction, u64),
 }
+
+impl Display for ParseTimeError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseTimeError::InvalidLength(length) => {
+                write!(formatter, "expected 7 or 8 characters but got {length}")
+            }
+            ParseTimeError::InvalidPartLength(fraction, length) => {
+                write!(formatter, "expected 2 digits for {fraction:?} but got {length}")
+            }
+            ParseTimeError::InvalidFormat(part) => write!(formatter, "missing {part:?}"),
+            ParseTimeError::ParseIntError(part, error) => {
+                write!(formatter, "could not parse {part:?}: {error}")
+            }
+            ParseTimeError::ValueOutOfRange(fraction, value) => {
+                write!(formatter, "{fraction:?} out of range: {value}")
+            }
+        }
+    }
+}
 impl FromStr for Time {
     type Err = ParseTimeError;
 
@@ -132,6 +147,45 @@ impl FromStr for Time {
         }
     }
 }
+
+impl Display for Time {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        // Idk how to do basic math
+        let (seconds, minutes) = (self.0 % 60, self.0 / 60);
+        let (seconds, minutes, hours) = (seconds, minutes % 60, minutes / 60);
+        write!(formatter, "{hours}:{minutes}:{seconds}")
+    }
+}
+
+// Deserialization specific code for serde
+struct TimeVisitor;
+
+impl<'de> Visitor<'de> for TimeVisitor {
+    type Value = Time;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str(
+            "a string in the format HH:MM:SS or H:MM:SS. Can be a value greater than 24:00:00",
+        )
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        Time::from_str(value)
+            .map_err(|error| de::Error::custom(format!("Invalid time value \"{value}\": {error}")))
+    }
+}
+
+impl<'de> Deserialize<'de> for Time {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(TimeVisitor)
+    }
+}
 #[cfg(test)]
 mod tests {
     use crate::sql::time::{ParseTimeError, Time};
@@ -155,7 +209,7 @@ mod tests {
         let a = "12:34:56";
         let expected_a = Time((12 * 60 * 60) + (34 * 60) + 56);
         let b = "69:42:00";
-        let expected_b = Time((69 * 60 * 60) + (42 * 60) + 00);
+        let expected_b = Time((69 * 60 * 60) + (42 * 60));
 
         // Act
         let actual_a = Time::from_str(a).unwrap();
@@ -192,42 +246,3 @@ mod tests {
         assert!(results.iter().all(Result::is_err));
     }
 }
-
-impl Display for Time {
-    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
-        // Idk how to do basic math
-        let (seconds, minutes) = (self.0 % 60, self.0 / 60);
-        let (seconds, minutes, hours) = (seconds, minutes % 60, minutes / 60);
-        write!(formatter, "{hours}:{minutes}:{seconds}")
-    }
-}
-
-// Deserialization specific code for serde
-struct TimeVisitor;
-
-impl<'de> Visitor<'de> for TimeVisitor {
-    type Value = Time;
-
-    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-        formatter.write_str(
-            "a string in the format HH:MM:SS or H:MM:SS. Can be a value greater than 24:00:00",
-        )
-    }
-
-    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-    where
-        E: Error,
-    {
-        //TODO improve error message
-        Time::from_str(value).map_err(|_| de::Error::custom("Invalid time value"))
-    }
-}
-
-impl<'de> Deserialize<'de> for Time {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        deserializer.deserialize_str(TimeVisitor)
-    }
-}
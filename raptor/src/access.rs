@@ -0,0 +1,302 @@
+use crate::journey::{reconstruct_journey_with, Journey, Leg};
+use crate::shared::TimetableView;
+use crate::{Connection, QueryOptions, Search, Time};
+
+/// RAPTOR query from an origin to a destination that are not stops, like coordinates.
+/// The origin reaches the access stops and the destination is reached from the egress stops by
+/// walking. Both are given as pairs of stop and walking time.
+///
+/// Returns one journey for each round that improved the arrival at the destination, starting with
+/// the walk to the first stop and ending with the walk to the destination unless they take no time.
+/// Journeys without any trip are not included.
+pub fn access_egress_raptor(
+    access: &[(usize, Time)],
+    egress: &[(usize, Time)],
+    departure: &Time,
+    timetable: &impl TimetableView,
+    options: &QueryOptions,
+) -> Vec<Journey> {
+    let mut search = Search {
+        options: *options,
+        ..Search::default()
+    };
+    search.run(access, *departure, egress, timetable);
+
+    collect_journeys(
+        access,
+        egress,
+        departure,
+        search.connections_by_round.len(),
+        |round, stop| search.labels_by_round[round].get(&stop).copied(),
+        |round, stop| search.connections_by_round[round - 1].get(&stop).copied(),
+        timetable,
+    )
+}
+
+/// Reconstructs the journeys of an access and egress query from the arrivals and connections by
+/// round and stop, which are none for stops not reached by a connection in the round
+pub(crate) fn collect_journeys(
+    access: &[(usize, Time)],
+    egress: &[(usize, Time)],
+    departure: &Time,
+    rounds: usize,
+    get_arrival: impl Fn(usize, usize) -> Option<Time>,
+    get_connection: impl Fn(usize, usize) -> Option<Connection>,
+    timetable: &impl TimetableView,
+) -> Vec<Journey> {
+    let mut journeys: Vec<Journey> = Vec::new();
+    for round in 1..=rounds {
+        // The egress stop reached in this round with the earliest arrival at the destination
+        let best_egress = egress
+            .iter()
+            .filter(|(stop, _)| get_connection(round, *stop).is_some())
+            .filter_map(|(stop, walking_time)| {
+                let arrival = get_arrival(round, *stop)?;
+                Some((*stop, arrival, arrival + *walking_time))
+            })
+            .min_by_key(|(_, _, destination_arrival)| *destination_arrival);
+
+        let Some((egress_stop, egress_departure, destination_arrival)) = best_egress else {
+            continue;
+        };
+
+        let is_improvement = journeys
+            .last()
+            .is_none_or(|previous| destination_arrival < previous.arrival());
+        if !is_improvement {
+            continue;
+        }
+
+        let Some(mut journey) =
+            reconstruct_journey_with(egress_stop, round, &get_connection, timetable)
+        else {
+            continue;
+        };
+
+        let Some(Leg::Transit {
+            boarded_at_stop, ..
+        }) = journey.legs.first()
+        else {
+            continue;
+        };
+
+        let Some((access_stop, access_time)) = access
+            .iter()
+            .filter(|(stop, _)| stop == boarded_at_stop)
+            .min_by_key(|(_, walking_time)| *walking_time)
+        else {
+            continue;
+        };
+
+        // Starting or ending at a stop needs no walk
+        if *access_time != Time::from(0) {
+            journey.legs.insert(
+                0,
+                Leg::Access {
+                    to_stop: *access_stop,
+                    departure: *departure,
+                    arrival: *departure + *access_time,
+                },
+            );
+        }
+        if destination_arrival != egress_departure {
+            journey.legs.push(Leg::Egress {
+                from_stop: egress_stop,
+                departure: egress_departure,
+                arrival: destination_arrival,
+            });
+        }
+
+        journeys.push(journey);
+    }
+
+    journeys
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::access::access_egress_raptor;
+    use crate::journey::Leg;
+    use crate::shared::Timetable;
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{QueryOptions, Time};
+
+    #[test]
+    fn walks_to_best_access_and_from_best_egress_stop() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            4,
+            vec![
+                TestRoute {
+                    stops: vec![0, 2],
+                    trips: vec![vec![100, 500]],
+                },
+                TestRoute {
+                    stops: vec![1, 3],
+                    trips: vec![vec![200, 300]],
+                },
+            ],
+            Vec::new(),
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = access_egress_raptor(
+            &[(0, Time::from(60)), (1, Time::from(180))],
+            &[(2, Time::from(30)), (3, Time::from(120))],
+            &Time::from(0),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(1, journeys.len());
+        let legs = &journeys[0].legs;
+        assert_eq!(
+            Leg::Access {
+                to_stop: 1,
+                departure: Time::from(0),
+                arrival: Time::from(180),
+            },
+            legs[0]
+        );
+        assert_eq!(
+            Leg::Egress {
+                from_stop: 3,
+                departure: Time::from(300),
+                arrival: Time::from(420),
+            },
+            legs[2]
+        );
+        assert_eq!(Time::from(420), journeys[0].arrival());
+    }
+
+    #[test]
+    fn misses_trip_departing_before_access_walk_ends() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            2,
+            vec![TestRoute {
+                stops: vec![0, 1],
+                trips: vec![vec![100, 200], vec![300, 400]],
+            }],
+            Vec::new(),
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = access_egress_raptor(
+            &[(0, Time::from(150))],
+            &[(1, Time::from(0))],
+            &Time::from(0),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(Time::from(400), journeys[0].arrival());
+    }
+
+    #[test]
+    fn avoids_inaccessible_trips_and_foot_paths_for_wheelchair_users() {
+        // Arrange
+        let (mut routes_data, mut stops_data) = build_network(
+            4,
+            vec![
+                TestRoute {
+                    stops: vec![0, 3],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 150]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![300, 350]],
+                },
+            ],
+            vec![(1, 2, 60), (2, 3, 50)],
+        );
+        // The direct trip can't be boarded and the walk between the trips has stairs
+        routes_data.stop_times[0].wheelchair_accessible = false;
+        stops_data.transfers[0].wheelchair_accessible = false;
+        let options = QueryOptions {
+            wheelchair_accessible: true,
+        };
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = access_egress_raptor(
+            &[(0, Time::from(0))],
+            &[(3, Time::from(0))],
+            &Time::from(0),
+            &timetable,
+            &options,
+        );
+        let without_options = access_egress_raptor(
+            &[(0, Time::from(0))],
+            &[(3, Time::from(0))],
+            &Time::from(0),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(1, journeys.len());
+        assert_eq!(Time::from(400), journeys[0].arrival());
+        assert_eq!(1, journeys[0].transfers());
+        assert_eq!(Time::from(200), without_options[0].arrival());
+    }
+
+    #[test]
+    fn only_wheelchair_users_take_foot_paths_meant_for_them() {
+        // Arrange
+        let (routes_data, mut stops_data) = build_network(
+            3,
+            vec![TestRoute {
+                stops: vec![0, 1],
+                trips: vec![vec![100, 200]],
+            }],
+            vec![(1, 2, 60)],
+        );
+        // Like the elevators of a station, which everyone else skips by taking the stairs
+        stops_data.transfers[0].wheelchair_only = true;
+        let options = QueryOptions {
+            wheelchair_accessible: true,
+        };
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = access_egress_raptor(
+            &[(0, Time::from(0))],
+            &[(2, Time::from(0))],
+            &Time::from(0),
+            &timetable,
+            &options,
+        );
+        let without_options = access_egress_raptor(
+            &[(0, Time::from(0))],
+            &[(2, Time::from(0))],
+            &Time::from(0),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(Time::from(260), journeys[0].arrival());
+        assert!(without_options.is_empty());
+    }
+}
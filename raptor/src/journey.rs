@@ -0,0 +1,480 @@
+use crate::shared::{TimetableLookup, TimetableView};
+use crate::{Connection, Time};
+use std::collections::HashMap;
+
+/// A part of a journey that is either spent on a single trip or walking between two stops
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Leg {
+    /// Riding a trip from the stop it was boarded at to the stop it was exited at
+    Transit {
+        /// Index of the route in the route data
+        route: usize,
+        /// Number of the trip within the route (index in the sequence of trips of the route)
+        trip_number: usize,
+        boarded_at_stop: usize,
+        exited_at_stop: usize,
+        /// Departure of the trip at the stop it was boarded at
+        departure: Time,
+        /// Arrival of the trip at the stop it was exited at
+        arrival: Time,
+    },
+    /// Staying seated while the trip of the previous leg continues as the trip of another route
+    /// with the same vehicle. Not a transfer, as riders don't change vehicles
+    Continuation {
+        /// Index of the route in the route data
+        route: usize,
+        /// Number of the trip within the route (index in the sequence of trips of the route)
+        trip_number: usize,
+        /// The first stop of the route where the previous trip ended
+        from_stop: usize,
+        exited_at_stop: usize,
+        /// Departure of the trip at its first stop
+        departure: Time,
+        /// Arrival of the trip at the stop it was exited at
+        arrival: Time,
+    },
+    /// Walking from one stop to another through a foot-path
+    FootPath {
+        from_stop: usize,
+        to_stop: usize,
+        /// When the walk starts which is the arrival of the previous leg
+        departure: Time,
+        arrival: Time,
+    },
+    /// Walking from the origin of the query to the first stop
+    Access {
+        to_stop: usize,
+        departure: Time,
+        arrival: Time,
+    },
+    /// Walking from the last stop to the destination of the query
+    Egress {
+        from_stop: usize,
+        departure: Time,
+        arrival: Time,
+    },
+}
+
+impl Leg {
+    pub fn departure(&self) -> Time {
+        match self {
+            Leg::Transit { departure, .. }
+            | Leg::Continuation { departure, .. }
+            | Leg::FootPath { departure, .. }
+            | Leg::Access { departure, .. }
+            | Leg::Egress { departure, .. } => *departure,
+        }
+    }
+
+    pub fn arrival(&self) -> Time {
+        match self {
+            Leg::Transit { arrival, .. }
+            | Leg::Continuation { arrival, .. }
+            | Leg::FootPath { arrival, .. }
+            | Leg::Access { arrival, .. }
+            | Leg::Egress { arrival, .. } => *arrival,
+        }
+    }
+}
+
+/// A complete journey from a source stop to a target stop with its legs in travel order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Journey {
+    pub legs: Vec<Leg>,
+}
+
+impl Journey {
+    /// The departure of the first leg or infinite if the journey has no legs
+    pub fn departure(&self) -> Time {
+        self.legs.first().map_or(Time::INFINITE, Leg::departure)
+    }
+
+    /// The arrival of the last leg or infinite if the journey has no legs
+    pub fn arrival(&self) -> Time {
+        self.legs.last().map_or(Time::INFINITE, Leg::arrival)
+    }
+
+    /// The number of times the passenger has to change between trips
+    pub fn transfers(&self) -> usize {
+        let trips = self
+            .legs
+            .iter()
+            .filter(|leg| matches!(leg, Leg::Transit { .. }))
+            .count();
+        trips.saturating_sub(1)
+    }
+}
+
+/// Reconstructs the journeys to the target stop from the connections returned by [crate::raptor].
+/// Returns one journey for each round that improved the arrival at the target, so the first journey
+/// is the one with the least transfers and every following journey is faster but has more
+/// transfers.
+pub fn reconstruct_journeys(
+    target: usize,
+    connections_by_round: &[HashMap<usize, Connection>],
+    timetable: &impl TimetableView,
+) -> Vec<Journey> {
+    let mut journeys: Vec<Journey> = Vec::new();
+
+    for round in 1..=connections_by_round.len() {
+        if !connections_by_round[round - 1].contains_key(&target) {
+            continue;
+        }
+
+        let Some(journey) = reconstruct_journey(target, round, connections_by_round, timetable)
+        else {
+            continue;
+        };
+
+        // Foot-paths are relaxed against the current round only, so a round can contain the target
+        // without actually arriving earlier than a journey with fewer transfers
+        let is_improvement = journeys
+            .last()
+            .is_none_or(|previous| journey.arrival() < previous.arrival());
+
+        if is_improvement {
+            journeys.push(journey);
+        }
+    }
+
+    journeys
+}
+
+/// Walks back from the target through the connections starting at the given round (amount of trips)
+pub(crate) fn reconstruct_journey(
+    target: usize,
+    round: usize,
+    connections_by_round: &[HashMap<usize, Connection>],
+    timetable: &impl TimetableView,
+) -> Option<Journey> {
+    reconstruct_journey_with(
+        target,
+        round,
+        |round, stop| connections_by_round[round - 1].get(&stop).copied(),
+        timetable,
+    )
+}
+
+/// Like [reconstruct_journey] for connections that are not stored in maps. Gets the connection
+/// reaching a stop by the round and the stop
+pub(crate) fn reconstruct_journey_with(
+    target: usize,
+    mut round: usize,
+    get_connection: impl Fn(usize, usize) -> Option<Connection>,
+    timetable: &impl TimetableView,
+) -> Option<Journey> {
+    // Legs are collected from the target back to the source
+    let mut legs = Vec::new();
+    let mut stop = target;
+
+    while round > 0 {
+        let connection = get_connection(round, stop)?;
+        match &connection {
+            Connection::Connection {
+                route,
+                trip_number,
+                boarded_at_stop,
+                exited_at_stop,
+            } => {
+                legs.push(get_transit_leg(
+                    *route,
+                    *trip_number,
+                    *boarded_at_stop,
+                    *exited_at_stop,
+                    timetable,
+                )?);
+
+                stop = *boarded_at_stop;
+                // The boarded stop was reached in the previous round
+                round -= 1;
+            }
+            Connection::Continuation {
+                boarded_route,
+                boarded_trip_number,
+                boarded_at_stop,
+                route,
+                trip_number,
+                exited_at_stop,
+            } => {
+                // Legs are collected backwards, so the trips the boarded trip continued as are
+                // pushed in reverse
+                let mut continued_legs = Vec::new();
+                let (mut from_route, mut from_trip_number) = (*boarded_route, *boarded_trip_number);
+                let from_last_stop = get_last_stop(from_route, timetable)?;
+                continued_legs.push(get_transit_leg(
+                    from_route,
+                    from_trip_number,
+                    *boarded_at_stop,
+                    from_last_stop,
+                    timetable,
+                )?);
+
+                while (from_route, from_trip_number) != (*route, *trip_number) {
+                    // The search only follows each continuation once
+                    if continued_legs.len() > timetable.continuation_count() {
+                        return None;
+                    }
+
+                    let continuation = timetable.get_continuation(from_route, from_trip_number)?;
+                    (from_route, from_trip_number) =
+                        (continuation.to_route, continuation.to_trip_number);
+                    let from_route_value = timetable.route(from_route);
+                    let is_exited = (from_route, from_trip_number) == (*route, *trip_number);
+                    let exited_at_stop = if is_exited {
+                        *exited_at_stop
+                    } else {
+                        get_last_stop(from_route, timetable)?
+                    };
+                    let Leg::Transit {
+                        boarded_at_stop: from_stop,
+                        departure,
+                        arrival,
+                        ..
+                    } = get_transit_leg(
+                        from_route,
+                        from_trip_number,
+                        timetable.get_route_stop(&from_route_value, 0),
+                        exited_at_stop,
+                        timetable,
+                    )?
+                    else {
+                        return None;
+                    };
+                    continued_legs.push(Leg::Continuation {
+                        route: from_route,
+                        trip_number: from_trip_number,
+                        from_stop,
+                        exited_at_stop,
+                        departure,
+                        arrival,
+                    });
+                }
+
+                continued_legs.reverse();
+                legs.append(&mut continued_legs);
+
+                stop = *boarded_at_stop;
+                // Staying seated does not take another round
+                round -= 1;
+            }
+            Connection::FootPath { source, transfer } => {
+                let transfer = timetable.get_transfer(*source, *transfer);
+                // Times are filled in when the legs are in travel order
+                legs.push(Leg::FootPath {
+                    from_stop: *source,
+                    to_stop: stop,
+                    departure: Time::INFINITE,
+                    arrival: transfer.time,
+                });
+
+                // Foot-paths are relaxed in the same round as the trip that reached the source
+                stop = *source;
+            }
+        }
+    }
+
+    legs.reverse();
+
+    // Foot-paths start when the previous leg arrives
+    let mut previous_arrival = Time::INFINITE;
+    for leg in legs.iter_mut() {
+        if let Leg::FootPath {
+            departure, arrival, ..
+        } = leg
+        {
+            // Arrival holds the walking time until here
+            let walking_time = *arrival;
+            *departure = previous_arrival;
+            *arrival = previous_arrival + walking_time;
+        }
+        previous_arrival = leg.arrival();
+    }
+
+    Some(Journey { legs })
+}
+
+/// The leg riding the trip of the route from the stop it was boarded at to the stop it was exited
+/// at. Returns none if the route doesn't serve the stops in that order
+pub(crate) fn get_transit_leg(
+    route: usize,
+    trip_number: usize,
+    boarded_at_stop: usize,
+    exited_at_stop: usize,
+    timetable: &impl TimetableView,
+) -> Option<Leg> {
+    let route_value = timetable.route(route);
+    let boarded_sequence = timetable.get_stop_sequence(&route_value, &boarded_at_stop)?;
+    // Routes can visit a stop more than once, so look for the exit after the boarding
+    let exited_sequence = (boarded_sequence..route_value.number_of_stops)
+        .find(|&sequence| timetable.get_route_stop(&route_value, sequence) == exited_at_stop)?;
+
+    let trip = timetable.get_trip(&route_value, trip_number);
+    Some(Leg::Transit {
+        route,
+        trip_number,
+        boarded_at_stop,
+        exited_at_stop,
+        departure: trip.get(boarded_sequence).departure_time,
+        arrival: trip.get(exited_sequence).arrival_time,
+    })
+}
+
+/// The stop where the route ends
+fn get_last_stop(route: usize, timetable: &impl TimetableView) -> Option<usize> {
+    let route = timetable.route(route);
+    let last_sequence = route.number_of_stops.checked_sub(1)?;
+    Some(timetable.get_route_stop(&route, last_sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journey::{reconstruct_journeys, Leg};
+    use crate::shared::{Timetable, TripContinuation};
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{raptor, Time};
+
+    #[test]
+    fn one_journey_per_improving_round() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            4,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1, 2],
+                    trips: vec![vec![100, 200, 300]],
+                },
+                TestRoute {
+                    stops: vec![1, 3],
+                    trips: vec![vec![250, 400]],
+                },
+                TestRoute {
+                    stops: vec![0, 3],
+                    trips: vec![vec![150, 600]],
+                },
+            ],
+            Vec::new(),
+        );
+
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let rounds = raptor(0, 3, &Time::from(50), &timetable);
+        let journeys = reconstruct_journeys(3, &rounds, &timetable);
+
+        // Assert
+        assert_eq!(2, journeys.len());
+        assert_eq!(
+            vec![Leg::Transit {
+                route: 2,
+                trip_number: 0,
+                boarded_at_stop: 0,
+                exited_at_stop: 3,
+                departure: Time::from(150),
+                arrival: Time::from(600),
+            }],
+            journeys[0].legs
+        );
+        assert_eq!(0, journeys[0].transfers());
+        assert_eq!(1, journeys[1].transfers());
+        assert_eq!(Time::from(100), journeys[1].departure());
+        assert_eq!(Time::from(400), journeys[1].arrival());
+    }
+
+    #[test]
+    fn journey_ends_with_foot_path() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            3,
+            vec![TestRoute {
+                stops: vec![0, 1],
+                trips: vec![vec![100, 200]],
+            }],
+            vec![(1, 2, 60)],
+        );
+
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let rounds = raptor(0, 2, &Time::from(0), &timetable);
+        let journeys = reconstruct_journeys(2, &rounds, &timetable);
+
+        // Assert
+        assert_eq!(1, journeys.len());
+        assert_eq!(
+            Leg::FootPath {
+                from_stop: 1,
+                to_stop: 2,
+                departure: Time::from(200),
+                arrival: Time::from(260),
+            },
+            journeys[0].legs[1]
+        );
+    }
+
+    #[test]
+    fn stays_seated_on_continuing_trip() {
+        // Arrange
+        let (mut routes_data, mut stops_data) = build_network(
+            3,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![200, 300], vec![400, 500]],
+                },
+            ],
+            Vec::new(),
+        );
+        // Changing vehicles would miss the first trip of the second route
+        stops_data.default_change_time = Time::from(60);
+        routes_data.continuations = vec![TripContinuation {
+            from_route: 0,
+            from_trip_number: 0,
+            to_route: 1,
+            to_trip_number: 0,
+        }];
+
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let rounds = raptor(0, 2, &Time::from(0), &timetable);
+        let journeys = reconstruct_journeys(2, &rounds, &timetable);
+
+        // Assert
+        assert_eq!(1, journeys.len());
+        assert_eq!(
+            vec![
+                Leg::Transit {
+                    route: 0,
+                    trip_number: 0,
+                    boarded_at_stop: 0,
+                    exited_at_stop: 1,
+                    departure: Time::from(100),
+                    arrival: Time::from(200),
+                },
+                Leg::Continuation {
+                    route: 1,
+                    trip_number: 0,
+                    from_stop: 1,
+                    exited_at_stop: 2,
+                    departure: Time::from(200),
+                    arrival: Time::from(300),
+                },
+            ],
+            journeys[0].legs
+        );
+        assert_eq!(0, journeys[0].transfers());
+    }
+}
@@ -1,74 +1,87 @@
+pub mod access;
+pub mod journey;
+pub mod matrix;
+pub mod mc;
+pub mod one_to_all;
+pub mod range;
+pub mod reverse;
+mod scan;
 pub mod shared;
-
-use crate::Time::{Finite, Infinite};
-use std::cmp::{min, Ordering};
+#[cfg(test)]
+mod test_network;
+pub mod workspace;
+
+use rkyv::{Archive, Deserialize, Serialize};
+use scan::{scan_route, RoundLabels};
+#[cfg(test)]
+use shared::Timetable;
+use shared::{TimetableLookup, TimetableView, TripTimes};
+use std::cmp::min;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
-use std::hash::{Hash, Hasher};
 use std::ops::Add;
-use shared::{RoutesData, StopTime, StopsData};
 
 /// Represents a time stamp for various structures in RAPTOR.
 /// The value represents a time after midnight for a day. It can be greater than 24h if a stop on a
-/// trip is reached the next day after midnight
-#[derive(Copy, Clone, Debug)]
-pub enum Time {
-    Finite(u64),
-    Infinite,
-}
+/// trip is reached the next day after midnight.
+/// Stored as seconds in 32 bits with the largest value as infinity to keep stop times small for
+/// large feeds and compare them without branching
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Archive, Serialize, Deserialize,
+)]
+pub struct Time(u32);
 
-impl Eq for Time {}
+impl Add for Time {
+    type Output = Time;
 
-impl Ord for Time {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (Infinite, Infinite) => Ordering::Equal,
-            (Infinite, Finite(_)) => Ordering::Greater,
-            (Finite(_), Infinite) => Ordering::Less,
-            (Finite(value), Finite(other)) => value.cmp(other),
-        }
+    /// Adding anything to infinity stays infinite and sums too large for 32 bits become infinite
+    fn add(self, other: Self) -> Self::Output {
+        Time(self.0.saturating_add(other.0))
     }
 }
 
-impl PartialOrd for Time {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
+impl Time {
+    /// Later than any other time like the arrival at stops that can not be reached
+    pub const INFINITE: Time = Time(u32::MAX);
 
-impl PartialEq for Time {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Infinite, Infinite) => true,
-            (Infinite, Finite(_)) | (Finite(_), Infinite) => false,
-            (Finite(value), Finite(other)) => value.eq(other),
-        }
+    /// The seconds after midnight or none if the time is infinite
+    pub fn seconds(self) -> Option<u64> {
+        (self != Time::INFINITE).then_some(u64::from(self.0))
     }
-}
 
-impl Add for Time {
-    type Output = Time;
-
-    fn add(self, other: Self) -> Self::Output {
-        match (self, other) {
-            (Infinite, _) => Infinite,
-            (_, Infinite) => Infinite,
-            (Finite(self_value), Finite(other_value)) => Finite(self_value + other_value),
+    /// Subtracts a duration from the time. Returns none if the result would be before midnight or
+    /// the duration is infinite
+    pub fn checked_sub(self, duration: Time) -> Option<Time> {
+        if duration == Time::INFINITE {
+            return None;
+        }
+        if self == Time::INFINITE {
+            return Some(Time::INFINITE);
         }
+
+        self.0.checked_sub(duration.0).map(Time)
     }
 }
 
 impl From<u64> for Time {
+    /// Seconds that don't fit into 32 bits are clamped to the latest finite time
     fn from(value: u64) -> Self {
-        Finite(value)
+        let latest_finite = u64::from(u32::MAX - 1);
+        Time(value.min(latest_finite) as u32)
+    }
+}
+
+impl From<&ArchivedTime> for Time {
+    fn from(time: &ArchivedTime) -> Self {
+        Time(time.0.to_native())
     }
 }
 
 impl Display for Time {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Infinite => write!(formatter, "Infinite"),
-            Finite(seconds) => {
+        match self.seconds() {
+            None => write!(formatter, "Infinite"),
+            Some(seconds) => {
                 // Idk how to do basic math
                 let (seconds, minutes) = (seconds % 60, seconds / 60);
                 let (seconds, minutes, hours) = (seconds, minutes % 60, minutes / 60);
@@ -89,8 +102,8 @@ fn format_time() {
     let hours_in_seconds = hours * 60 * 60;
     let minutes_in_seconds = minutes * 60;
 
-    let time = Time::Finite(hours_in_seconds + minutes_in_seconds + seconds);
-    let expected = format!("Time {hours}:{minutes}:{seconds}");
+    let time = Time::from(hours_in_seconds + minutes_in_seconds + seconds);
+    let expected = format!("{hours}:{minutes}:{seconds}");
 
     // Act
     let actual = format!("{}", time);
@@ -98,8 +111,62 @@ fn format_time() {
     assert_eq!(expected, actual);
 }
 
+#[test]
+fn boards_trips_departing_at_arrival_time() {
+    // Arrange
+    let (routes_data, stops_data) = test_network::build_network(
+        3,
+        vec![
+            test_network::TestRoute {
+                stops: vec![0, 1],
+                trips: vec![vec![100, 200]],
+            },
+            test_network::TestRoute {
+                stops: vec![1, 2],
+                trips: vec![vec![200, 300]],
+            },
+        ],
+        Vec::new(),
+    );
+
+    let timetable = Timetable {
+        routes_data,
+        stops_data,
+    };
+
+    // Act
+    let rounds = raptor(0, 2, &Time::from(100), &timetable);
+
+    // Assert
+    assert!(matches!(
+        rounds[1].get(&2),
+        Some(Connection::Connection {
+            route: 1,
+            boarded_at_stop: 1,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn infinite_time_is_later_than_any_sum() {
+    // Arrange
+    let latest = Time::from(u64::MAX);
+
+    // Act
+    let overflowing = latest + Time::from(1);
+    let from_infinite = Time::INFINITE + Time::from(1);
+
+    // Assert
+    assert!(latest < Time::INFINITE);
+    assert_eq!(Time::INFINITE, overflowing);
+    assert_eq!(Time::INFINITE, from_infinite);
+    assert_eq!(None, Time::INFINITE.seconds());
+    assert_eq!("Infinite", Time::INFINITE.to_string());
+}
+
 /// A connection between two stops
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 pub enum Connection {
     /// By using a trip with on a route with the respective transportation
     Connection {
@@ -110,207 +177,301 @@ pub enum Connection {
     },
     /// By walking from a source stop (index in stops data structure) and the connected transfer (index)
     FootPath { source: usize, transfer: usize },
+    /// By staying seated on a trip that continues as the trips of other routes with the same
+    /// vehicle. Riders don't change vehicles, so it counts as a single trip
+    Continuation {
+        /// The route and trip that were boarded
+        boarded_route: usize,
+        boarded_trip_number: usize,
+        boarded_at_stop: usize,
+        /// The route and trip the boarded trip continued as when exiting
+        route: usize,
+        trip_number: usize,
+        exited_at_stop: usize,
+    },
+}
+
+/// What riders need from the trips, stops and foot-paths of their journeys
+#[derive(Clone, Copy, Default)]
+pub struct QueryOptions {
+    /// Only board and exit wheelchair accessible trips at wheelchair accessible stops and only walk
+    /// foot-paths without stairs or escalators
+    pub wheelchair_accessible: bool,
 }
 
 pub fn raptor(
     source: usize,
     target: usize,
     departure: &Time,
-    route_data: RoutesData,
-    stops: StopsData,
+    timetable: &impl TimetableView,
 ) -> Vec<HashMap<usize, Connection>> {
+    let mut search = Search::default();
+    search.run(
+        &[(source, Time::from(0))],
+        *departure,
+        &[(target, Time::from(0))],
+        timetable,
+    );
+    search.connections_by_round
+}
 
-    let mut k = 0usize;
-
-    // For each round the best arrival by stop. Index is amount of transfers or k - 1
-    let mut labels_by_round = vec![HashMap::from([(source, departure.clone())])];
-    // The best arrival time for any stop without caring about the round
-    let mut best_by_stop = HashMap::from([(source, departure)]);
-    // Connections to reconstruct journey
-    let mut connections_by_round = Vec::new();
-
-    let mut marked_stops = HashSet::from([&source]);
-    // Stops by route
-    // Don't use HashMap because it doesn't ensure ordering (it actually randomizes the order)
-    // TODO measure if VecDeque is faster but we don't need it as we remove elements all at once when iterating
-    let mut queue = Vec::<(&usize, &usize)>::new();
-
-    while !marked_stops.is_empty() {
-        k += 1;
-        let last_round_labels = &labels_by_round[k - 1];
+/// The labels and connections of a search.
+/// Range queries keep them between the runs for each departure, as an arrival reached with a later
+/// departure can also be reached when departing earlier and waiting.
+#[derive(Default)]
+pub(crate) struct Search {
+    /// For each round the best arrival by stop. Index is amount of transfers or k - 1
+    pub(crate) labels_by_round: Vec<HashMap<usize, Time>>,
+    /// Connections to reconstruct journeys. Index is the round k - 1
+    pub(crate) connections_by_round: Vec<HashMap<usize, Connection>>,
+    pub(crate) options: QueryOptions,
+}
 
-        let mut current_round_labels: HashMap<usize, Time> = HashMap::new();
-        // Best connection for current round by the stop the connection reaches
-        // For journey reconstruction
-        let mut connection_by_stop: HashMap<usize, Connection> = HashMap::new();
+/// The labels of a round of a [Search] for scanning routes
+struct SearchRound<'a> {
+    last_round_labels: &'a HashMap<usize, Time>,
+    last_round_connections: Option<&'a HashMap<usize, Connection>>,
+    current_round_labels: &'a mut HashMap<usize, Time>,
+    connection_by_stop: &'a mut HashMap<usize, Connection>,
+    /// The best arrival time for any stop in this run without caring about the round
+    best_by_stop: &'a mut HashMap<usize, Time>,
+    egress_by_stop: &'a HashMap<usize, Time>,
+    /// Earliest arrival at the destination, lowered when an egress stop improves
+    target_bound: Time,
+    marked_stops: &'a mut HashSet<usize>,
+}
 
-        // Accumulate routes serving marked stops from previous round
-        let routes_at_stop: HashMap<&usize, &[usize]> = marked_stops
-            .iter()
-            .map(|stop| (*stop, stops.get_routes(stop)))
-            .collect();
+impl RoundLabels for SearchRound<'_> {
+    fn previous_arrival(&self, stop: usize) -> Time {
+        *self.last_round_labels.get(&stop).unwrap_or(&Time::INFINITE)
+    }
 
-        //TODO use consume queue when iterating below and remove clear
-        queue.clear();
+    fn is_reached_by_trip(&self, stop: usize) -> bool {
+        self.last_round_connections
+            .and_then(|connections| connections.get(&stop))
+            .is_some_and(|connection| {
+                matches!(
+                    connection,
+                    Connection::Connection { .. } | Connection::Continuation { .. }
+                )
+            })
+    }
 
-        for p in &marked_stops {
-            let routes_serving_p = routes_at_stop[p];
+    fn exit_bound(&self, stop: usize) -> Time {
+        // Earliest known arrival at stop for any route and trip (for local pruning?)
+        let earliest_arrival = *self.best_by_stop.get(&stop).unwrap_or(&Time::INFINITE);
+        // Arrivals in this round from runs with a later departure in range queries. Arriving later
+        // than those with the same amount of trips is no improvement
+        let round_arrival = *self
+            .current_round_labels
+            .get(&stop)
+            .unwrap_or(&Time::INFINITE);
+        min(min(earliest_arrival, round_arrival), self.target_bound)
+    }
 
-            for route in routes_serving_p {
-                // If there is another stop that we reached, and it serves the same route,
-                // check if we can replace the other stop with the current one
-                //TODO measure performance impact of sequential search
-                if let Some(p_other_index) = queue.iter().position(|(queued_route, _p_other)| *queued_route == route) {
-                    let p_other = &queue[p_other_index].1;
-                    let route_value = &route_data.routes[*route];
-                    let sequence = &route_data.get_stop_sequence(route_value, p).unwrap();
-                    let sequence_other =
-                        &route_data.get_stop_sequence(route_value, p_other).unwrap();
+    fn improve(&mut self, stop: usize, arrival: Time, connection: Connection) {
+        self.current_round_labels.insert(stop, arrival);
+        self.best_by_stop.insert(stop, arrival);
+        if let Some(&walking_time) = self.egress_by_stop.get(&stop) {
+            self.target_bound = min(self.target_bound, arrival + walking_time);
+        }
+        self.connection_by_stop.insert(stop, connection);
+        // Mark as improved
+        self.marked_stops.insert(stop);
+    }
+}
 
-                    // If p comes before p' (p_other) replace p' with p
-                    if sequence < sequence_other {
-                        queue[p_other_index] = (route, p);
-                        // Continue loop
-                    }
-                    // Else if the stop p doesn't come before p' (p_other), the other reached stop
-                    // reaches the route earlier than p, so we can't replace p' with p, and we don't
-                    // need to add p to the queue
-                    continue;
-                }
+/// The earliest arrival at the destination by walking from any of the egress stops
+fn get_destination_arrival(labels: &HashMap<usize, Time>, egress: &[(usize, Time)]) -> Time {
+    egress
+        .iter()
+        .filter_map(|(stop, walking_time)| labels.get(stop).map(|arrival| *arrival + *walking_time))
+        .min()
+        .unwrap_or(Time::INFINITE)
+}
 
-                // Else add to queue
-                queue.push((route, p));
-            }
+impl Search {
+    /// Runs the RAPTOR rounds departing at the given time until no stop can be improved anymore.
+    /// The access stops are reached by walking from the origin and the destination is reached by
+    /// walking from the egress stops. Both are given as pairs of stop and walking time.
+    pub(crate) fn run(
+        &mut self,
+        access: &[(usize, Time)],
+        departure: Time,
+        egress: &[(usize, Time)],
+        timetable: &impl TimetableView,
+    ) {
+        let mut k = 0usize;
+        let options = self.options;
+
+        if self.labels_by_round.is_empty() {
+            self.labels_by_round.push(HashMap::new());
         }
 
-        marked_stops.clear();
-
-        for (route_index, p) in &queue {
-            // Go through each stop of route starting with p
-            let route = &route_data.routes[**route_index];
-            let route_stops = route_data.get_route_stops(route);
-            let mut current_trip: Option<(usize, &[StopTime], &usize)> = None;
+        // The best arrival time for any stop in this run without caring about the round
+        let mut best_by_stop = HashMap::new();
+        let mut marked_stops = HashSet::new();
+        // To update the arrival at the destination when an egress stop improves
+        let mut egress_by_stop: HashMap<usize, Time> = HashMap::new();
+        for &(stop, walking_time) in egress {
+            let egress_time = egress_by_stop.entry(stop).or_insert(walking_time);
+            *egress_time = min(*egress_time, walking_time);
+        }
 
-            // Traverse stops in route starting with marked stop
-            let start_sequence = route_stops.iter().position(|stop| &stop == p).unwrap();
+        for &(stop, walking_time) in access {
+            let arrival = departure + walking_time;
+            let label = self.labels_by_round[0].entry(stop).or_insert(arrival);
+            *label = min(*label, arrival);
+            best_by_stop.insert(stop, *label);
+            marked_stops.insert(stop);
+        }
 
-            for stop_sequence in start_sequence..route_stops.len() {
-                // Stop (index) of the stop in the trip we traverse
-                let trip_stop = &route_stops[stop_sequence];
+        // Positions of the earliest marked stop by route
+        // Don't use HashMap because it doesn't ensure ordering (it actually randomizes the order)
+        // TODO measure if VecDeque is faster but we don't need it as we remove elements all at once when iterating
+        let mut queue = Vec::<(usize, usize)>::new();
 
-                if let Some((trip_number, trip_times, boarded_at_stop)) = current_trip {
-                    // Earliest known arrival at stop for any route and trip (for local pruning?)
-                    let earliest_arrival = best_by_stop.get(&trip_stop).unwrap_or(&&Infinite);
-                    // Earliest arrival at target stop for journey. Used for target pruning.
-                    // (We don't need to look at stops that arrive after the target arrival if we
-                    // have one)
-                    let earliest_arrival_target = best_by_stop.get(&target).unwrap_or(&&Infinite);
-                    // Arrival time for the current stop on the current trip for the current route
-                    let arrival_time = &trip_times[stop_sequence].arrival_time;
-                    // Can label be improved
+        while !marked_stops.is_empty() {
+            k += 1;
 
-                    //TODO consider minimum time it takes to transfer between lines/routes/trips
-                    //TODO check if we can drop off at stop
+            if self.labels_by_round.len() <= k {
+                self.labels_by_round.push(HashMap::new());
+                self.connections_by_round.push(HashMap::new());
+            }
 
-                    if &arrival_time < min(earliest_arrival, earliest_arrival_target) {
-                        current_round_labels.insert(*trip_stop, *arrival_time);
-                        best_by_stop.insert(*trip_stop, arrival_time);
-                        // Save connection to reconstruct journey
-                        let connection = Connection::Connection {
-                            route: **route_index,
-                            trip_number,
-                            boarded_at_stop: *boarded_at_stop,
-                            exited_at_stop: *trip_stop,
-                        };
-                        connection_by_stop.insert(*trip_stop, connection);
-                        // Mark as improved
-                        marked_stops.insert(&trip_stop);
+            let (previous_rounds, next_rounds) = self.labels_by_round.split_at_mut(k);
+            let last_round_labels = &previous_rounds[k - 1];
+            let current_round_labels = &mut next_rounds[0];
+            // Best connection for current round by the stop the connection reaches
+            // For journey reconstruction
+            let (previous_connections, next_connections) =
+                self.connections_by_round.split_at_mut(k - 1);
+            let last_round_connections = previous_connections.last();
+            let connection_by_stop = &mut next_connections[0];
+
+            //TODO use consume queue when iterating below and remove clear
+            queue.clear();
+
+            for &p in &marked_stops {
+                // Accumulate routes serving marked stops from previous round with the position of
+                // the stop on the route
+                for (route, sequence) in timetable.get_route_positions(&p) {
+                    // If there is another stop that we reached, and it serves the same route,
+                    // check if we can replace the other stop with the current one
+                    //TODO measure performance impact of sequential search
+                    if let Some(p_other_index) = queue
+                        .iter()
+                        .position(|(queued_route, _sequence_other)| *queued_route == route)
+                    {
+                        let sequence_other = queue[p_other_index].1;
+
+                        // If p comes before p' (p_other) replace p' with p
+                        if sequence < sequence_other {
+                            queue[p_other_index] = (route, sequence);
+                            // Continue loop
+                        }
+                        // Else if the stop p doesn't come before p' (p_other), the other reached stop
+                        // reaches the route earlier than p, so we can't replace p' with p, and we don't
+                        // need to add p to the queue
+                        continue;
                     }
-                }
-
-                // Can we catch an earlier trip?
-                let previous_arrival = last_round_labels.get(trip_stop).unwrap_or(&&Infinite);
 
-                // Pseudo code example code uses departure but this is probably a typo as text uses
-                // arrival which makes more sense to my understanding of the algorithm
-                let arrival_time = &current_trip
-                    .map(|(_, trip, _)| &trip[stop_sequence].arrival_time)
-                    .unwrap_or(&Infinite);
-
-                if previous_arrival <= arrival_time {
-                    current_trip = route_data
-                        .get_earliest_departing_trip(route, &stop_sequence, previous_arrival)
-                        .map(|(trip_number, trip_times)| (trip_number, trip_times, trip_stop));
+                    // Else add to queue
+                    queue.push((route, sequence));
                 }
             }
-        }
 
-        // Can not change marked stops while iterating, so we save them here temporarily
-        let mut new_marks = HashSet::new();
-        // Look at foot-paths
-        for p in &marked_stops {
-            let stop = &stops.stops[**p];
-            let start = stop.transfers_index_start;
+            marked_stops.clear();
+
+            // Earliest arrival at the destination for journey. Used for target pruning.
+            // (We don't need to look at stops that arrive after the destination arrival if we have
+            // one). Includes arrivals in this round from runs with a later departure in range queries
+            let target_bound = min(
+                get_destination_arrival(&best_by_stop, egress),
+                get_destination_arrival(current_round_labels, egress),
+            );
+            let mut round = SearchRound {
+                last_round_labels,
+                last_round_connections,
+                current_round_labels: &mut *current_round_labels,
+                connection_by_stop: &mut *connection_by_stop,
+                best_by_stop: &mut best_by_stop,
+                egress_by_stop: &egress_by_stop,
+                target_bound,
+                marked_stops: &mut marked_stops,
+            };
+            for &(queued_route, queued_sequence) in &queue {
+                scan_route(
+                    &mut round,
+                    queued_route,
+                    queued_sequence,
+                    timetable,
+                    &options,
+                );
+            }
 
-            let arrival_at_p = current_round_labels.get(*p).cloned().unwrap_or(Infinite);
+            // Can not change marked stops while iterating, so we save them here temporarily
+            let mut new_marks = HashSet::new();
+            // Look at foot-paths
+            for &p in &marked_stops {
+                let arrival_at_p = current_round_labels
+                    .get(&p)
+                    .cloned()
+                    .unwrap_or(Time::INFINITE);
 
-            for transfer_index in 0..stop.transfers_count {
-                let transfer = &stops.transfers[start + transfer_index];
-                let arrival_by_foot = arrival_at_p + transfer.time;
+                for (transfer_index, transfer) in timetable.get_transfers(p).enumerate() {
+                    if !transfer.can_walk_with(&options) {
+                        continue;
+                    }
 
-                let current_arrival_target = current_round_labels
-                    .get(&transfer.target)
-                    .cloned()
-                    .unwrap_or(Infinite);
+                    let arrival_by_foot = arrival_at_p + transfer.time;
 
-                if arrival_by_foot < current_arrival_target {
-                    // Improved arrival time by walking
-                    current_round_labels.insert(transfer.target, arrival_by_foot);
-                    // Add footpath to connections
-                    let connection = Connection::FootPath {
-                        source: **p,
-                        transfer: transfer_index,
-                    };
-                    connection_by_stop.insert(transfer.target, connection);
-                    // Mark stop as improved
-                    new_marks.insert(&transfer.target);
-                    // marked_stops.insert(&transfer.target);
+                    let current_arrival_target = current_round_labels
+                        .get(&transfer.target)
+                        .cloned()
+                        .unwrap_or(Time::INFINITE);
+
+                    if arrival_by_foot < current_arrival_target {
+                        // Improved arrival time by walking
+                        current_round_labels.insert(transfer.target, arrival_by_foot);
+                        // Add footpath to connections
+                        let connection = Connection::FootPath {
+                            source: p,
+                            transfer: transfer_index,
+                        };
+                        connection_by_stop.insert(transfer.target, connection);
+                        // Mark stop as improved
+                        new_marks.insert(transfer.target);
+                    }
                 }
             }
-        }
 
-        // Add collected improved stops
-        marked_stops.extend(new_marks);
-
-        labels_by_round.push(current_round_labels);
-        connections_by_round.push(connection_by_stop);
+            // Add collected improved stops
+            marked_stops.extend(new_marks);
+        }
     }
-
-    connections_by_round
 }
 
 pub fn raptor_bugged(
     source: usize,
     target: usize,
     departure: &Time,
-    route_data: RoutesData,
-    stops: StopsData,
+    timetable: &impl TimetableView,
 ) -> Vec<HashMap<usize, Connection>> {
-
     let mut k = 0usize;
 
     // For each round the best arrival by stop. Index is amount of transfers or k - 1
-    let mut labels_by_round = vec![HashMap::from([(source, departure.clone())])];
+    let mut labels_by_round = vec![HashMap::from([(source, *departure)])];
     // The best arrival time for any stop without caring about the round
-    let mut best_by_stop = HashMap::from([(source, departure)]);
+    let mut best_by_stop = HashMap::from([(source, *departure)]);
     // Connections to reconstruct journey
     let mut connections_by_round = Vec::new();
 
-    let mut marked_stops = HashSet::from([&source]);
+    let mut marked_stops = HashSet::from([source]);
     // Stops by route
     // Don't use HashMap because it doesn't ensure ordering (it actually randomizes the order)
     // TODO measure if VecDeque is faster but we don't need it as we remove elements all at once when iterating
-    let mut queue: HashMap<&usize, &usize> = HashMap::new();
+    let mut queue: HashMap<usize, usize> = HashMap::new();
 
     while !marked_stops.is_empty() {
         k += 1;
@@ -322,30 +483,30 @@ pub fn raptor_bugged(
         let mut connection_by_stop: HashMap<usize, Connection> = HashMap::new();
 
         // Accumulate routes serving marked stops from previous round
-        let routes_at_stop: HashMap<&usize, &[usize]> = marked_stops
+        let routes_at_stop: HashMap<usize, Vec<usize>> = marked_stops
             .iter()
-            .map(|stop| (*stop, stops.get_routes(stop)))
+            .map(|stop| (*stop, timetable.get_routes(stop).collect()))
             .collect();
 
         //TODO use consume queue when iterating below and remove clear
         queue.clear();
 
-        for p in &marked_stops {
-            let routes_serving_p = routes_at_stop[p];
+        for &p in &marked_stops {
+            let routes_serving_p = &routes_at_stop[&p];
 
-            for route in routes_serving_p {
+            for &route in routes_serving_p {
                 // If there is another stop that we reached, and it serves the same route,
                 // check if we can replace the other stop with the current one
                 //TODO measure performance impact of sequential search
-                if let Some(p_other) = queue.get(route) {
-                    let route_value = &route_data.routes[*route];
-                    let sequence = &route_data.get_stop_sequence(route_value, p).unwrap();
+                if let Some(p_other) = queue.get(&route) {
+                    let route_value = timetable.route(route);
+                    let sequence = timetable.get_stop_sequence(&route_value, &p).unwrap();
                     let sequence_other =
-                        &route_data.get_stop_sequence(route_value, p_other).unwrap();
+                        timetable.get_stop_sequence(&route_value, p_other).unwrap();
 
                     // If p comes before p' (p_other) replace p' with p
                     if sequence < sequence_other {
-                        &queue.insert(route, p);
+                        queue.insert(route, p);
                     }
                     continue;
                 }
@@ -356,61 +517,65 @@ pub fn raptor_bugged(
 
         marked_stops.clear();
 
-        for (route_index, p) in &queue {
+        for (&route_index, p) in &queue {
             // Go through each stop of route starting with p
-            let route = &route_data.routes[**route_index];
-            let route_stops = route_data.get_route_stops(route);
-            let mut current_trip: Option<(usize, &[StopTime], &usize)> = None;
+            let route = timetable.route(route_index);
+            let mut current_trip: Option<(usize, TripTimes<'_, _>, usize)> = None;
 
             // Traverse stops in route starting with marked stop
-            let start_sequence = route_stops.iter().position(|stop| &stop == p).unwrap();
+            let start_sequence = timetable.get_stop_sequence(&route, p).unwrap();
 
-            for stop_sequence in start_sequence..route_stops.len() {
+            for stop_sequence in start_sequence..route.number_of_stops {
                 // Stop (index) of the stop in the trip we traverse
-                let trip_stop = &route_stops[stop_sequence];
-
+                let trip_stop = timetable.get_route_stop(&route, stop_sequence);
                 if let Some((trip_number, trip_times, boarded_at_stop)) = current_trip {
                     // Earliest known arrival at stop for any route and trip (for local pruning?)
-                    let earliest_arrival = best_by_stop.get(&trip_stop).unwrap_or(&&Infinite);
+                    let earliest_arrival = best_by_stop.get(&trip_stop).unwrap_or(&Time::INFINITE);
                     // Earliest arrival at target stop for journey. Used for target pruning.
                     // (We don't need to look at stops that arrive after the target arrival if we
                     // have one)
-                    let earliest_arrival_target = best_by_stop.get(&target).unwrap_or(&&Infinite);
+                    let earliest_arrival_target =
+                        best_by_stop.get(&target).unwrap_or(&Time::INFINITE);
                     // Arrival time for the current stop on the current trip for the current route
-                    let arrival_time = &trip_times[stop_sequence].arrival_time;
+                    let arrival_time = trip_times.get(stop_sequence).arrival_time;
                     // Can label be improved
 
                     //TODO consider minimum time it takes to transfer between lines/routes/trips
                     //TODO check if we can drop off at stop
 
                     if &arrival_time < min(earliest_arrival, earliest_arrival_target) {
-                        current_round_labels.insert(*trip_stop, *arrival_time);
-                        best_by_stop.insert(*trip_stop, arrival_time);
+                        current_round_labels.insert(trip_stop, arrival_time);
+                        best_by_stop.insert(trip_stop, arrival_time);
                         // Save connection to reconstruct journey
                         let connection = Connection::Connection {
-                            route: **route_index,
+                            route: route_index,
                             trip_number,
-                            boarded_at_stop: *boarded_at_stop,
-                            exited_at_stop: *trip_stop,
+                            boarded_at_stop,
+                            exited_at_stop: trip_stop,
                         };
-                        connection_by_stop.insert(*trip_stop, connection);
+                        connection_by_stop.insert(trip_stop, connection);
                         // Mark as improved
-                        marked_stops.insert(&trip_stop);
+                        marked_stops.insert(trip_stop);
                     }
                 }
 
                 // Can we catch an earlier trip?
-                let previous_arrival = last_round_labels.get(trip_stop).unwrap_or(&&Infinite);
+                let previous_arrival = last_round_labels.get(&trip_stop).unwrap_or(&Time::INFINITE);
 
                 // Pseudo code example code uses departure but this is probably a typo as text uses
                 // arrival which makes more sense to my understanding of the algorithm
                 let arrival_time = &current_trip
-                    .map(|(_, trip, _)| &trip[stop_sequence].arrival_time)
-                    .unwrap_or(&Infinite);
+                    .map(|(_, trip, _)| trip.get(stop_sequence).arrival_time)
+                    .unwrap_or(Time::INFINITE);
 
                 if previous_arrival <= arrival_time {
-                    current_trip = route_data
-                        .get_earliest_departing_trip(route, &stop_sequence, previous_arrival)
+                    current_trip = timetable
+                        .get_earliest_departing_trip(
+                            &route,
+                            &stop_sequence,
+                            previous_arrival,
+                            &QueryOptions::default(),
+                        )
                         .map(|(trip_number, trip_times)| (trip_number, trip_times, trip_stop));
                 }
             }
@@ -419,32 +584,35 @@ pub fn raptor_bugged(
         // Can not change marked stops while iterating, so we save them here temporarily
         let mut new_marks = HashSet::new();
         // Look at foot-paths
-        for p in &marked_stops {
-            let stop = &stops.stops[**p];
-            let start = stop.transfers_index_start;
-
-            let arrival_at_p = current_round_labels.get(*p).cloned().unwrap_or(Infinite);
+        for &p in &marked_stops {
+            let arrival_at_p = current_round_labels
+                .get(&p)
+                .cloned()
+                .unwrap_or(Time::INFINITE);
+
+            for (transfer_index, transfer) in timetable.get_transfers(p).enumerate() {
+                if !transfer.can_walk_with(&QueryOptions::default()) {
+                    continue;
+                }
 
-            for transfer_index in 0..stop.transfers_count {
-                let transfer = &stops.transfers[start + transfer_index];
                 let arrival_by_foot = arrival_at_p + transfer.time;
 
                 let current_arrival_target = current_round_labels
                     .get(&transfer.target)
                     .cloned()
-                    .unwrap_or(Infinite);
+                    .unwrap_or(Time::INFINITE);
 
                 if arrival_by_foot < current_arrival_target {
                     // Improved arrival time by walking
                     current_round_labels.insert(transfer.target, arrival_by_foot);
                     // Add footpath to connections
                     let connection = Connection::FootPath {
-                        source: **p,
+                        source: p,
                         transfer: transfer_index,
                     };
                     connection_by_stop.insert(transfer.target, connection);
                     // Mark stop as improved
-                    new_marks.insert(&transfer.target);
+                    new_marks.insert(transfer.target);
                     // marked_stops.insert(&transfer.target);
                 }
             }
@@ -462,7 +630,108 @@ pub fn raptor_bugged(
 
 #[test]
 fn huh() {
-    assert_eq!(Finite(3), Finite(3))
+    assert_eq!(Time::from(3), Time::from(3))
 }
 
 //TODO Benchmark passing time as reference (Arc/Ref or &) vs copying/cloning time values...If that even matters at all
+
+#[cfg(test)]
+mod tests {
+    use crate::journey::reconstruct_journeys;
+    use crate::shared::Timetable;
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{raptor, Time};
+
+    fn change_network() -> Timetable {
+        let (routes_data, stops_data) = build_network(
+            3,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![230, 300], vec![300, 400]],
+                },
+            ],
+            Vec::new(),
+        );
+
+        Timetable {
+            routes_data,
+            stops_data,
+        }
+    }
+
+    #[test]
+    fn changes_without_buffer() {
+        // Arrange
+        let timetable = change_network();
+
+        // Act
+        let rounds = raptor(0, 2, &Time::from(0), &timetable);
+        let journeys = reconstruct_journeys(2, &rounds, &timetable);
+
+        // Assert
+        assert_eq!(Time::from(300), journeys[0].arrival());
+    }
+
+    #[test]
+    fn stop_change_time_misses_close_connection() {
+        // Arrange
+        let mut timetable = change_network();
+        timetable.stops_data.stops[1].minimum_change_time = Some(Time::from(60));
+
+        // Act
+        let rounds = raptor(0, 2, &Time::from(0), &timetable);
+        let journeys = reconstruct_journeys(2, &rounds, &timetable);
+
+        // Assert
+        assert_eq!(Time::from(400), journeys[0].arrival());
+    }
+
+    #[test]
+    fn default_change_time_applies_to_stops_without_data() {
+        // Arrange
+        let mut timetable = change_network();
+        timetable.stops_data.default_change_time = Time::from(60);
+
+        // Act
+        let rounds = raptor(0, 2, &Time::from(0), &timetable);
+        let journeys = reconstruct_journeys(2, &rounds, &timetable);
+
+        // Assert
+        assert_eq!(Time::from(400), journeys[0].arrival());
+    }
+
+    #[test]
+    fn does_not_board_where_pickup_is_forbidden() {
+        // Arrange
+        let mut timetable = change_network();
+        // First trip of the second route at its first stop
+        timetable.routes_data.stop_times[2].can_board = false;
+
+        // Act
+        let rounds = raptor(0, 2, &Time::from(0), &timetable);
+        let journeys = reconstruct_journeys(2, &rounds, &timetable);
+
+        // Assert
+        assert_eq!(Time::from(400), journeys[0].arrival());
+    }
+
+    #[test]
+    fn does_not_exit_where_drop_off_is_forbidden() {
+        // Arrange
+        let mut timetable = change_network();
+        // Only trip of the first route at its last stop
+        timetable.routes_data.stop_times[1].can_exit = false;
+
+        // Act
+        let rounds = raptor(0, 2, &Time::from(0), &timetable);
+        let journeys = reconstruct_journeys(2, &rounds, &timetable);
+
+        // Assert
+        assert!(journeys.is_empty());
+    }
+}
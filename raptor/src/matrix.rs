@@ -0,0 +1,149 @@
+use crate::one_to_all::{one_to_all_raptor, Arrival};
+use crate::shared::TimetableView;
+use crate::{QueryOptions, Time};
+use std::num::NonZeroUsize;
+use std::thread;
+
+/// Earliest arrivals from each source to each target stored row by row
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Matrix {
+    /// The amount of targets
+    columns: usize,
+    arrivals: Vec<Option<Arrival>>,
+}
+
+impl Matrix {
+    /// The earliest arrival from the source to the target by their position in the lists the matrix
+    /// was calculated for or none if the target can not be reached
+    pub fn get(&self, source: usize, target: usize) -> Option<Arrival> {
+        assert!(target < self.columns, "Target out of bounds");
+        self.arrivals[source * self.columns + target]
+    }
+
+    /// The arrivals from the source at the position in the source list at each target
+    pub fn row(&self, source: usize) -> &[Option<Arrival>] {
+        &self.arrivals[source * self.columns..(source + 1) * self.columns]
+    }
+}
+
+/// Calculates the earliest arrival and the amount of trips needed from every source stop to every
+/// target stop with one one-to-all query per source. The transfers are one less than the trips, see
+/// [Arrival::transfers].
+/// Sources are split across threads which all borrow the same timetable. Only takes the trips and
+/// foot-paths the options allow.
+pub fn matrix_raptor(
+    sources: &[usize],
+    targets: &[usize],
+    departure: &Time,
+    timetable: &(impl TimetableView + Sync),
+    options: &QueryOptions,
+) -> Matrix {
+    let columns = targets.len();
+    let mut arrivals = vec![None; sources.len() * columns];
+    if columns == 0 {
+        return Matrix { columns, arrivals };
+    }
+
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let sources_per_thread = sources.len().div_ceil(threads).max(1);
+
+    thread::scope(|scope| {
+        for (sources, rows) in sources
+            .chunks(sources_per_thread)
+            .zip(arrivals.chunks_mut(sources_per_thread * columns))
+        {
+            scope.spawn(move || {
+                for (source, row) in sources.iter().zip(rows.chunks_mut(columns)) {
+                    let arrival_by_stop = one_to_all_raptor(
+                        &[(*source, Time::from(0))],
+                        departure,
+                        timetable,
+                        options,
+                    );
+                    for (arrival, target) in row.iter_mut().zip(targets) {
+                        *arrival = arrival_by_stop[*target];
+                    }
+                }
+            });
+        }
+    });
+
+    Matrix { columns, arrivals }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matrix::matrix_raptor;
+    use crate::one_to_all::Arrival;
+    use crate::shared::Timetable;
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{QueryOptions, Time};
+
+    #[test]
+    fn finds_arrival_from_every_source_at_every_target() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            4,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1, 2],
+                    trips: vec![vec![100, 200, 300]],
+                },
+                TestRoute {
+                    stops: vec![2, 3],
+                    trips: vec![vec![400, 500]],
+                },
+            ],
+            Vec::new(),
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let matrix = matrix_raptor(
+            &[0, 1, 3],
+            &[2, 3],
+            &Time::from(0),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(
+            &[
+                Some(Arrival {
+                    time: Time::from(300),
+                    trips: 1,
+                }),
+                Some(Arrival {
+                    time: Time::from(500),
+                    trips: 2,
+                }),
+            ],
+            matrix.row(0)
+        );
+        assert_eq!(
+            Some(Time::from(300)),
+            matrix.get(1, 0).map(|arrival| arrival.time)
+        );
+        assert_eq!(None, matrix.get(2, 0));
+        let transfers = |source, target| {
+            matrix
+                .get(source, target)
+                .map(|arrival| arrival.transfers())
+        };
+        assert_eq!(Some(0), transfers(0, 0));
+        assert_eq!(Some(1), transfers(0, 1));
+        // Already at the target
+        assert_eq!(Some(0), transfers(2, 1));
+        assert_eq!(
+            Some(Arrival {
+                time: Time::from(0),
+                trips: 0,
+            }),
+            matrix.get(2, 1)
+        );
+    }
+}
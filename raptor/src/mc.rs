@@ -0,0 +1,669 @@
+use crate::journey::{Journey, Leg};
+use crate::shared::{TimetableLookup, TimetableView};
+use crate::{QueryOptions, Time};
+use std::collections::{HashMap, HashSet};
+
+/// A ride on a trip from one stop to another that a criterion can be evaluated on
+pub struct Ride {
+    /// Index of the route in the route data
+    pub route: usize,
+    /// Number of the trip within the route
+    pub trip_number: usize,
+    pub boarded_at_stop: usize,
+    pub exited_at_stop: usize,
+    pub departure: Time,
+    pub arrival: Time,
+    /// How many trips were taken before this one
+    pub trips_before: usize,
+}
+
+/// A walk through a foot-path that a criterion can be evaluated on
+pub struct Walk {
+    pub from_stop: usize,
+    pub to_stop: usize,
+    pub time: Time,
+}
+
+/// An additional criterion McRAPTOR optimizes besides the arrival time. Smaller values are better.
+/// Values start at 0 at the source and must never decrease along a journey, otherwise the pruning
+/// of the search would discard journeys that could become better later.
+pub trait Criterion {
+    /// The value after riding a trip given the value before boarding it
+    fn ride(&self, value: u64, ride: &Ride) -> u64;
+
+    /// The value after walking a foot-path given the value before starting to walk
+    fn walk(&self, value: u64, walk: &Walk) -> u64;
+}
+
+/// The total time spent walking in seconds
+pub struct WalkingTime;
+
+impl Criterion for WalkingTime {
+    fn ride(&self, value: u64, _ride: &Ride) -> u64 {
+        value
+    }
+
+    fn walk(&self, value: u64, walk: &Walk) -> u64 {
+        match walk.time.seconds() {
+            Some(seconds) => value.saturating_add(seconds),
+            None => u64::MAX,
+        }
+    }
+}
+
+/// The number of times the passenger changes from one vehicle to another
+pub struct VehicleChanges;
+
+impl Criterion for VehicleChanges {
+    fn ride(&self, value: u64, ride: &Ride) -> u64 {
+        if ride.trips_before == 0 {
+            value
+        } else {
+            value + 1
+        }
+    }
+
+    fn walk(&self, value: u64, _walk: &Walk) -> u64 {
+        value
+    }
+}
+
+/// The fare cost when every boarding costs the fare of its route
+pub struct RouteFare {
+    /// The fare (e.g. in cents) by the index of the route in the route data
+    pub fare_by_route: Vec<u64>,
+}
+
+impl Criterion for RouteFare {
+    fn ride(&self, value: u64, ride: &Ride) -> u64 {
+        value.saturating_add(self.fare_by_route[ride.route])
+    }
+
+    fn walk(&self, value: u64, _walk: &Walk) -> u64 {
+        value
+    }
+}
+
+/// A journey from McRAPTOR with the values for each criterion in the order they were passed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct McJourney {
+    pub journey: Journey,
+    pub values: Vec<u64>,
+}
+
+/// How a label was reached for journey reconstruction. Parents are indices into the label arena
+enum Reached {
+    Source,
+    Ride {
+        parent: usize,
+        route: usize,
+        trip_number: usize,
+        boarded_at_stop: usize,
+        departure: Time,
+    },
+    Walk {
+        parent: usize,
+        from_stop: usize,
+    },
+}
+
+struct Label {
+    arrival: Time,
+    values: Vec<u64>,
+    reached: Reached,
+}
+
+impl Label {
+    fn dominates(&self, other: &Label) -> bool {
+        self.arrival <= other.arrival
+            && self
+                .values
+                .iter()
+                .zip(&other.values)
+                .all(|(value, other_value)| value <= other_value)
+    }
+}
+
+/// A trip boarded by a label of the previous round while scanning a route
+struct RouteLabel {
+    parent: usize,
+    trip_number: usize,
+    boarded_at_stop: usize,
+    boarded_sequence: usize,
+}
+
+/// Labels that are not dominated by each other. Contains indices into the label arena
+type Bag = Vec<usize>;
+
+/// Adds the label to the bag if no label in the bag dominates it and removes the labels it
+/// dominates. Returns whether the label was added.
+fn merge(bag: &mut Bag, labels: &[Label], label: usize) -> bool {
+    if bag
+        .iter()
+        .any(|existing| labels[*existing].dominates(&labels[label]))
+    {
+        return false;
+    }
+
+    bag.retain(|existing| !labels[label].dominates(&labels[*existing]));
+    bag.push(label);
+    true
+}
+
+fn is_dominated(bag: Option<&Bag>, labels: &[Label], label: &Label) -> bool {
+    bag.is_some_and(|bag| {
+        bag.iter()
+            .any(|existing| labels[*existing].dominates(label))
+    })
+}
+
+/// Multi-criteria RAPTOR (McRAPTOR) that keeps a bag of Pareto-optimal labels for each stop and
+/// round instead of a single arrival time. Besides the arrival time the labels are compared by the
+/// given criteria.
+///
+/// Only takes the trips and foot-paths the options allow.
+/// Trip continuations are not followed, so riders change between trips of the same vehicle like
+/// between any other trips.
+///
+/// Returns all Pareto-optimal journeys to the target
+pub fn mc_raptor(
+    source: usize,
+    target: usize,
+    departure: &Time,
+    criteria: &[&dyn Criterion],
+    timetable: &impl TimetableView,
+    options: &QueryOptions,
+) -> Vec<McJourney> {
+    // All labels ever created. Bags only refer to them by index, so parents stay reachable for
+    // journey reconstruction
+    let mut labels = vec![Label {
+        arrival: *departure,
+        values: vec![0; criteria.len()],
+        reached: Reached::Source,
+    }];
+
+    // For each round the bags by stop
+    let mut bags_by_round: Vec<HashMap<usize, Bag>> = vec![HashMap::from([(source, vec![0])])];
+    // The best bag for any stop without caring about the round for pruning
+    let mut best_by_stop: HashMap<usize, Bag> = HashMap::from([(source, vec![0])]);
+
+    let mut marked_stops = HashSet::from([source]);
+    let mut queue = Vec::<(usize, usize)>::new();
+
+    while !marked_stops.is_empty() {
+        let k = bags_by_round.len();
+        let mut current_round_bags: HashMap<usize, Bag> = HashMap::new();
+
+        queue.clear();
+        for &p in &marked_stops {
+            for (route, sequence) in timetable.get_route_positions(&p) {
+                if let Some(p_other_index) = queue
+                    .iter()
+                    .position(|(queued_route, _sequence_other)| *queued_route == route)
+                {
+                    if sequence < queue[p_other_index].1 {
+                        queue[p_other_index] = (route, sequence);
+                    }
+                    continue;
+                }
+
+                queue.push((route, sequence));
+            }
+        }
+
+        marked_stops.clear();
+        let last_round_bags = &bags_by_round[k - 1];
+
+        for &(route_index, start_sequence) in &queue {
+            let route = timetable.route(route_index);
+            // Trips boarded on this route with the values they would have when exiting at the
+            // current stop
+            let mut route_bag: Vec<(RouteLabel, Vec<u64>)> = Vec::new();
+
+            for stop_sequence in start_sequence..route.number_of_stops {
+                let trip_stop = timetable.get_route_stop(&route, stop_sequence);
+                // Exit the trips of the route bag at this stop
+                for (route_label, values) in route_bag.iter_mut() {
+                    let label = ride_label(
+                        route_label,
+                        route_index,
+                        trip_stop,
+                        stop_sequence,
+                        k,
+                        criteria,
+                        &labels,
+                        timetable,
+                    );
+                    values.clone_from(&label.values);
+
+                    // Only exit where the feed allows passengers to be dropped off
+                    let trip = timetable.get_trip(&route, route_label.trip_number);
+                    if !trip.get(stop_sequence).can_exit_with(options) {
+                        continue;
+                    }
+
+                    if is_dominated(best_by_stop.get(&trip_stop), &labels, &label)
+                        || is_dominated(best_by_stop.get(&target), &labels, &label)
+                    {
+                        continue;
+                    }
+
+                    labels.push(label);
+                    let label_index = labels.len() - 1;
+                    merge(
+                        current_round_bags.entry(trip_stop).or_default(),
+                        &labels,
+                        label_index,
+                    );
+                    merge(
+                        best_by_stop.entry(trip_stop).or_default(),
+                        &labels,
+                        label_index,
+                    );
+                    marked_stops.insert(trip_stop);
+                }
+
+                // Board the earliest trip with each label reaching this stop in the previous round
+                let Some(last_round_bag) = last_round_bags.get(&trip_stop) else {
+                    continue;
+                };
+
+                for &parent in last_round_bag {
+                    let parent_label = &labels[parent];
+                    // Changing from another vehicle takes time
+                    let ready_to_board = match parent_label.reached {
+                        Reached::Ride { .. } => {
+                            parent_label.arrival + timetable.change_time(trip_stop)
+                        }
+                        Reached::Source | Reached::Walk { .. } => parent_label.arrival,
+                    };
+
+                    let Some((trip_number, _)) = timetable.get_earliest_departing_trip(
+                        &route,
+                        &stop_sequence,
+                        &ready_to_board,
+                        options,
+                    ) else {
+                        continue;
+                    };
+
+                    let candidate = RouteLabel {
+                        parent,
+                        trip_number,
+                        boarded_at_stop: trip_stop,
+                        boarded_sequence: stop_sequence,
+                    };
+                    let values = ride_label(
+                        &candidate,
+                        route_index,
+                        trip_stop,
+                        stop_sequence,
+                        k,
+                        criteria,
+                        &labels,
+                        timetable,
+                    )
+                    .values;
+
+                    // Trips on a route don't overtake each other, so an earlier trip arrives
+                    // earlier at every following stop
+                    let route_label_dominates =
+                        |trip_number: usize,
+                         values: &[u64],
+                         other_trip_number: usize,
+                         other_values: &[u64]| {
+                            trip_number <= other_trip_number
+                                && values
+                                    .iter()
+                                    .zip(other_values)
+                                    .all(|(value, other)| value <= other)
+                        };
+
+                    if route_bag.iter().any(|(route_label, existing_values)| {
+                        route_label_dominates(
+                            route_label.trip_number,
+                            existing_values,
+                            candidate.trip_number,
+                            &values,
+                        )
+                    }) {
+                        continue;
+                    }
+
+                    route_bag.retain(|(route_label, existing_values)| {
+                        !route_label_dominates(
+                            candidate.trip_number,
+                            &values,
+                            route_label.trip_number,
+                            existing_values,
+                        )
+                    });
+                    route_bag.push((candidate, values));
+                }
+            }
+        }
+
+        // Look at foot-paths
+        let mut new_marks = HashSet::new();
+        for &p in &marked_stops {
+            let bag = current_round_bags.get(&p).cloned().unwrap_or_default();
+
+            for transfer in timetable.get_transfers(p) {
+                if !transfer.can_walk_with(options) {
+                    continue;
+                }
+
+                for &parent in &bag {
+                    let walk = Walk {
+                        from_stop: p,
+                        to_stop: transfer.target,
+                        time: transfer.time,
+                    };
+                    let parent_label = &labels[parent];
+                    let label = Label {
+                        arrival: parent_label.arrival + transfer.time,
+                        values: criteria
+                            .iter()
+                            .zip(&parent_label.values)
+                            .map(|(criterion, value)| criterion.walk(*value, &walk))
+                            .collect(),
+                        reached: Reached::Walk {
+                            parent,
+                            from_stop: p,
+                        },
+                    };
+
+                    if is_dominated(best_by_stop.get(&transfer.target), &labels, &label)
+                        || is_dominated(best_by_stop.get(&target), &labels, &label)
+                    {
+                        continue;
+                    }
+
+                    labels.push(label);
+                    let label_index = labels.len() - 1;
+                    merge(
+                        current_round_bags.entry(transfer.target).or_default(),
+                        &labels,
+                        label_index,
+                    );
+                    merge(
+                        best_by_stop.entry(transfer.target).or_default(),
+                        &labels,
+                        label_index,
+                    );
+                    new_marks.insert(transfer.target);
+                }
+            }
+        }
+
+        marked_stops.extend(new_marks);
+        bags_by_round.push(current_round_bags);
+    }
+
+    let mut journeys: Vec<McJourney> = best_by_stop
+        .remove(&target)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|label| *label != 0)
+        .map(|label| reconstruct(target, label, &labels))
+        .collect();
+
+    journeys.sort_by(|journey, other| {
+        journey
+            .journey
+            .arrival()
+            .cmp(&other.journey.arrival())
+            .then(journey.values.cmp(&other.values))
+    });
+    journeys
+}
+
+/// Creates the label for exiting the trip of the route label at the given stop
+#[allow(clippy::too_many_arguments)]
+fn ride_label(
+    route_label: &RouteLabel,
+    route_index: usize,
+    exited_at_stop: usize,
+    exited_sequence: usize,
+    round: usize,
+    criteria: &[&dyn Criterion],
+    labels: &[Label],
+    timetable: &impl TimetableView,
+) -> Label {
+    let trip = timetable.get_trip(&timetable.route(route_index), route_label.trip_number);
+    let departure = trip.get(route_label.boarded_sequence).departure_time;
+    let arrival = trip.get(exited_sequence).arrival_time;
+
+    let ride = Ride {
+        route: route_index,
+        trip_number: route_label.trip_number,
+        boarded_at_stop: route_label.boarded_at_stop,
+        exited_at_stop,
+        departure,
+        arrival,
+        trips_before: round - 1,
+    };
+
+    let parent = &labels[route_label.parent];
+    Label {
+        arrival,
+        values: criteria
+            .iter()
+            .zip(&parent.values)
+            .map(|(criterion, value)| criterion.ride(*value, &ride))
+            .collect(),
+        reached: Reached::Ride {
+            parent: route_label.parent,
+            route: route_index,
+            trip_number: route_label.trip_number,
+            boarded_at_stop: route_label.boarded_at_stop,
+            departure,
+        },
+    }
+}
+
+/// Follows the parents of the label back to the source
+fn reconstruct(target: usize, label_index: usize, labels: &[Label]) -> McJourney {
+    let mut legs = Vec::new();
+    let mut stop = target;
+    let mut current = label_index;
+
+    loop {
+        let label = &labels[current];
+        match label.reached {
+            Reached::Source => break,
+            Reached::Ride {
+                parent,
+                route,
+                trip_number,
+                boarded_at_stop,
+                departure,
+            } => {
+                legs.push(Leg::Transit {
+                    route,
+                    trip_number,
+                    boarded_at_stop,
+                    exited_at_stop: stop,
+                    departure,
+                    arrival: label.arrival,
+                });
+                stop = boarded_at_stop;
+                current = parent;
+            }
+            Reached::Walk { parent, from_stop } => {
+                legs.push(Leg::FootPath {
+                    from_stop,
+                    to_stop: stop,
+                    departure: labels[parent].arrival,
+                    arrival: label.arrival,
+                });
+                stop = from_stop;
+                current = parent;
+            }
+        }
+    }
+
+    legs.reverse();
+
+    McJourney {
+        journey: Journey { legs },
+        values: labels[label_index].values.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mc::{mc_raptor, RouteFare, VehicleChanges, WalkingTime};
+    use crate::shared::Timetable;
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{QueryOptions, Time};
+
+    #[test]
+    fn keeps_slower_journey_with_less_walking() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            3,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![0, 2],
+                    trips: vec![vec![100, 600]],
+                },
+            ],
+            vec![(1, 2, 300)],
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = mc_raptor(
+            0,
+            2,
+            &Time::from(0),
+            &[&WalkingTime],
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        let summary: Vec<(Time, Vec<u64>)> = journeys
+            .iter()
+            .map(|journey| (journey.journey.arrival(), journey.values.clone()))
+            .collect();
+        assert_eq!(
+            vec![(Time::from(500), vec![300]), (Time::from(600), vec![0])],
+            summary
+        );
+    }
+
+    #[test]
+    fn trades_fare_against_vehicle_changes() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            3,
+            vec![
+                // Cheap but with a change
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![300, 400]],
+                },
+                // Expensive but direct and arriving at the same time
+                TestRoute {
+                    stops: vec![0, 2],
+                    trips: vec![vec![100, 400]],
+                },
+                // Expensive and slow which is dominated
+                TestRoute {
+                    stops: vec![0, 2],
+                    trips: vec![vec![100, 500]],
+                },
+            ],
+            Vec::new(),
+        );
+        let fare = RouteFare {
+            fare_by_route: vec![100, 100, 500, 500],
+        };
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = mc_raptor(
+            0,
+            2,
+            &Time::from(0),
+            &[&fare, &VehicleChanges],
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        let values: Vec<Vec<u64>> = journeys
+            .iter()
+            .map(|journey| journey.values.clone())
+            .collect();
+        assert_eq!(vec![vec![200, 1], vec![500, 0]], values);
+        assert!(journeys
+            .iter()
+            .all(|journey| journey.journey.arrival() == Time::from(400)));
+    }
+
+    #[test]
+    fn avoids_inaccessible_trips_and_foot_paths_for_wheelchair_users() {
+        // Arrange
+        let (mut routes_data, mut stops_data) = build_network(
+            4,
+            vec![
+                TestRoute {
+                    stops: vec![0, 3],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 150]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![300, 350]],
+                },
+            ],
+            vec![(1, 2, 60), (2, 3, 50)],
+        );
+        // The direct trip can't be boarded and the walk between the trips has stairs
+        routes_data.stop_times[0].wheelchair_accessible = false;
+        stops_data.transfers[0].wheelchair_accessible = false;
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = mc_raptor(
+            0,
+            3,
+            &Time::from(0),
+            &[&VehicleChanges],
+            &timetable,
+            &QueryOptions {
+                wheelchair_accessible: true,
+            },
+        );
+
+        // Assert
+        let summary: Vec<(Time, Vec<u64>)> = journeys
+            .iter()
+            .map(|journey| (journey.journey.arrival(), journey.values.clone()))
+            .collect();
+        assert_eq!(vec![(Time::from(400), vec![1])], summary);
+    }
+}
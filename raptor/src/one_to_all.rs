@@ -0,0 +1,115 @@
+use crate::shared::TimetableView;
+use crate::{QueryOptions, Search, Time};
+
+/// The earliest arrival at a stop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arrival {
+    pub time: Time,
+    /// The amount of trips needed to arrive. Zero for the stops the search started at
+    pub trips: usize,
+}
+
+impl Arrival {
+    /// The amount of changes between trips needed to arrive. Zero for walking only
+    pub fn transfers(&self) -> usize {
+        self.trips.saturating_sub(1)
+    }
+}
+
+/// One-to-all RAPTOR query that finds the earliest arrival at every stop reachable from the access
+/// stops. As there is no target, nothing is pruned by the arrival at a target.
+/// Access stops are given as pairs of stop and walking time like for
+/// [crate::access::access_egress_raptor]. For a single stop use a walking time of zero.
+///
+/// Only takes the trips and foot-paths the options allow.
+///
+/// Returns the earliest arrival by stop index or none if the stop can not be reached.
+/// If the earliest arrival can be reached in multiple rounds, the one with the least trips is used.
+pub fn one_to_all_raptor(
+    access: &[(usize, Time)],
+    departure: &Time,
+    timetable: &impl TimetableView,
+    options: &QueryOptions,
+) -> Vec<Option<Arrival>> {
+    let mut search = Search {
+        options: *options,
+        ..Search::default()
+    };
+    search.run(access, *departure, &[], timetable);
+
+    let mut arrivals: Vec<Option<Arrival>> = vec![None; timetable.stop_count()];
+    for (trips, labels) in search.labels_by_round.iter().enumerate() {
+        for (&stop, &time) in labels {
+            let arrival = &mut arrivals[stop];
+            if arrival.is_none_or(|arrival| time < arrival.time) {
+                *arrival = Some(Arrival { time, trips });
+            }
+        }
+    }
+
+    arrivals
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::one_to_all::{one_to_all_raptor, Arrival};
+    use crate::shared::Timetable;
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{QueryOptions, Time};
+
+    #[test]
+    fn finds_earliest_arrival_at_every_stop() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            5,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1, 2],
+                    trips: vec![vec![100, 200, 900]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![250, 400]],
+                },
+            ],
+            vec![(2, 3, 60)],
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let arrivals = one_to_all_raptor(
+            &[(0, Time::from(0))],
+            &Time::from(50),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(
+            vec![
+                Some(Arrival {
+                    time: Time::from(50),
+                    trips: 0,
+                }),
+                Some(Arrival {
+                    time: Time::from(200),
+                    trips: 1,
+                }),
+                // Changing is faster than staying on the first trip
+                Some(Arrival {
+                    time: Time::from(400),
+                    trips: 2,
+                }),
+                Some(Arrival {
+                    time: Time::from(460),
+                    trips: 2,
+                }),
+                None,
+            ],
+            arrivals
+        );
+    }
+}
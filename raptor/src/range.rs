@@ -0,0 +1,304 @@
+use crate::journey::{reconstruct_journey, Journey};
+use crate::shared::{Route, TimetableLookup, TimetableView};
+use crate::{QueryOptions, Search, Time};
+
+/// Range RAPTOR (rRAPTOR) profile query that finds all journeys departing from the source within
+/// the departure window.
+/// RAPTOR is run for every distinct departure of a trip at the source within the window, starting
+/// with the latest. The labels are kept between runs as everything reachable with a later departure
+/// is also reachable when departing earlier. Only takes the trips and foot-paths the options allow.
+///
+/// Returns the Pareto set of journeys for departure (later is better), arrival (earlier is better)
+/// and transfers (fewer is better) ordered by departure.
+pub fn range_raptor(
+    source: usize,
+    target: usize,
+    earliest_departure: &Time,
+    latest_departure: &Time,
+    timetable: &impl TimetableView,
+    options: &QueryOptions,
+) -> Vec<Journey> {
+    let departures = get_departures(
+        source,
+        earliest_departure,
+        latest_departure,
+        timetable,
+        options,
+    );
+
+    let mut search = Search {
+        options: *options,
+        ..Search::default()
+    };
+    let mut journeys: Vec<Journey> = Vec::new();
+
+    for departure in departures.into_iter().rev() {
+        search.run(
+            &[(source, Time::from(0))],
+            departure,
+            &[(target, Time::from(0))],
+            timetable,
+        );
+
+        for round in 1..=search.connections_by_round.len() {
+            if !search.connections_by_round[round - 1].contains_key(&target) {
+                continue;
+            }
+
+            let journey =
+                reconstruct_journey(target, round, &search.connections_by_round, timetable);
+
+            if let Some(journey) = journey {
+                if !journeys.contains(&journey) {
+                    journeys.push(journey);
+                }
+            }
+        }
+    }
+
+    pareto_set(journeys)
+}
+
+/// Get the distinct departures of all trips the options allow at the source stop within the window
+/// in ascending order, including the ones catching trips of routes with a headway
+fn get_departures(
+    source: usize,
+    earliest_departure: &Time,
+    latest_departure: &Time,
+    timetable: &impl TimetableView,
+    options: &QueryOptions,
+) -> Vec<Time> {
+    let mut departures = Vec::new();
+    let is_in_window =
+        |departure: &Time| earliest_departure <= departure && departure <= latest_departure;
+
+    // A route can serve the source more than once
+    for (route_index, stop_sequence) in timetable.get_route_positions(&source) {
+        let route = timetable.route(route_index);
+        if route.headway.is_some() {
+            let headway_departures =
+                get_headway_departures(timetable, &route, stop_sequence, options);
+            departures.extend(headway_departures.into_iter().filter(is_in_window));
+            continue;
+        }
+
+        for trip_number in 0..route.number_of_trips {
+            let stop_time = timetable.get_trip(&route, trip_number).get(stop_sequence);
+            if !stop_time.can_board_with(options) {
+                continue;
+            }
+
+            let departure = stop_time.departure_time;
+            if is_in_window(&departure) {
+                departures.push(departure);
+            }
+        }
+    }
+
+    departures.sort();
+    departures.dedup();
+    departures
+}
+
+/// Get the departures from the stop with the sequence on the route with a headway that catch each of
+/// its trips. Riders are assumed to wait a full headway for trips without exact times, so they need
+/// to be at the stop a headway before the trip departs
+fn get_headway_departures(
+    timetable: &impl TimetableView,
+    route: &Route,
+    stop_sequence: usize,
+    options: &QueryOptions,
+) -> Vec<Time> {
+    // The template trip departs the first stop at 0
+    let stop_time = timetable.get_trip(route, 0).get(stop_sequence);
+    let Some(headway) = route.headway.filter(|_| stop_time.can_board_with(options)) else {
+        return Vec::new();
+    };
+    let (Some(start), Some(end), Some(interval)) = (
+        headway.start.seconds(),
+        headway.end.seconds(),
+        headway.headway.seconds(),
+    ) else {
+        return Vec::new();
+    };
+    if interval == 0 {
+        return Vec::new();
+    }
+
+    (start..end)
+        .step_by(interval as usize)
+        .filter_map(|trip_departure| {
+            let departure = Time::from(trip_departure) + stop_time.departure_time;
+            departure.checked_sub(headway.headway)
+        })
+        .collect()
+}
+
+/// Whether a journey is at least as good as the other journey in every criterion
+fn dominates(journey: &Journey, other: &Journey) -> bool {
+    journey.departure() >= other.departure()
+        && journey.arrival() <= other.arrival()
+        && journey.transfers() <= other.transfers()
+}
+
+/// Removes all journeys that are dominated by another journey
+fn pareto_set(mut journeys: Vec<Journey>) -> Vec<Journey> {
+    // Any journey dominating another journey is sorted before it
+    journeys.sort_by(|journey, other| {
+        other
+            .departure()
+            .cmp(&journey.departure())
+            .then(journey.arrival().cmp(&other.arrival()))
+            .then(journey.transfers().cmp(&other.transfers()))
+    });
+
+    let mut pareto_set: Vec<Journey> = Vec::with_capacity(journeys.len());
+    for journey in journeys {
+        if !pareto_set.iter().any(|kept| dominates(kept, &journey)) {
+            pareto_set.push(journey);
+        }
+    }
+
+    pareto_set.reverse();
+    pareto_set
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::range::range_raptor;
+    use crate::shared::{Headway, Timetable};
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{QueryOptions, Time};
+
+    #[test]
+    fn finds_pareto_set_in_departure_window() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            3,
+            vec![
+                TestRoute {
+                    stops: vec![0, 2],
+                    trips: vec![vec![100, 500], vec![300, 700]],
+                },
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![200, 250]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![260, 400]],
+                },
+                // Dominated by the first route
+                TestRoute {
+                    stops: vec![0, 2],
+                    trips: vec![vec![100, 800]],
+                },
+            ],
+            Vec::new(),
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = range_raptor(
+            0,
+            2,
+            &Time::from(0),
+            &Time::from(400),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        let summary: Vec<(Time, Time, usize)> = journeys
+            .iter()
+            .map(|journey| (journey.departure(), journey.arrival(), journey.transfers()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Time::from(100), Time::from(500), 0),
+                (Time::from(200), Time::from(400), 1),
+                (Time::from(300), Time::from(700), 0),
+            ],
+            summary
+        );
+    }
+
+    #[test]
+    fn ignores_departures_outside_window() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            2,
+            vec![TestRoute {
+                stops: vec![0, 1],
+                trips: vec![vec![100, 200], vec![300, 400], vec![500, 600]],
+            }],
+            Vec::new(),
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = range_raptor(
+            0,
+            1,
+            &Time::from(150),
+            &Time::from(450),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(1, journeys.len());
+        assert_eq!(Time::from(300), journeys[0].departure());
+    }
+
+    #[test]
+    fn finds_departures_of_route_with_headway() {
+        // Arrange
+        let (mut routes_data, stops_data) = build_network(
+            2,
+            vec![TestRoute {
+                stops: vec![0, 1],
+                trips: vec![vec![0, 300]],
+            }],
+            Vec::new(),
+        );
+        routes_data.routes[0].headway = Some(Headway {
+            start: Time::from(600),
+            end: Time::from(1800),
+            headway: Time::from(600),
+        });
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = range_raptor(
+            0,
+            1,
+            &Time::from(0),
+            &Time::from(1000),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        let summary: Vec<(Time, Time)> = journeys
+            .iter()
+            .map(|journey| (journey.departure(), journey.arrival()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Time::from(600), Time::from(900)),
+                (Time::from(1200), Time::from(1500)),
+            ],
+            summary
+        );
+    }
+}
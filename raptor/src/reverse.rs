@@ -0,0 +1,455 @@
+use crate::journey::{get_transit_leg, Journey, Leg};
+use crate::shared::{TimetableLookup, TimetableView, TripTimes};
+use crate::{Connection, QueryOptions, Time};
+use std::collections::{HashMap, HashSet};
+
+/// The latest departure from the origin by walking to any of the access stops
+fn get_origin_departure(labels: &HashMap<usize, Time>, access: &[(usize, Time)]) -> Option<Time> {
+    access
+        .iter()
+        .filter_map(|(stop, walking_time)| labels.get(stop)?.checked_sub(*walking_time))
+        .max()
+}
+
+/// Whether the departure is later than the latest known departure if there is any
+fn is_later(departure: Time, latest: Option<Time>) -> bool {
+    latest.is_none_or(|latest| departure > latest)
+}
+
+/// Reverse RAPTOR for arrive-by queries that finds the latest departure from the origin to still
+/// arrive at the destination before the given arrival.
+/// Routes are scanned in reverse stop order, exiting the latest trip that still arrives in time and
+/// foot-paths are relaxed backwards.
+/// Access and egress stops are given as pairs of stop and walking time like for
+/// [crate::access::access_egress_raptor]. For a single stop use a walking time of zero.
+///
+/// Returns one journey for each round that improved the departure from the origin, so the first
+/// journey is the one with the least transfers and every following journey departs later but has
+/// more transfers.
+/// Trip continuations are not followed, so riders change between trips of the same vehicle like
+/// between any other trips.
+pub fn reverse_raptor(
+    access: &[(usize, Time)],
+    egress: &[(usize, Time)],
+    arrival: &Time,
+    timetable: &impl TimetableView,
+    options: &QueryOptions,
+) -> Vec<Journey> {
+    // Foot-paths by the stop they lead to, as (source stop, transfer index relative to the source)
+    let mut incoming_transfers: Vec<Vec<(usize, usize)>> = vec![Vec::new(); timetable.stop_count()];
+    for source in 0..timetable.stop_count() {
+        for (transfer_index, transfer) in timetable.get_transfers(source).enumerate() {
+            if transfer.can_walk_with(options) {
+                incoming_transfers[transfer.target].push((source, transfer_index));
+            }
+        }
+    }
+
+    // For each round the latest departure by stop that still arrives in time
+    let mut labels_by_round: Vec<HashMap<usize, Time>> = vec![HashMap::new()];
+    // Connections to reconstruct journeys. A connection starts at the stop it is stored for
+    let mut connections_by_round: Vec<HashMap<usize, Connection>> = Vec::new();
+    // The latest departure for any stop without caring about the round
+    let mut best_by_stop: HashMap<usize, Time> = HashMap::new();
+    let mut marked_stops = HashSet::new();
+
+    for &(stop, walking_time) in egress {
+        let Some(departure) = arrival.checked_sub(walking_time) else {
+            continue;
+        };
+
+        if is_later(departure, labels_by_round[0].get(&stop).copied()) {
+            labels_by_round[0].insert(stop, departure);
+            best_by_stop.insert(stop, departure);
+            marked_stops.insert(stop);
+        }
+    }
+
+    let mut queue = Vec::<(usize, usize)>::new();
+    let mut k = 0usize;
+    while !marked_stops.is_empty() {
+        k += 1;
+        labels_by_round.push(HashMap::new());
+        connections_by_round.push(HashMap::new());
+
+        let (previous_rounds, next_rounds) = labels_by_round.split_at_mut(k);
+        let last_round_labels = &previous_rounds[k - 1];
+        let current_round_labels = &mut next_rounds[0];
+        let (previous_connections, next_connections) = connections_by_round.split_at_mut(k - 1);
+        let last_round_connections = previous_connections.last();
+        let connection_by_stop = &mut next_connections[0];
+
+        // Routes with the position of the latest marked stop on them, as routes are scanned backwards
+        queue.clear();
+        for &p in &marked_stops {
+            for (route, sequence) in timetable.get_route_positions(&p) {
+                if let Some(p_other_index) = queue
+                    .iter()
+                    .position(|(queued_route, _sequence_other)| *queued_route == route)
+                {
+                    if sequence > queue[p_other_index].1 {
+                        queue[p_other_index] = (route, sequence);
+                    }
+                    continue;
+                }
+
+                queue.push((route, sequence));
+            }
+        }
+
+        marked_stops.clear();
+
+        for &(route_index, start_sequence) in &queue {
+            let route = timetable.route(route_index);
+            // The trip with its stop times and the stop it is exited at
+            let mut current_trip: Option<(usize, TripTimes<'_, _>, usize)> = None;
+
+            for stop_sequence in (0..=start_sequence).rev() {
+                let trip_stop = timetable.get_route_stop(&route, stop_sequence);
+
+                if let Some((trip_number, trip_times, exited_at_stop)) = current_trip {
+                    let departure_time = trip_times.get(stop_sequence).departure_time;
+                    // Target pruning with the origin and local pruning with the stop
+                    let latest = best_by_stop
+                        .get(&trip_stop)
+                        .copied()
+                        .max(get_origin_departure(&best_by_stop, access));
+
+                    // Only board where the feed allows passengers to be picked up
+                    let can_board = trip_times.get(stop_sequence).can_board_with(options);
+                    if can_board && is_later(departure_time, latest) {
+                        current_round_labels.insert(trip_stop, departure_time);
+                        best_by_stop.insert(trip_stop, departure_time);
+                        let connection = Connection::Connection {
+                            route: route_index,
+                            trip_number,
+                            boarded_at_stop: trip_stop,
+                            exited_at_stop,
+                        };
+                        connection_by_stop.insert(trip_stop, connection);
+                        marked_stops.insert(trip_stop);
+                    }
+                }
+
+                // Can we exit a later trip?
+                let Some(mut previous_departure) = last_round_labels.get(&trip_stop).copied()
+                else {
+                    continue;
+                };
+
+                // Changing to another vehicle takes time. Walking already includes the time to get
+                // to the vehicle
+                let is_left_by_trip = last_round_connections
+                    .and_then(|connections| connections.get(&trip_stop))
+                    .is_some_and(|connection| matches!(connection, Connection::Connection { .. }));
+                if is_left_by_trip {
+                    let Some(departure) =
+                        previous_departure.checked_sub(timetable.change_time(trip_stop))
+                    else {
+                        continue;
+                    };
+                    previous_departure = departure;
+                }
+
+                let can_exit_later = current_trip.is_none_or(|(_, trip, _)| {
+                    trip.get(stop_sequence).arrival_time <= previous_departure
+                });
+                if can_exit_later {
+                    let later_trip = timetable
+                        .get_latest_arriving_trip(
+                            &route,
+                            &stop_sequence,
+                            &previous_departure,
+                            options,
+                        )
+                        .filter(|(trip_number, _)| {
+                            current_trip.is_none_or(|(current, ..)| trip_number >= &current)
+                        });
+                    if let Some((trip_number, trip_times)) = later_trip {
+                        current_trip = Some((trip_number, trip_times, trip_stop));
+                    }
+                }
+            }
+        }
+
+        // Walk backwards to the stops foot-paths to the marked stops start from
+        let mut new_marks = HashSet::new();
+        for &q in &marked_stops {
+            let Some(departure_at_q) = current_round_labels.get(&q).copied() else {
+                continue;
+            };
+
+            for &(source, transfer_index) in &incoming_transfers[q] {
+                let transfer = timetable.get_transfer(source, transfer_index);
+                let Some(departure_by_foot) = departure_at_q.checked_sub(transfer.time) else {
+                    continue;
+                };
+
+                if is_later(
+                    departure_by_foot,
+                    current_round_labels.get(&source).copied(),
+                ) {
+                    current_round_labels.insert(source, departure_by_foot);
+                    let connection = Connection::FootPath {
+                        source,
+                        transfer: transfer_index,
+                    };
+                    connection_by_stop.insert(source, connection);
+                    new_marks.insert(source);
+                }
+            }
+        }
+
+        marked_stops.extend(new_marks);
+    }
+
+    let mut journeys: Vec<Journey> = Vec::new();
+    for round in 1..=connections_by_round.len() {
+        // The access stop reached in this round with the latest departure from the origin
+        let best_access = access
+            .iter()
+            .filter(|(stop, _)| connections_by_round[round - 1].contains_key(stop))
+            .filter_map(|(stop, walking_time)| {
+                let departure = *labels_by_round[round].get(stop)?;
+                Some((*stop, departure, departure.checked_sub(*walking_time)?))
+            })
+            .max_by_key(|(_, _, origin_departure)| *origin_departure);
+
+        let Some((access_stop, access_arrival, origin_departure)) = best_access else {
+            continue;
+        };
+
+        let is_improvement = journeys
+            .last()
+            .is_none_or(|previous| origin_departure > previous.departure());
+        if !is_improvement {
+            continue;
+        }
+
+        let Some(mut journey) =
+            reconstruct_journey(access_stop, round, &connections_by_round, timetable)
+        else {
+            continue;
+        };
+
+        // Starting or ending at a stop needs no walk
+        if origin_departure != access_arrival {
+            journey.legs.insert(
+                0,
+                Leg::Access {
+                    to_stop: access_stop,
+                    departure: origin_departure,
+                    arrival: access_arrival,
+                },
+            );
+        }
+
+        let Some(Leg::Transit {
+            exited_at_stop,
+            arrival: exit_arrival,
+            ..
+        }) = journey.legs.last().cloned()
+        else {
+            continue;
+        };
+
+        let egress_time = egress
+            .iter()
+            .filter(|(stop, _)| *stop == exited_at_stop)
+            .map(|(_, walking_time)| *walking_time)
+            .min();
+        if let Some(egress_time) = egress_time {
+            if egress_time != Time::from(0) {
+                journey.legs.push(Leg::Egress {
+                    from_stop: exited_at_stop,
+                    departure: exit_arrival,
+                    arrival: exit_arrival + egress_time,
+                });
+            }
+        }
+
+        journeys.push(journey);
+    }
+
+    journeys
+}
+
+/// Follows the connections from the stop the journey starts at in the given round towards the
+/// destination. Legs are already in travel order as reverse connections point forward in time.
+fn reconstruct_journey(
+    start: usize,
+    mut round: usize,
+    connections_by_round: &[HashMap<usize, Connection>],
+    timetable: &impl TimetableView,
+) -> Option<Journey> {
+    let mut legs = Vec::new();
+    let mut stop = start;
+
+    while round > 0 {
+        match connections_by_round[round - 1].get(&stop)? {
+            Connection::Connection {
+                route,
+                trip_number,
+                boarded_at_stop,
+                exited_at_stop,
+            } => {
+                legs.push(get_transit_leg(
+                    *route,
+                    *trip_number,
+                    *boarded_at_stop,
+                    *exited_at_stop,
+                    timetable,
+                )?);
+
+                stop = *exited_at_stop;
+                // The exited stop was reached in the previous round
+                round -= 1;
+            }
+            Connection::FootPath { source, transfer } => {
+                let transfer = timetable.get_transfer(*source, *transfer);
+                // Times are filled in when the following leg is known
+                legs.push(Leg::FootPath {
+                    from_stop: *source,
+                    to_stop: transfer.target,
+                    departure: Time::INFINITE,
+                    arrival: transfer.time,
+                });
+
+                // Foot-paths are relaxed in the same round as the trip that leaves their target
+                stop = transfer.target;
+            }
+            // Not recorded by the reverse search as it doesn't follow trip continuations
+            Connection::Continuation { .. } => return None,
+        }
+    }
+
+    // Foot-paths end just in time for the next leg
+    let mut next_departure = Time::INFINITE;
+    for leg in legs.iter_mut().rev() {
+        if let Leg::FootPath {
+            departure, arrival, ..
+        } = leg
+        {
+            // Arrival holds the walking time until here
+            let walking_time = *arrival;
+            *departure = next_departure.checked_sub(walking_time)?;
+            *arrival = next_departure;
+        }
+        next_departure = leg.departure();
+    }
+
+    Some(Journey { legs })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journey::Leg;
+    use crate::reverse::reverse_raptor;
+    use crate::shared::Timetable;
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{QueryOptions, Time};
+
+    #[test]
+    fn finds_latest_departure_per_round() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            4,
+            vec![
+                TestRoute {
+                    stops: vec![0, 3],
+                    trips: vec![vec![100, 500], vec![200, 700]],
+                },
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![300, 400]],
+                },
+                TestRoute {
+                    stops: vec![2, 3],
+                    trips: vec![vec![450, 550], vec![600, 650]],
+                },
+            ],
+            vec![(1, 2, 30)],
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = reverse_raptor(
+            &[(0, Time::from(0))],
+            &[(3, Time::from(0))],
+            &Time::from(600),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(2, journeys.len());
+        assert_eq!(Time::from(100), journeys[0].departure());
+        assert_eq!(0, journeys[0].transfers());
+        assert_eq!(Time::from(300), journeys[1].departure());
+        assert_eq!(Time::from(550), journeys[1].arrival());
+        assert_eq!(
+            Leg::FootPath {
+                from_stop: 1,
+                to_stop: 2,
+                departure: Time::from(420),
+                arrival: Time::from(450),
+            },
+            journeys[1].legs[1]
+        );
+    }
+
+    #[test]
+    fn walks_from_origin_and_to_destination() {
+        // Arrange
+        let (routes_data, stops_data) = build_network(
+            2,
+            vec![TestRoute {
+                stops: vec![0, 1],
+                trips: vec![vec![100, 200], vec![300, 400]],
+            }],
+            Vec::new(),
+        );
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+
+        // Act
+        let journeys = reverse_raptor(
+            &[(0, Time::from(60))],
+            &[(1, Time::from(120))],
+            &Time::from(500),
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        assert_eq!(1, journeys.len());
+        assert_eq!(
+            vec![
+                Leg::Access {
+                    to_stop: 0,
+                    departure: Time::from(40),
+                    arrival: Time::from(100),
+                },
+                Leg::Transit {
+                    route: 0,
+                    trip_number: 0,
+                    boarded_at_stop: 0,
+                    exited_at_stop: 1,
+                    departure: Time::from(100),
+                    arrival: Time::from(200),
+                },
+                Leg::Egress {
+                    from_stop: 1,
+                    departure: Time::from(200),
+                    arrival: Time::from(320),
+                },
+            ],
+            journeys[0].legs
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::shared::{TimetableLookup, TimetableView, TripTimes};
+use crate::{Connection, QueryOptions, Time};
+
+/// Where a route scan reads the labels of the previous round and writes the improved labels of the
+/// current round. [crate::Search] keeps them in maps and [crate::workspace::RaptorWorkspace] in
+/// vectors indexed by stop.
+pub(crate) trait RoundLabels {
+    /// The arrival at the stop in the previous round
+    fn previous_arrival(&self, stop: usize) -> Time;
+
+    /// Whether the stop was reached by a trip in the previous round, so boarding needs the time to
+    /// change vehicles
+    fn is_reached_by_trip(&self, stop: usize) -> bool;
+
+    /// Arriving at the stop at or after this time is no improvement
+    fn exit_bound(&self, stop: usize) -> Time;
+
+    /// Sets the arrival at the stop in the current round with the connection reaching it and marks
+    /// the stop as improved
+    fn improve(&mut self, stop: usize, arrival: Time, connection: Connection);
+}
+
+/// Scans the route starting at the stop with the sequence as one step of a RAPTOR round. Boards the
+/// earliest trip that can be caught at each stop and exits it wherever that improves the arrival.
+/// Riders stay seated on trips continuing as trips of other routes and the continued trips are
+/// scanned from their first stop.
+pub(crate) fn scan_route(
+    labels: &mut impl RoundLabels,
+    queued_route: usize,
+    queued_sequence: usize,
+    timetable: &impl TimetableView,
+    options: &QueryOptions,
+) {
+    let (mut route_index, mut start_sequence) = (queued_route, queued_sequence);
+    let mut current_trip: Option<(usize, TripTimes<'_, _>, usize)> = None;
+    // The route and trip that were boarded when riding on as the trip they continue as
+    let mut continued_from: Option<(usize, usize)> = None;
+    // Each continuation is followed at most once, so data with cycles can't loop forever
+    let mut continuations_left = timetable.continuation_count();
+
+    loop {
+        // Go through each stop of route starting with p
+        let route = timetable.route(route_index);
+
+        for stop_sequence in start_sequence..route.number_of_stops {
+            // Stop (index) of the stop in the trip we traverse
+            let trip_stop = timetable.get_route_stop(&route, stop_sequence);
+
+            if let Some((trip_number, trip_times, boarded_at_stop)) = current_trip {
+                // Arrival time for the current stop on the current trip for the current route
+                let arrival_time = trip_times.get(stop_sequence).arrival_time;
+                // Only exit where the feed allows passengers to be dropped off
+                let can_exit = trip_times.get(stop_sequence).can_exit_with(options);
+                if can_exit && arrival_time < labels.exit_bound(trip_stop) {
+                    // Save connection to reconstruct journey
+                    let connection = match continued_from {
+                        None => Connection::Connection {
+                            route: route_index,
+                            trip_number,
+                            boarded_at_stop,
+                            exited_at_stop: trip_stop,
+                        },
+                        Some((boarded_route, boarded_trip_number)) => Connection::Continuation {
+                            boarded_route,
+                            boarded_trip_number,
+                            boarded_at_stop,
+                            route: route_index,
+                            trip_number,
+                            exited_at_stop: trip_stop,
+                        },
+                    };
+                    labels.improve(trip_stop, arrival_time, connection);
+                }
+            }
+
+            // Riders staying seated are already on the trip they continue as. The trips of the
+            // route they can board are scanned from the queue
+            if continued_from.is_some() {
+                continue;
+            }
+
+            // Can we catch an earlier trip?
+            let mut previous_arrival = labels.previous_arrival(trip_stop);
+            if previous_arrival == Time::INFINITE {
+                continue;
+            }
+
+            // Changing from another vehicle takes time. Walking from another stop already includes
+            // the time to get to the vehicle
+            if labels.is_reached_by_trip(trip_stop) {
+                previous_arrival = previous_arrival + timetable.change_time(trip_stop);
+            }
+
+            // Pseudo code example code uses departure but this is probably a typo as text uses
+            // arrival which makes more sense to my understanding of the algorithm
+            let arrival_time = current_trip
+                .map(|(_, trip, _)| trip.get(stop_sequence).arrival_time)
+                .unwrap_or(Time::INFINITE);
+
+            if previous_arrival <= arrival_time {
+                // Stops where boarding is not allowed can skip past the current trip
+                let earlier_trip = timetable
+                    .get_earliest_departing_trip(&route, &stop_sequence, &previous_arrival, options)
+                    .filter(|(trip_number, _)| {
+                        current_trip.is_none_or(|(current, ..)| trip_number <= &current)
+                    });
+                if let Some((trip_number, trip_times)) = earlier_trip {
+                    current_trip = Some((trip_number, trip_times, trip_stop));
+                }
+            }
+        }
+
+        // Stay seated if the trip at the end of the route continues as another trip
+        let Some((trip_number, trip_times, boarded_at_stop)) = current_trip else {
+            break;
+        };
+        let Some(continuation) = timetable.get_continuation(route_index, trip_number) else {
+            break;
+        };
+        if continuations_left == 0 {
+            break;
+        }
+        continuations_left -= 1;
+
+        let next_route = timetable.route(continuation.to_route);
+        let next_trip = timetable.get_trip(&next_route, continuation.to_trip_number);
+        // The vehicle can not depart before it arrived
+        let last_arrival = trip_times.get(route.number_of_stops - 1).arrival_time;
+        if next_trip.get(0).departure_time < last_arrival {
+            break;
+        }
+
+        continued_from.get_or_insert((route_index, trip_number));
+        current_trip = Some((continuation.to_trip_number, next_trip, boarded_at_stop));
+        route_index = continuation.to_route;
+        start_sequence = 0;
+    }
+}
@@ -1,11 +1,14 @@
+use crate::{QueryOptions, Time};
+use rkyv::{Archive, Deserialize, Serialize};
+use std::cmp::{max, min};
 use std::hash::{Hash, Hasher};
-use crate::Time;
+use std::ops::Range;
 
 /// A route or line in a transportation network. A route has multiple trips a day.
 /// In contrast to GTFS data a route has always the same sequence of stops in its trips.
 /// This means there is a separate route for every trip in GTFS where the sequence of stops or
 /// direction is not the same
-#[derive(Clone)]
+#[derive(Clone, Copy, Archive, Serialize, Deserialize)]
 pub struct Route {
     /// Number of trips in a route. You can get the length of the block in StopTimes that represent
     /// all trips of this route by multiplying this with number_of_stops
@@ -19,15 +22,52 @@ pub struct Route {
 
     /// Pointer to the index that starts the first block of StopTimes for the first trip
     pub stop_times_start_index: usize,
+
+    /// Set if the trips of the route run in intervals instead of at scheduled times. The route
+    /// then has a single template trip
+    pub headway: Option<Headway>,
+}
+
+/// Trips that run every headway between start and end without exact departure times like GTFS
+/// frequencies with exact times set to 0. The times of the template trip of the route are relative
+/// to its departure at the first stop. The trip number of a trip is its departure at the first
+/// stop in seconds, as the trips are not stored
+#[derive(Clone, Copy, Archive, Serialize, Deserialize)]
+pub struct Headway {
+    /// The first trip departs the first stop at this time
+    pub start: Time,
+    /// All trips depart the first stop before this time
+    pub end: Time,
+    /// The time between two trips
+    pub headway: Time,
 }
 
 /// The departure and arrival time of a trip at a stop
-#[derive(Clone)]
+#[derive(Clone, Copy, Archive, Serialize, Deserialize)]
 pub struct StopTime {
     pub departure_time: Time,
     pub arrival_time: Time,
+    /// Whether passengers can board the trip at this stop. False if the GTFS pickup type is 1
+    pub can_board: bool,
+    /// Whether passengers can exit the trip at this stop. False if the GTFS drop off type is 1
+    pub can_exit: bool,
+    /// Whether wheelchair users can board and exit the trip at this stop. Only true if both the
+    /// trip and the stop are known to be accessible
+    pub wheelchair_accessible: bool,
 }
-#[derive(Clone)]
+
+impl StopTime {
+    /// Whether riders with the needs of the query can board the trip at this stop
+    pub(crate) fn can_board_with(&self, options: &QueryOptions) -> bool {
+        self.can_board && (!options.wheelchair_accessible || self.wheelchair_accessible)
+    }
+
+    /// Whether riders with the needs of the query can exit the trip at this stop
+    pub(crate) fn can_exit_with(&self, options: &QueryOptions) -> bool {
+        self.can_exit && (!options.wheelchair_accessible || self.wheelchair_accessible)
+    }
+}
+#[derive(Clone, Archive, Serialize, Deserialize)]
 pub struct RoutesData {
     /// This array is divided into blocks, and the i-th block contains all trips corresponding
     /// to route ri. Within a block, trips are sorted by departure time (at the first stop).
@@ -38,65 +78,78 @@ pub struct RoutesData {
     /// The stops for routes where segments represent stops sequence for routes
     /// The first entries belong to routes[0] then the next to route[1] and so on...
     pub route_stops: Vec<usize>,
+    /// Trips that continue as a trip of another route with the same vehicle. Sorted by the route
+    /// and trip number they continue from, as a trip continues as at most one other trip
+    pub continuations: Vec<TripContinuation>,
 }
 
-impl RoutesData {
-    fn get_stop_times(&self, route: &Route) -> &[StopTime] {
-        let start = route.stop_times_start_index;
-        let length = route.number_of_trips * route.number_of_stops;
-        let end = start + length;
-        &self.stop_times[start..end]
-    }
+/// A trip that continues as the trip of another route with the same vehicle, like trips of the same
+/// block in GTFS. Riders can stay seated instead of changing vehicles
+#[derive(Clone, Copy, Archive, Serialize, Deserialize)]
+pub struct TripContinuation {
+    pub from_route: usize,
+    pub from_trip_number: usize,
+    /// The route of the trip that continues. Riders stay seated from the first stop of the route
+    pub to_route: usize,
+    pub to_trip_number: usize,
+}
 
-    pub(crate) fn get_route_stops(&self, route: &Route) -> &[usize] {
-        let start = route.route_stops_start_index;
-        let end = start + route.number_of_stops;
-        &self.route_stops[start..end]
-    }
+/// The stop times of a trip. Trips of a route with a headway share the template stop times and are
+/// shifted by their departure
+pub(crate) struct TripTimes<'a, T> {
+    timetable: &'a T,
+    /// The index of the stop time of the trip at the first stop of the route
+    start: usize,
+    offset: Time,
+}
 
-    /// Get the sequence for a stop on the given route
-    /// Returns none if the stop is not on the route otherwise the sequence index of the stop on the
-    /// route
-    pub(crate) fn get_stop_sequence(&self, route: &Route, stop: &usize) -> Option<usize> {
-        let route_stops = self.get_route_stops(route);
-        route_stops
-            .iter()
-            .position(|route_stop| &route_stop == &stop)
+// Derived implementations would need the timetable to be copied
+impl<T> Clone for TripTimes<'_, T> {
+    fn clone(&self) -> Self {
+        *self
     }
+}
 
-    /// Get the earliest trip departing from a stop along the route after some time
-    /// returns the number of the trip in the route (index in sequence of trips for route) and the
-    /// trip stop times
-    pub(crate) fn get_earliest_departing_trip(
-        &self,
-        route: &Route,
-        // The sequence of the stop on the route for which the next trip departing should be found
-        from_stop_sequence: &usize,
-        after: &Time,
-    ) -> Option<(usize, &[StopTime])> {
-        // Assume we get have the stop_sequence
-        let stop_times = self.get_stop_times(route);
-        for trip_index in 0..route.number_of_trips {
-            let trip_start = trip_index * route.number_of_stops;
-            let stop_time = &stop_times[trip_start + from_stop_sequence];
-            if &stop_time.departure_time > after {
-                let trip_end = trip_start + route.number_of_stops;
-                let trip = &stop_times[trip_start..trip_end];
-                return Some((trip_index, trip));
-            }
+impl<T> Copy for TripTimes<'_, T> {}
+
+impl<T: TimetableView> TripTimes<'_, T> {
+    /// The stop time at the stop with the sequence on the route
+    pub(crate) fn get(&self, stop_sequence: usize) -> StopTime {
+        let stop_time = self.timetable.stop_time(self.start + stop_sequence);
+        StopTime {
+            departure_time: stop_time.departure_time + self.offset,
+            arrival_time: stop_time.arrival_time + self.offset,
+            ..stop_time
         }
+    }
+}
 
-        return None;
+/// Binary search for the first index in 0..length for which the predicate is false, assuming it is
+/// true for all indices before and false for all after like [slice::partition_point]
+fn partition_point(length: usize, predicate: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, length);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if predicate(middle) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
     }
+
+    low
 }
 
-#[derive(Clone)]
+#[derive(Clone, Archive, Serialize, Deserialize)]
 pub struct Stop {
     pub id: String,
     pub transfers_index_start: usize,
     pub stop_routes_index_start: usize,
     pub transfers_count: usize,
     pub stop_routes_count: usize,
+    /// The minimum time it takes to change from one vehicle to another at this stop.
+    /// If there is none, the default change time of the stops data is used
+    pub minimum_change_time: Option<Time>,
 }
 
 impl Hash for Stop {
@@ -114,31 +167,637 @@ impl PartialEq<Self> for Stop {
 impl Eq for Stop {}
 
 /// A transfer that leaves a stop and allows reaching another stop by foot path
-#[derive(Clone)]
+#[derive(Clone, Copy, Archive, Serialize, Deserialize)]
 pub struct Transfer {
     /// The target stop that can be reached by foot through this foot-path
     pub target: usize,
     /// Time it takes to reach the target stop by foot
     pub time: Time,
+    /// Whether the walk avoids stairs and escalators. Walks without information about the way are
+    /// assumed to be accessible
+    pub wheelchair_accessible: bool,
+    /// Whether only wheelchair users take this walk, like walks through station pathways that are
+    /// only known to find the ways without stairs
+    pub wheelchair_only: bool,
+}
+
+impl Transfer {
+    /// Whether riders with the needs of the query can walk this foot-path
+    pub(crate) fn can_walk_with(&self, options: &QueryOptions) -> bool {
+        if options.wheelchair_accessible {
+            self.wheelchair_accessible
+        } else {
+            !self.wheelchair_only
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Archive, Serialize, Deserialize)]
 pub struct StopsData {
     pub transfers: Vec<Transfer>,
     pub stops: Vec<Stop>,
     /// Not the routes themselves but the indices of in the route data
     pub stop_routes: Vec<usize>,
+    /// The position of the stop in the stop sequence of the route at the same index in
+    /// stop_routes. A route serving a stop more than once has an entry for each position
+    pub stop_route_positions: Vec<usize>,
+    /// The time it takes to change vehicles at stops without a minimum change time
+    pub default_change_time: Time,
+}
+
+/// The timetable that queries run on with the routes and their trips, the stops and the foot-paths
+/// between them. Queries only borrow it, so it can be shared between threads and requests
+#[derive(Clone, Archive, Serialize, Deserialize)]
+pub struct Timetable {
+    pub routes_data: RoutesData,
+    /// The stops with their foot-paths
+    pub stops_data: StopsData,
+}
+
+// Fails to compile when a field stops the timetable from being shared between threads
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Timetable>();
+    assert_send_sync::<ArchivedTimetable>();
+};
+
+/// Read access to the timetable the queries run on by the indices of [RoutesData] and [StopsData].
+/// Implemented by the [Timetable] and by its archived form, so queries can also run in place on
+/// the timetable of a memory-mapped snapshot. Values are returned as copies, as the archived form
+/// stores them in another layout.
+pub trait TimetableView {
+    fn stop_count(&self) -> usize;
+
+    fn route_count(&self) -> usize;
+
+    fn route(&self, route: usize) -> Route;
+
+    /// The stop at the index in [RoutesData::route_stops]
+    fn route_stop(&self, index: usize) -> usize;
+
+    /// The stop time at the index in [RoutesData::stop_times]
+    fn stop_time(&self, index: usize) -> StopTime;
+
+    fn continuation_count(&self) -> usize;
+
+    /// The continuation at the index in [RoutesData::continuations]
+    fn continuation(&self, index: usize) -> TripContinuation;
+
+    /// The indices of the foot-paths leaving the stop in [StopsData::transfers]
+    fn transfer_indices(&self, stop: usize) -> Range<usize>;
+
+    /// The foot-path at the index in [StopsData::transfers]
+    fn transfer(&self, index: usize) -> Transfer;
+
+    /// The indices of the routes serving the stop in [StopsData::stop_routes]
+    fn stop_route_indices(&self, stop: usize) -> Range<usize>;
+
+    /// The route at the index in [StopsData::stop_routes] with the position of the stop on it
+    fn stop_route(&self, index: usize) -> (usize, usize);
+
+    /// The minimum time it takes to change from one vehicle to another at the stop
+    fn change_time(&self, stop: usize) -> Time;
 }
 
-impl StopsData {
-    fn get_routes_for(&self, stop: &Stop) -> &[usize] {
+impl TimetableView for Timetable {
+    fn stop_count(&self) -> usize {
+        self.stops_data.stops.len()
+    }
+
+    fn route_count(&self) -> usize {
+        self.routes_data.routes.len()
+    }
+
+    fn route(&self, route: usize) -> Route {
+        self.routes_data.routes[route]
+    }
+
+    fn route_stop(&self, index: usize) -> usize {
+        self.routes_data.route_stops[index]
+    }
+
+    fn stop_time(&self, index: usize) -> StopTime {
+        self.routes_data.stop_times[index]
+    }
+
+    fn continuation_count(&self) -> usize {
+        self.routes_data.continuations.len()
+    }
+
+    fn continuation(&self, index: usize) -> TripContinuation {
+        self.routes_data.continuations[index]
+    }
+
+    fn transfer_indices(&self, stop: usize) -> Range<usize> {
+        let stop = &self.stops_data.stops[stop];
+        let start = stop.transfers_index_start;
+        start..start + stop.transfers_count
+    }
+
+    fn transfer(&self, index: usize) -> Transfer {
+        self.stops_data.transfers[index]
+    }
+
+    fn stop_route_indices(&self, stop: usize) -> Range<usize> {
+        let stop = &self.stops_data.stops[stop];
         let start = stop.stop_routes_index_start;
-        let end = start + stop.stop_routes_count;
-        &self.stop_routes[start..end]
+        start..start + stop.stop_routes_count
+    }
+
+    fn stop_route(&self, index: usize) -> (usize, usize) {
+        (
+            self.stops_data.stop_routes[index],
+            self.stops_data.stop_route_positions[index],
+        )
     }
 
-    pub(crate) fn get_routes(&self, stop: &usize) -> &[usize] {
-        let stop = &self.stops[*stop];
-        self.get_routes_for(stop)
+    fn change_time(&self, stop: usize) -> Time {
+        self.stops_data.stops[stop]
+            .minimum_change_time
+            .unwrap_or(self.stops_data.default_change_time)
     }
-}
\ No newline at end of file
+}
+
+impl From<&ArchivedHeadway> for Headway {
+    fn from(headway: &ArchivedHeadway) -> Self {
+        Headway {
+            start: Time::from(&headway.start),
+            end: Time::from(&headway.end),
+            headway: Time::from(&headway.headway),
+        }
+    }
+}
+
+impl From<&ArchivedRoute> for Route {
+    fn from(route: &ArchivedRoute) -> Self {
+        Route {
+            number_of_trips: route.number_of_trips.to_native() as usize,
+            number_of_stops: route.number_of_stops.to_native() as usize,
+            route_stops_start_index: route.route_stops_start_index.to_native() as usize,
+            stop_times_start_index: route.stop_times_start_index.to_native() as usize,
+            headway: route.headway.as_ref().map(Headway::from),
+        }
+    }
+}
+
+impl From<&ArchivedStopTime> for StopTime {
+    fn from(stop_time: &ArchivedStopTime) -> Self {
+        StopTime {
+            departure_time: Time::from(&stop_time.departure_time),
+            arrival_time: Time::from(&stop_time.arrival_time),
+            can_board: stop_time.can_board,
+            can_exit: stop_time.can_exit,
+            wheelchair_accessible: stop_time.wheelchair_accessible,
+        }
+    }
+}
+
+impl From<&ArchivedTripContinuation> for TripContinuation {
+    fn from(continuation: &ArchivedTripContinuation) -> Self {
+        TripContinuation {
+            from_route: continuation.from_route.to_native() as usize,
+            from_trip_number: continuation.from_trip_number.to_native() as usize,
+            to_route: continuation.to_route.to_native() as usize,
+            to_trip_number: continuation.to_trip_number.to_native() as usize,
+        }
+    }
+}
+
+impl From<&ArchivedTransfer> for Transfer {
+    fn from(transfer: &ArchivedTransfer) -> Self {
+        Transfer {
+            target: transfer.target.to_native() as usize,
+            time: Time::from(&transfer.time),
+            wheelchair_accessible: transfer.wheelchair_accessible,
+            wheelchair_only: transfer.wheelchair_only,
+        }
+    }
+}
+
+impl TimetableView for ArchivedTimetable {
+    fn stop_count(&self) -> usize {
+        self.stops_data.stops.len()
+    }
+
+    fn route_count(&self) -> usize {
+        self.routes_data.routes.len()
+    }
+
+    fn route(&self, route: usize) -> Route {
+        Route::from(&self.routes_data.routes[route])
+    }
+
+    fn route_stop(&self, index: usize) -> usize {
+        self.routes_data.route_stops[index].to_native() as usize
+    }
+
+    fn stop_time(&self, index: usize) -> StopTime {
+        StopTime::from(&self.routes_data.stop_times[index])
+    }
+
+    fn continuation_count(&self) -> usize {
+        self.routes_data.continuations.len()
+    }
+
+    fn continuation(&self, index: usize) -> TripContinuation {
+        TripContinuation::from(&self.routes_data.continuations[index])
+    }
+
+    fn transfer_indices(&self, stop: usize) -> Range<usize> {
+        let stop = &self.stops_data.stops[stop];
+        let start = stop.transfers_index_start.to_native() as usize;
+        start..start + stop.transfers_count.to_native() as usize
+    }
+
+    fn transfer(&self, index: usize) -> Transfer {
+        Transfer::from(&self.stops_data.transfers[index])
+    }
+
+    fn stop_route_indices(&self, stop: usize) -> Range<usize> {
+        let stop = &self.stops_data.stops[stop];
+        let start = stop.stop_routes_index_start.to_native() as usize;
+        start..start + stop.stop_routes_count.to_native() as usize
+    }
+
+    fn stop_route(&self, index: usize) -> (usize, usize) {
+        (
+            self.stops_data.stop_routes[index].to_native() as usize,
+            self.stops_data.stop_route_positions[index].to_native() as usize,
+        )
+    }
+
+    fn change_time(&self, stop: usize) -> Time {
+        self.stops_data.stops[stop]
+            .minimum_change_time
+            .as_ref()
+            .map_or(Time::from(&self.stops_data.default_change_time), Time::from)
+    }
+}
+
+/// The lookups of trips, routes and foot-paths the algorithms share on top of [TimetableView]
+pub(crate) trait TimetableLookup: TimetableView + Sized {
+    /// Get the stop with the sequence on the route
+    fn get_route_stop(&self, route: &Route, stop_sequence: usize) -> usize {
+        self.route_stop(route.route_stops_start_index + stop_sequence)
+    }
+
+    /// Get the stop times of a trip on the given route by the number of the trip in the route
+    fn get_trip(&self, route: &Route, trip_number: usize) -> TripTimes<'_, Self> {
+        if route.headway.is_some() {
+            // The trip number is the departure of the trip shifting the template trip
+            return TripTimes {
+                timetable: self,
+                start: route.stop_times_start_index,
+                offset: Time::from(trip_number as u64),
+            };
+        }
+
+        TripTimes {
+            timetable: self,
+            start: route.stop_times_start_index + trip_number * route.number_of_stops,
+            offset: Time::from(0),
+        }
+    }
+
+    /// Get the trip the trip of the route continues as with the same vehicle if there is one
+    fn get_continuation(&self, route: usize, trip_number: usize) -> Option<TripContinuation> {
+        let index = partition_point(self.continuation_count(), |index| {
+            let continuation = self.continuation(index);
+            (continuation.from_route, continuation.from_trip_number) < (route, trip_number)
+        });
+        if index == self.continuation_count() {
+            return None;
+        }
+
+        let continuation = self.continuation(index);
+        let is_continued =
+            (continuation.from_route, continuation.from_trip_number) == (route, trip_number);
+        is_continued.then_some(continuation)
+    }
+
+    /// Get the sequence for a stop on the given route
+    /// Returns none if the stop is not on the route otherwise the sequence index of the stop on the
+    /// route
+    fn get_stop_sequence(&self, route: &Route, stop: &usize) -> Option<usize> {
+        (0..route.number_of_stops)
+            .position(|stop_sequence| self.get_route_stop(route, stop_sequence) == *stop)
+    }
+
+    /// Get the earliest trip departing from a stop along the route after some time that can be
+    /// boarded at the stop
+    /// returns the number of the trip in the route (index in sequence of trips for route) and the
+    /// trip stop times
+    fn get_earliest_departing_trip(
+        &self,
+        route: &Route,
+        // The sequence of the stop on the route for which the next trip departing should be found
+        from_stop_sequence: &usize,
+        after: &Time,
+        options: &QueryOptions,
+    ) -> Option<(usize, TripTimes<'_, Self>)> {
+        if let Some(headway) = &route.headway {
+            return self.get_earliest_headway_trip(
+                route,
+                headway,
+                from_stop_sequence,
+                after,
+                options,
+            );
+        }
+
+        let stop_time = |trip_index: usize| {
+            self.stop_time(
+                route.stop_times_start_index
+                    + trip_index * route.number_of_stops
+                    + from_stop_sequence,
+            )
+        };
+
+        // Trips are sorted by departure and don't overtake each other, so they are also sorted by
+        // their departure at any other stop.
+        // A trip departing at the same time as we arrive can still be caught
+        let first_in_time = partition_point(route.number_of_trips, |trip_index| {
+            &stop_time(trip_index).departure_time < after
+        });
+        let trip_index = (first_in_time..route.number_of_trips)
+            .find(|trip_index| stop_time(*trip_index).can_board_with(options))?;
+
+        Some((trip_index, self.get_trip(route, trip_index)))
+    }
+
+    /// Get the latest trip arriving at a stop along the route before some time that can be exited
+    /// at the stop
+    /// returns the number of the trip in the route (index in sequence of trips for route) and the
+    /// trip stop times
+    fn get_latest_arriving_trip(
+        &self,
+        route: &Route,
+        // The sequence of the stop on the route for which the latest trip arriving should be found
+        to_stop_sequence: &usize,
+        before: &Time,
+        options: &QueryOptions,
+    ) -> Option<(usize, TripTimes<'_, Self>)> {
+        if let Some(headway) = &route.headway {
+            return self.get_latest_headway_trip(route, headway, to_stop_sequence, before, options);
+        }
+
+        let stop_time = |trip_index: usize| {
+            self.stop_time(
+                route.stop_times_start_index
+                    + trip_index * route.number_of_stops
+                    + to_stop_sequence,
+            )
+        };
+
+        // A trip arriving at the same time as we need to leave can still be used
+        let first_too_late = partition_point(route.number_of_trips, |trip_index| {
+            &stop_time(trip_index).arrival_time <= before
+        });
+        let trip_index = (0..first_too_late)
+            .rev()
+            .find(|trip_index| stop_time(*trip_index).can_exit_with(options))?;
+
+        Some((trip_index, self.get_trip(route, trip_index)))
+    }
+
+    /// Like [TimetableLookup::get_earliest_departing_trip] for a route with a headway. Riders can't
+    /// know when the next trip departs, so they are assumed to wait a full headway
+    fn get_earliest_headway_trip(
+        &self,
+        route: &Route,
+        headway: &Headway,
+        from_stop_sequence: &usize,
+        after: &Time,
+        options: &QueryOptions,
+    ) -> Option<(usize, TripTimes<'_, Self>)> {
+        let stop_time = self.stop_time(route.stop_times_start_index + from_stop_sequence);
+        if !stop_time.can_board_with(options) {
+            return None;
+        }
+
+        // The departure at the first stop of a trip reaching the stop after the time
+        let earliest = after
+            .seconds()?
+            .saturating_sub(stop_time.departure_time.seconds()?);
+        let departure = max(
+            earliest + headway.headway.seconds()?,
+            headway.start.seconds()?,
+        );
+        if departure >= headway.end.seconds()? {
+            return None;
+        }
+
+        let trip_number = departure as usize;
+        Some((trip_number, self.get_trip(route, trip_number)))
+    }
+
+    /// Like [TimetableLookup::get_latest_arriving_trip] for a route with a headway. Riders are
+    /// assumed to arrive a full headway before they need to
+    fn get_latest_headway_trip(
+        &self,
+        route: &Route,
+        headway: &Headway,
+        to_stop_sequence: &usize,
+        before: &Time,
+        options: &QueryOptions,
+    ) -> Option<(usize, TripTimes<'_, Self>)> {
+        let stop_time = self.stop_time(route.stop_times_start_index + to_stop_sequence);
+        if !stop_time.can_exit_with(options) {
+            return None;
+        }
+
+        // The departure at the first stop of a trip reaching the stop before the time
+        let latest = before
+            .seconds()?
+            .checked_sub(stop_time.arrival_time.seconds()?)?
+            .checked_sub(headway.headway.seconds()?)?;
+        let departure = min(latest, headway.end.seconds()?.checked_sub(1)?);
+        if departure < headway.start.seconds()? {
+            return None;
+        }
+
+        let trip_number = departure as usize;
+        Some((trip_number, self.get_trip(route, trip_number)))
+    }
+
+    fn get_routes(&self, stop: &usize) -> impl Iterator<Item = usize> + '_ {
+        self.get_route_positions(stop).map(|(route, _)| route)
+    }
+
+    /// Get the routes serving the stop together with the position of the stop on each route, so
+    /// the stop doesn't need to be searched in the stop sequence of the route
+    fn get_route_positions(&self, stop: &usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.stop_route_indices(*stop)
+            .map(|index| self.stop_route(index))
+    }
+
+    /// Get the foot-paths leaving the stop in the order their connections refer to them
+    fn get_transfers(&self, stop: usize) -> impl Iterator<Item = Transfer> + '_ {
+        self.transfer_indices(stop)
+            .map(|index| self.transfer(index))
+    }
+
+    /// Get the foot-path leaving the stop with the number a connection refers to it by
+    fn get_transfer(&self, stop: usize, transfer: usize) -> Transfer {
+        self.transfer(self.transfer_indices(stop).start + transfer)
+    }
+}
+
+impl<T: TimetableView> TimetableLookup for T {}
+
+#[cfg(test)]
+mod tests {
+    use crate::journey::reconstruct_journeys;
+    use crate::reverse::reverse_raptor;
+    use crate::shared::{ArchivedTimetable, Headway, Timetable, TimetableLookup, TripContinuation};
+    use crate::test_network::{build_network, TestRoute};
+    use crate::{raptor, QueryOptions, Time};
+
+    #[test]
+    fn finds_trips_around_time_skipping_forbidden_stops() {
+        // Arrange
+        let (mut routes_data, stops_data) = build_network(
+            2,
+            vec![TestRoute {
+                stops: vec![0, 1],
+                trips: (0..10)
+                    .map(|trip| vec![trip * 100, trip * 100 + 50])
+                    .collect(),
+            }],
+            Vec::new(),
+        );
+        // Trip 4 can not be boarded at the first stop and trip 2 not be exited at the second
+        routes_data.stop_times[8].can_board = false;
+        routes_data.stop_times[5].can_exit = false;
+        let route = routes_data.routes[0];
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+        let options = QueryOptions::default();
+
+        // Act
+        let earliest =
+            timetable.get_earliest_departing_trip(&route, &0, &Time::from(350), &options);
+        let latest = timetable.get_latest_arriving_trip(&route, &1, &Time::from(300), &options);
+
+        // Assert
+        assert_eq!(Some(5), earliest.map(|(trip_number, _)| trip_number));
+        assert_eq!(Some(1), latest.map(|(trip_number, _)| trip_number));
+        assert!(timetable
+            .get_earliest_departing_trip(&route, &0, &Time::from(901), &options)
+            .is_none());
+    }
+
+    #[test]
+    fn finds_headway_trips_waiting_a_full_headway() {
+        // Arrange
+        let (mut routes_data, stops_data) = build_network(
+            3,
+            vec![TestRoute {
+                stops: vec![0, 1, 2],
+                trips: vec![vec![0, 300, 600]],
+            }],
+            Vec::new(),
+        );
+        routes_data.routes[0].headway = Some(Headway {
+            start: Time::from(3600),
+            end: Time::from(7200),
+            headway: Time::from(600),
+        });
+        let route = routes_data.routes[0];
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+        let options = QueryOptions::default();
+
+        // Act
+        let earliest =
+            timetable.get_earliest_departing_trip(&route, &1, &Time::from(4000), &options);
+        let latest = timetable.get_latest_arriving_trip(&route, &2, &Time::from(5000), &options);
+
+        // Assert
+        let (trip_number, trip) = earliest.unwrap();
+        assert_eq!(4300, trip_number);
+        assert_eq!(Time::from(4600), trip.get(1).departure_time);
+        assert_eq!(Time::from(4900), trip.get(2).arrival_time);
+        let (trip_number, trip) = latest.unwrap();
+        assert_eq!(3800, trip_number);
+        assert_eq!(Time::from(4400), trip.get(2).arrival_time);
+        // No trips depart after the end
+        assert!(timetable
+            .get_earliest_departing_trip(&route, &0, &Time::from(6700), &options)
+            .is_none());
+    }
+
+    #[test]
+    fn answers_queries_on_archived_timetable_like_on_timetable() {
+        // Arrange
+        let (mut routes_data, stops_data) = build_network(
+            5,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![200, 300], vec![400, 500]],
+                },
+                TestRoute {
+                    stops: vec![0, 3],
+                    trips: vec![vec![0, 300]],
+                },
+            ],
+            vec![(2, 4, 60), (3, 4, 600)],
+        );
+        routes_data.continuations = vec![TripContinuation {
+            from_route: 0,
+            from_trip_number: 0,
+            to_route: 1,
+            to_trip_number: 0,
+        }];
+        routes_data.routes[2].headway = Some(Headway {
+            start: Time::from(0),
+            end: Time::from(3600),
+            headway: Time::from(600),
+        });
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&timetable).unwrap();
+        let archived = rkyv::access::<ArchivedTimetable, rkyv::rancor::Error>(&bytes).unwrap();
+
+        // Act
+        let rounds = raptor(0, 4, &Time::from(0), archived);
+        let journeys = reconstruct_journeys(4, &rounds, archived);
+        let options = QueryOptions::default();
+        let reverse_journeys = reverse_raptor(
+            &[(0, Time::from(0))],
+            &[(4, Time::from(0))],
+            &Time::from(1000),
+            archived,
+            &options,
+        );
+
+        // Assert
+        let expected_rounds = raptor(0, 4, &Time::from(0), &timetable);
+        assert_eq!(
+            reconstruct_journeys(4, &expected_rounds, &timetable),
+            journeys
+        );
+        assert!(!journeys.is_empty());
+        assert_eq!(
+            reverse_raptor(
+                &[(0, Time::from(0))],
+                &[(4, Time::from(0))],
+                &Time::from(1000),
+                &timetable,
+                &options
+            ),
+            reverse_journeys
+        );
+    }
+}
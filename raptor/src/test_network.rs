@@ -0,0 +1,95 @@
+//! Small hand-built networks to test the algorithms without a GTFS database
+
+use crate::shared::{Route, RoutesData, Stop, StopTime, StopsData, Transfer};
+use crate::Time;
+
+/// A route with its stop sequence and the times of each trip at those stops. Trips need to be
+/// sorted by departure and arrival and departure are the same at each stop.
+pub(crate) struct TestRoute {
+    pub(crate) stops: Vec<usize>,
+    pub(crate) trips: Vec<Vec<u64>>,
+}
+
+/// Builds the RAPTOR data structures for a network with the given number of stops, routes and
+/// foot-paths given as (source, target, walking seconds)
+pub(crate) fn build_network(
+    stop_count: usize,
+    test_routes: Vec<TestRoute>,
+    foot_paths: Vec<(usize, usize, u64)>,
+) -> (RoutesData, StopsData) {
+    let mut stop_times = Vec::new();
+    let mut routes = Vec::new();
+    let mut route_stops = Vec::new();
+    // Routes with the position of the stop on them by stop
+    let mut routes_by_stop: Vec<Vec<(usize, usize)>> = vec![Vec::new(); stop_count];
+
+    for (route_index, TestRoute { stops, trips }) in test_routes.into_iter().enumerate() {
+        routes.push(Route {
+            number_of_trips: trips.len(),
+            number_of_stops: stops.len(),
+            route_stops_start_index: route_stops.len(),
+            stop_times_start_index: stop_times.len(),
+            headway: None,
+        });
+
+        for (position, &stop) in stops.iter().enumerate() {
+            routes_by_stop[stop].push((route_index, position));
+        }
+        route_stops.extend(stops);
+
+        for time in trips.into_iter().flatten() {
+            stop_times.push(StopTime {
+                departure_time: Time::from(time),
+                arrival_time: Time::from(time),
+                can_board: true,
+                can_exit: true,
+                wheelchair_accessible: true,
+            });
+        }
+    }
+
+    let mut transfers = Vec::new();
+    let mut stops = Vec::new();
+    let mut stop_routes = Vec::new();
+    let mut stop_route_positions = Vec::new();
+    for (stop, route_positions) in routes_by_stop.into_iter().enumerate() {
+        let transfers_index_start = transfers.len();
+        for (_, target, time) in foot_paths.iter().filter(|(source, ..)| *source == stop) {
+            transfers.push(Transfer {
+                target: *target,
+                time: Time::from(*time),
+                wheelchair_accessible: true,
+                wheelchair_only: false,
+            });
+        }
+
+        stops.push(Stop {
+            id: stop.to_string(),
+            transfers_index_start,
+            stop_routes_index_start: stop_routes.len(),
+            transfers_count: transfers.len() - transfers_index_start,
+            stop_routes_count: route_positions.len(),
+            minimum_change_time: None,
+        });
+        for (route, position) in route_positions {
+            stop_routes.push(route);
+            stop_route_positions.push(position);
+        }
+    }
+
+    (
+        RoutesData {
+            stop_times,
+            routes,
+            route_stops,
+            continuations: Vec::new(),
+        },
+        StopsData {
+            transfers,
+            stops,
+            stop_routes,
+            stop_route_positions,
+            default_change_time: Time::from(0),
+        },
+    )
+}
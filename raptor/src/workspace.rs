@@ -0,0 +1,548 @@
+use crate::access::collect_journeys;
+use crate::journey::{reconstruct_journey_with, Journey};
+use crate::scan::{scan_route, RoundLabels};
+use crate::shared::{TimetableLookup, TimetableView};
+use crate::{Connection, QueryOptions, Time};
+use std::cmp::min;
+
+/// A set of stop indices with one bit per stop
+#[derive(Default)]
+struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    /// Removes all indices and makes room for indices up to the given length
+    fn reset(&mut self, length: usize) {
+        self.words.clear();
+        self.words.resize(length.div_ceil(u64::BITS as usize), 0);
+    }
+
+    fn clear(&mut self) {
+        self.words.fill(0);
+    }
+
+    fn insert(&mut self, index: usize) {
+        let bits = u64::BITS as usize;
+        self.words[index / bits] |= 1 << (index % bits);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    fn union_with(&mut self, other: &BitSet) {
+        for (word, other_word) in self.words.iter_mut().zip(&other.words) {
+            *word |= other_word;
+        }
+    }
+
+    /// The indices in ascending order
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .flat_map(|(word_index, &word)| {
+                let mut remaining = word;
+                std::iter::from_fn(move || {
+                    if remaining == 0 {
+                        return None;
+                    }
+
+                    let bit = remaining.trailing_zeros() as usize;
+                    // Remove the lowest set bit
+                    remaining &= remaining - 1;
+                    Some(word_index * u64::BITS as usize + bit)
+                })
+            })
+    }
+}
+
+/// The labels of a round of a [RaptorWorkspace] for scanning routes
+struct WorkspaceRound<'a> {
+    last_round_labels: &'a [Time],
+    last_round_connections: Option<&'a Vec<Option<Connection>>>,
+    current_round_labels: &'a mut [Time],
+    connection_by_stop: &'a mut [Option<Connection>],
+    best_by_stop: &'a mut [Time],
+    egress_by_stop: &'a [Time],
+    /// Earliest arrival at the destination, lowered when an egress stop improves
+    target_bound: Time,
+    marked_stops: &'a mut BitSet,
+}
+
+impl RoundLabels for WorkspaceRound<'_> {
+    fn previous_arrival(&self, stop: usize) -> Time {
+        self.last_round_labels[stop]
+    }
+
+    fn is_reached_by_trip(&self, stop: usize) -> bool {
+        self.last_round_connections.is_some_and(|connections| {
+            matches!(
+                connections[stop],
+                Some(Connection::Connection { .. } | Connection::Continuation { .. })
+            )
+        })
+    }
+
+    fn exit_bound(&self, stop: usize) -> Time {
+        // Local pruning with the stop and target pruning with the destination
+        min(self.best_by_stop[stop], self.target_bound)
+    }
+
+    fn improve(&mut self, stop: usize, arrival: Time, connection: Connection) {
+        self.current_round_labels[stop] = arrival;
+        self.best_by_stop[stop] = arrival;
+        self.target_bound = min(self.target_bound, arrival + self.egress_by_stop[stop]);
+        self.connection_by_stop[stop] = Some(connection);
+        self.marked_stops.insert(stop);
+    }
+}
+
+/// The memory [crate::raptor] needs for a query, kept to answer further queries without allocating.
+/// As stops and routes are dense indices, labels and connections are stored in vectors indexed by
+/// stop instead of maps. A server thread can keep one workspace and reuse it for every query.
+///
+/// The workspace allocates on the first query and grows only when a query needs more rounds or a
+/// timetable has more stops than before.
+#[derive(Default)]
+pub struct RaptorWorkspace {
+    /// For each round the earliest arrival by stop. Index is the round
+    labels_by_round: Vec<Vec<Time>>,
+    /// Connections to reconstruct journeys by the stop they reach. Index is the round k - 1
+    connections_by_round: Vec<Vec<Option<Connection>>>,
+    /// The earliest arrival for any stop without caring about the round
+    best_by_stop: Vec<Time>,
+    /// The walking time from each stop to the destination, infinite for stops that are no egress
+    /// stops
+    egress_by_stop: Vec<Time>,
+    marked_stops: BitSet,
+    /// Stops improved by foot-paths, as the marked stops can not change while going through them
+    foot_path_marks: BitSet,
+    /// The sequence of the earliest marked stop on each route or none if the route is not queued
+    queue_by_route: Vec<Option<usize>>,
+    /// The queued routes, to not go through all routes of the timetable in each round
+    queued_routes: Vec<usize>,
+    /// The amount of rounds the last query used
+    rounds: usize,
+}
+
+impl RaptorWorkspace {
+    /// Resets the labels and connections of a round. Rounds not used by the current query keep
+    /// their old values as they are never read
+    fn prepare_round(&mut self, round: usize, stop_count: usize) {
+        if self.labels_by_round.len() <= round {
+            self.labels_by_round.push(Vec::new());
+        }
+        let labels = &mut self.labels_by_round[round];
+        labels.clear();
+        labels.resize(stop_count, Time::INFINITE);
+
+        // There are no connections to reach the stops in round 0
+        if round == 0 {
+            return;
+        }
+
+        if self.connections_by_round.len() < round {
+            self.connections_by_round.push(Vec::new());
+        }
+        let connections = &mut self.connections_by_round[round - 1];
+        connections.clear();
+        connections.resize(stop_count, None);
+    }
+
+    /// Runs the same query as [crate::raptor] from the source to the target stop, staying seated on
+    /// trips that continue as another trip and only taking the trips and foot-paths the options
+    /// allow. The results can be read from the workspace until the next query
+    pub fn run(
+        &mut self,
+        source: usize,
+        target: usize,
+        departure: &Time,
+        timetable: &impl TimetableView,
+        options: &QueryOptions,
+    ) {
+        self.run_access_egress(
+            &[(source, Time::from(0))],
+            departure,
+            &[(target, Time::from(0))],
+            timetable,
+            options,
+        );
+    }
+
+    /// Runs the same query as [crate::access::access_egress_raptor] from the origin reaching the
+    /// access stops to the destination reached from the egress stops, both given as pairs of stop
+    /// and walking time. The journeys can be read with [RaptorWorkspace::access_egress_journeys]
+    /// until the next query
+    pub fn run_access_egress(
+        &mut self,
+        access: &[(usize, Time)],
+        departure: &Time,
+        egress: &[(usize, Time)],
+        timetable: &impl TimetableView,
+        options: &QueryOptions,
+    ) {
+        let stop_count = timetable.stop_count();
+
+        self.best_by_stop.clear();
+        self.best_by_stop.resize(stop_count, Time::INFINITE);
+        self.egress_by_stop.clear();
+        self.egress_by_stop.resize(stop_count, Time::INFINITE);
+        self.marked_stops.reset(stop_count);
+        self.foot_path_marks.reset(stop_count);
+        self.queue_by_route.clear();
+        self.queue_by_route.resize(timetable.route_count(), None);
+        self.queued_routes.clear();
+
+        self.prepare_round(0, stop_count);
+        for &(stop, walking_time) in egress {
+            let egress_time = &mut self.egress_by_stop[stop];
+            *egress_time = min(*egress_time, walking_time);
+        }
+        for &(stop, walking_time) in access {
+            let label = &mut self.labels_by_round[0][stop];
+            *label = min(*label, *departure + walking_time);
+            self.best_by_stop[stop] = *label;
+            self.marked_stops.insert(stop);
+        }
+        // Earliest arrival at the destination for target pruning
+        let mut target_bound = egress
+            .iter()
+            .map(|&(stop, walking_time)| self.best_by_stop[stop] + walking_time)
+            .min()
+            .unwrap_or(Time::INFINITE);
+
+        let mut k = 0usize;
+        while !self.marked_stops.is_empty() {
+            k += 1;
+            self.prepare_round(k, stop_count);
+
+            // Accumulate routes serving marked stops from the previous round with the earliest
+            // marked stop on the route
+            for p in self.marked_stops.iter() {
+                for (route, sequence) in timetable.get_route_positions(&p) {
+                    match &mut self.queue_by_route[route] {
+                        Some(queued_sequence) => *queued_sequence = min(*queued_sequence, sequence),
+                        queued @ None => {
+                            *queued = Some(sequence);
+                            self.queued_routes.push(route);
+                        }
+                    }
+                }
+            }
+
+            self.marked_stops.clear();
+
+            let (previous_rounds, next_rounds) = self.labels_by_round.split_at_mut(k);
+            let last_round_labels = &previous_rounds[k - 1];
+            let current_round_labels = &mut next_rounds[0];
+            let (previous_connections, next_connections) =
+                self.connections_by_round.split_at_mut(k - 1);
+            let last_round_connections = previous_connections.last();
+            let connection_by_stop = &mut next_connections[0];
+
+            let mut round = WorkspaceRound {
+                last_round_labels,
+                last_round_connections,
+                current_round_labels: &mut *current_round_labels,
+                connection_by_stop: &mut *connection_by_stop,
+                best_by_stop: &mut self.best_by_stop,
+                egress_by_stop: &self.egress_by_stop,
+                target_bound,
+                marked_stops: &mut self.marked_stops,
+            };
+            for queued_route in self.queued_routes.drain(..) {
+                // Taking the sequence leaves the queue empty for the next round
+                let Some(queued_sequence) = self.queue_by_route[queued_route].take() else {
+                    continue;
+                };
+                scan_route(
+                    &mut round,
+                    queued_route,
+                    queued_sequence,
+                    timetable,
+                    options,
+                );
+            }
+            target_bound = round.target_bound;
+
+            // Look at foot-paths
+            for p in self.marked_stops.iter() {
+                let arrival_at_p = current_round_labels[p];
+
+                for (transfer_index, transfer) in timetable.get_transfers(p).enumerate() {
+                    if !transfer.can_walk_with(options) {
+                        continue;
+                    }
+
+                    let arrival_by_foot = arrival_at_p + transfer.time;
+
+                    if arrival_by_foot < current_round_labels[transfer.target] {
+                        current_round_labels[transfer.target] = arrival_by_foot;
+                        connection_by_stop[transfer.target] = Some(Connection::FootPath {
+                            source: p,
+                            transfer: transfer_index,
+                        });
+                        self.foot_path_marks.insert(transfer.target);
+                    }
+                }
+            }
+
+            self.marked_stops.union_with(&self.foot_path_marks);
+            self.foot_path_marks.clear();
+        }
+
+        self.rounds = k;
+    }
+
+    /// The earliest arrival at the stop in any round of the last query
+    pub fn arrival(&self, stop: usize) -> Time {
+        self.labels_by_round[..=self.rounds]
+            .iter()
+            .map(|labels| labels[stop])
+            .min()
+            .unwrap_or(Time::INFINITE)
+    }
+
+    /// Reconstructs the journeys to the target of the last query like
+    /// [crate::journey::reconstruct_journeys]
+    pub fn journeys(&self, target: usize, timetable: &impl TimetableView) -> Vec<Journey> {
+        let mut journeys: Vec<Journey> = Vec::new();
+
+        for round in 1..=self.rounds {
+            if self.connections_by_round[round - 1][target].is_none() {
+                continue;
+            }
+
+            let Some(journey) = reconstruct_journey_with(
+                target,
+                round,
+                |round, stop| self.connections_by_round[round - 1][stop],
+                timetable,
+            ) else {
+                continue;
+            };
+
+            let is_improvement = journeys
+                .last()
+                .is_none_or(|previous| journey.arrival() < previous.arrival());
+            if is_improvement {
+                journeys.push(journey);
+            }
+        }
+
+        journeys
+    }
+
+    /// Reconstructs the journeys of the last query like [crate::access::access_egress_raptor] with
+    /// the same access and egress stops and departure the query ran with
+    pub fn access_egress_journeys(
+        &self,
+        access: &[(usize, Time)],
+        egress: &[(usize, Time)],
+        departure: &Time,
+        timetable: &impl TimetableView,
+    ) -> Vec<Journey> {
+        collect_journeys(
+            access,
+            egress,
+            departure,
+            self.rounds,
+            |round, stop| Some(self.labels_by_round[round][stop]),
+            |round, stop| self.connections_by_round[round - 1][stop],
+            timetable,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::access::access_egress_raptor;
+    use crate::journey::reconstruct_journeys;
+    use crate::shared::{Timetable, TripContinuation};
+    use crate::test_network::{build_network, TestRoute};
+    use crate::workspace::RaptorWorkspace;
+    use crate::{raptor, QueryOptions, Time};
+
+    fn network() -> Timetable {
+        let (routes_data, stops_data) = build_network(
+            5,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1, 2],
+                    trips: vec![vec![100, 200, 300]],
+                },
+                TestRoute {
+                    stops: vec![1, 3],
+                    trips: vec![vec![250, 400]],
+                },
+                TestRoute {
+                    stops: vec![0, 3],
+                    trips: vec![vec![150, 600]],
+                },
+            ],
+            vec![(3, 4, 60)],
+        );
+
+        Timetable {
+            routes_data,
+            stops_data,
+        }
+    }
+
+    #[test]
+    fn finds_same_journeys_as_raptor() {
+        // Arrange
+        let timetable = network();
+        let mut workspace = RaptorWorkspace::default();
+
+        // Act
+        workspace.run(0, 4, &Time::from(50), &timetable, &QueryOptions::default());
+
+        // Assert
+        let rounds = raptor(0, 4, &Time::from(50), &timetable);
+        let expected = reconstruct_journeys(4, &rounds, &timetable);
+        assert_eq!(expected, workspace.journeys(4, &timetable));
+        assert_eq!(Time::from(460), workspace.arrival(4));
+    }
+
+    #[test]
+    fn stays_seated_like_raptor() {
+        // Arrange
+        let (mut routes_data, mut stops_data) = build_network(
+            4,
+            vec![
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![1, 2, 3],
+                    trips: vec![vec![200, 300, 350], vec![400, 500, 550]],
+                },
+            ],
+            Vec::new(),
+        );
+        // Changing vehicles would miss the first trip of the second route
+        stops_data.default_change_time = Time::from(60);
+        routes_data.continuations = vec![TripContinuation {
+            from_route: 0,
+            from_trip_number: 0,
+            to_route: 1,
+            to_trip_number: 0,
+        }];
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+        let mut workspace = RaptorWorkspace::default();
+
+        // Act
+        workspace.run(0, 3, &Time::from(0), &timetable, &QueryOptions::default());
+
+        // Assert
+        let rounds = raptor(0, 3, &Time::from(0), &timetable);
+        let expected = reconstruct_journeys(3, &rounds, &timetable);
+        assert_eq!(1, expected.len());
+        assert_eq!(expected, workspace.journeys(3, &timetable));
+        assert_eq!(Time::from(300), workspace.arrival(2));
+        assert_eq!(Time::from(350), workspace.arrival(3));
+    }
+
+    #[test]
+    fn avoids_inaccessible_trips_and_foot_paths_for_wheelchair_users() {
+        // Arrange
+        let (mut routes_data, mut stops_data) = build_network(
+            4,
+            vec![
+                TestRoute {
+                    stops: vec![0, 3],
+                    trips: vec![vec![100, 200]],
+                },
+                TestRoute {
+                    stops: vec![0, 1],
+                    trips: vec![vec![100, 150]],
+                },
+                TestRoute {
+                    stops: vec![1, 2],
+                    trips: vec![vec![300, 350]],
+                },
+            ],
+            vec![(1, 2, 60), (2, 3, 50)],
+        );
+        // The direct trip can't be boarded and the walk between the trips has stairs
+        routes_data.stop_times[0].wheelchair_accessible = false;
+        stops_data.transfers[0].wheelchair_accessible = false;
+        let timetable = Timetable {
+            routes_data,
+            stops_data,
+        };
+        let options = QueryOptions {
+            wheelchair_accessible: true,
+        };
+        let mut workspace = RaptorWorkspace::default();
+
+        // Act
+        workspace.run(0, 3, &Time::from(0), &timetable, &options);
+
+        // Assert
+        let expected = access_egress_raptor(
+            &[(0, Time::from(0))],
+            &[(3, Time::from(0))],
+            &Time::from(0),
+            &timetable,
+            &options,
+        );
+        assert_eq!(expected, workspace.journeys(3, &timetable));
+        assert_eq!(Time::from(400), workspace.arrival(3));
+    }
+
+    #[test]
+    fn walks_to_and_from_stops_like_access_egress_raptor() {
+        // Arrange
+        let timetable = network();
+        let access = [(0, Time::from(30)), (1, Time::from(120))];
+        let egress = [(3, Time::from(90)), (4, Time::from(0))];
+        let mut workspace = RaptorWorkspace::default();
+
+        // Act
+        workspace.run_access_egress(
+            &access,
+            &Time::from(50),
+            &egress,
+            &timetable,
+            &QueryOptions::default(),
+        );
+
+        // Assert
+        let expected = access_egress_raptor(
+            &access,
+            &egress,
+            &Time::from(50),
+            &timetable,
+            &QueryOptions::default(),
+        );
+        assert!(!expected.is_empty());
+        assert_eq!(
+            expected,
+            workspace.access_egress_journeys(&access, &egress, &Time::from(50), &timetable)
+        );
+    }
+
+    #[test]
+    fn forgets_previous_query() {
+        // Arrange
+        let timetable = network();
+        let mut workspace = RaptorWorkspace::default();
+        workspace.run(0, 4, &Time::from(50), &timetable, &QueryOptions::default());
+
+        // Act
+        workspace.run(1, 4, &Time::from(300), &timetable, &QueryOptions::default());
+
+        // Assert
+        assert!(workspace.journeys(4, &timetable).is_empty());
+        assert_eq!(Time::INFINITE, workspace.arrival(0));
+        assert_eq!(Time::INFINITE, workspace.arrival(4));
+    }
+}
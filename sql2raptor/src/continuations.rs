@@ -0,0 +1,168 @@
+//! Trips that continue as another trip with the same vehicle. Riders can stay seated when the trips
+//! are in the same block of the feed or transfers.txt declares an in-seat transfer between them.
+
+use crate::ServiceDay;
+use libsql::Connection;
+use raptor::shared::TripContinuation;
+use raptor::Time;
+use std::collections::{HashMap, HashSet};
+
+/// The in-seat transfers declared between trips by their trip ids
+#[derive(Default)]
+pub(crate) struct InSeatTransfers {
+    /// Transfer type 4. The first trip continues as the second
+    allowed: Vec<(String, String)>,
+    /// Transfer type 5. Riders have to get off even if the trips are in the same block
+    not_allowed: HashSet<(String, String)>,
+}
+
+/// Loads the in-seat transfers from transfers.txt
+pub(crate) async fn get_in_seat_transfers(
+    connection: &Connection,
+) -> Result<InSeatTransfers, libsql::Error> {
+    let mut rows = connection
+        .query(
+            "SELECT from_trip_id, to_trip_id, type
+            FROM transfers
+            WHERE type IN (4, 5) AND from_trip_id IS NOT NULL AND to_trip_id IS NOT NULL;",
+            (),
+        )
+        .await?;
+
+    let mut in_seat_transfers = InSeatTransfers::default();
+    while let Some(row) = rows.next().await? {
+        let trips = (row.get(0 /* from_trip_id */)?, row.get(1 /* to_trip_id */)?);
+        match row.get::<u32>(2 /* type */)? {
+            4 => in_seat_transfers.allowed.push(trips),
+            _ => {
+                in_seat_transfers.not_allowed.insert(trips);
+            }
+        }
+    }
+
+    Ok(in_seat_transfers)
+}
+
+/// Identifies a trip of the feed on a service day. Trip ids are only unique within a service day
+pub(crate) type TripKey = (ServiceDay, String);
+
+/// A trip as it was placed in the timetable with what is needed to find the trips it continues as
+pub(crate) struct PlacedTrip {
+    pub(crate) route: usize,
+    pub(crate) trip_number: usize,
+    pub(crate) block_id: Option<String>,
+    pub(crate) first_stop: usize,
+    pub(crate) last_stop: usize,
+    /// Departure at the first stop
+    pub(crate) departure: Time,
+    /// Arrival at the last stop
+    pub(crate) arrival: Time,
+}
+
+/// Links each trip to the trip it continues as ordered like [raptor::shared::RoutesData::continuations].
+/// Declared in-seat transfers take precedence over blocks. Within a block, a trip continues as the
+/// next trip if that departs from the stop where it ends
+pub(crate) fn link_continuations(
+    trips: &HashMap<TripKey, PlacedTrip>,
+    in_seat_transfers: &InSeatTransfers,
+) -> Vec<TripContinuation> {
+    let mut continuation_by_trip: HashMap<(usize, usize), TripContinuation> = HashMap::new();
+    let mut link = |from: &PlacedTrip, to: &PlacedTrip| {
+        // The vehicle can not depart before it arrived
+        if to.departure < from.arrival {
+            return;
+        }
+
+        continuation_by_trip
+            .entry((from.route, from.trip_number))
+            .or_insert(TripContinuation {
+                from_route: from.route,
+                from_trip_number: from.trip_number,
+                to_route: to.route,
+                to_trip_number: to.trip_number,
+            });
+    };
+
+    for (from_trip_id, to_trip_id) in &in_seat_transfers.allowed {
+        // The trips have to run on the same service day
+        for service_day in ServiceDay::ALL {
+            let from = trips.get(&(service_day, from_trip_id.clone()));
+            let to = trips.get(&(service_day, to_trip_id.clone()));
+            if let (Some(from), Some(to)) = (from, to) {
+                link(from, to);
+            }
+        }
+    }
+
+    let mut blocks: HashMap<(ServiceDay, &str), Vec<(&str, &PlacedTrip)>> = HashMap::new();
+    for ((service_day, trip_id), trip) in trips {
+        if let Some(block_id) = &trip.block_id {
+            blocks
+                .entry((*service_day, block_id))
+                .or_default()
+                .push((trip_id, trip));
+        }
+    }
+
+    for mut block in blocks.into_values() {
+        // Trips departing together are ordered by their arrival and then by their id, so the same
+        // feed always links the same trips regardless of the hash map order
+        block.sort_by_key(|(trip_id, trip)| (trip.departure, trip.arrival, *trip_id));
+        for pair in block.windows(2) {
+            let [(from_trip_id, from), (to_trip_id, to)] = pair else {
+                continue;
+            };
+            let is_not_allowed = in_seat_transfers
+                .not_allowed
+                .contains(&(from_trip_id.to_string(), to_trip_id.to_string()));
+            if from.last_stop == to.first_stop && !is_not_allowed {
+                link(from, to);
+            }
+        }
+    }
+
+    let mut continuations: Vec<TripContinuation> = continuation_by_trip.into_values().collect();
+    continuations
+        .sort_by_key(|continuation| (continuation.from_route, continuation.from_trip_number));
+    continuations
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::continuations::{link_continuations, InSeatTransfers, PlacedTrip};
+    use crate::ServiceDay;
+    use raptor::Time;
+    use std::collections::HashMap;
+
+    #[test]
+    fn links_block_trips_departing_together_in_a_fixed_order() {
+        // Arrange
+        let trip = |route: usize, first_stop: usize, departure: u64, arrival: u64| PlacedTrip {
+            route,
+            trip_number: 0,
+            block_id: Some("block".to_string()),
+            first_stop,
+            last_stop: route,
+            departure: Time::from(departure),
+            arrival: Time::from(arrival),
+        };
+        // The trips of routes 1 to 3 depart together from the stop the first trip ends at
+        let trips = HashMap::from([
+            ((ServiceDay::Today, "a".to_string()), trip(0, 4, 100, 200)),
+            ((ServiceDay::Today, "d".to_string()), trip(1, 0, 200, 300)),
+            ((ServiceDay::Today, "c".to_string()), trip(2, 0, 200, 250)),
+            ((ServiceDay::Today, "b".to_string()), trip(3, 0, 200, 250)),
+        ]);
+
+        // Act
+        let continuations = link_continuations(&trips, &InSeatTransfers::default());
+
+        // Assert
+        // The earliest arrival and then the smallest trip id comes first
+        let links: Vec<(usize, usize)> = continuations
+            .iter()
+            .map(|continuation| (continuation.from_route, continuation.to_route))
+            .collect();
+        assert_eq!(vec![(0, 3)], links);
+    }
+}
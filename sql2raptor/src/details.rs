@@ -0,0 +1,116 @@
+//! The GTFS information users need to recognize routes and trips. RAPTOR only works with indices,
+//! so this is kept in side tables next to the timetable.
+
+use libsql::Connection;
+use std::collections::HashMap;
+
+/// A GTFS route. Each RAPTOR route only has trips of one GTFS route
+#[derive(Clone, Debug, PartialEq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct RouteDetails {
+    /// The id in the routes table
+    pub id: String,
+    /// Like "42". Either the short or the long name is set in valid feeds
+    pub short_name: Option<String>,
+    pub long_name: Option<String>,
+    /// The GTFS route type like 3 for bus. None if the route is missing in the feed
+    pub route_type: Option<u32>,
+}
+
+impl RouteDetails {
+    /// Stand-in for routes that trips reference but the feed doesn't have
+    fn unknown(id: String) -> Self {
+        RouteDetails {
+            id,
+            short_name: None,
+            long_name: None,
+            route_type: None,
+        }
+    }
+}
+
+/// A GTFS trip
+#[derive(Clone, Debug, PartialEq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct TripDetails {
+    /// The id in the trips table
+    pub id: String,
+    /// Where the trip is going, like "Central"
+    pub headsign: Option<String>,
+    /// Like a train number. Most trips don't have one
+    pub short_name: Option<String>,
+}
+
+/// The details of the routes and trips in the timetable. Indexed like
+/// [raptor::shared::RoutesData::routes] and then by the trip number of the trip blocks in the stop
+/// times
+#[derive(Default, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct GtfsDetails {
+    pub routes: Vec<RouteDetails>,
+    pub trips: Vec<Vec<TripDetails>>,
+}
+
+impl GtfsDetails {
+    pub fn get_route(&self, route: usize) -> &RouteDetails {
+        &self.routes[route]
+    }
+
+    /// The trip of the route by its trip number. Routes with a headway only have their template
+    /// trip, which stands in for all trips as their trip numbers are departures
+    pub fn get_trip(&self, route: usize, trip_number: usize) -> &TripDetails {
+        match self.trips[route].as_slice() {
+            [template] => template,
+            trips => &trips[trip_number],
+        }
+    }
+
+    /// Adds the route with its trips in the order of their trip numbers
+    pub(crate) fn add_route(&mut self, route: RouteDetails, trips: Vec<TripDetails>) {
+        self.routes.push(route);
+        self.trips.push(trips);
+    }
+}
+
+impl ArchivedGtfsDetails {
+    /// Like [GtfsDetails::get_route] on the details of a snapshot
+    pub fn get_route(&self, route: usize) -> &ArchivedRouteDetails {
+        &self.routes[route]
+    }
+
+    /// Like [GtfsDetails::get_trip] on the details of a snapshot
+    pub fn get_trip(&self, route: usize, trip_number: usize) -> &ArchivedTripDetails {
+        match self.trips[route].as_slice() {
+            [template] => template,
+            trips => &trips[trip_number],
+        }
+    }
+}
+
+/// Loads the routes of the feed by their id
+pub(crate) async fn get_route_details(
+    connection: &Connection,
+) -> Result<HashMap<String, RouteDetails>, libsql::Error> {
+    let mut rows = connection
+        .query("SELECT id, short_name, long_name, type FROM routes;", ())
+        .await?;
+
+    let mut routes_by_id = HashMap::new();
+    while let Some(row) = rows.next().await? {
+        let id: String = row.get(0 /* id */)?;
+        let route = RouteDetails {
+            id: id.clone(),
+            short_name: row.get(1 /* short_name */)?,
+            long_name: row.get(2 /* long_name */)?,
+            route_type: Some(row.get(3 /* type */)?),
+        };
+        routes_by_id.insert(id, route);
+    }
+
+    Ok(routes_by_id)
+}
+
+/// The details of the route with the id or a stand-in if the feed doesn't have it
+pub(crate) fn find_route(routes_by_id: &HashMap<String, RouteDetails>, id: &str) -> RouteDetails {
+    routes_by_id
+        .get(id)
+        .cloned()
+        .unwrap_or_else(|| RouteDetails::unknown(id.to_string()))
+}
@@ -0,0 +1,666 @@
+//! Generates foot-paths between stops that are close to each other for feeds that come without or
+//! with only few transfers
+
+use crate::pathways::is_within_pathway_station;
+use libsql::Connection;
+use raptor::shared::{Stop, StopsData, Transfer};
+use raptor::Time;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Mean radius of the earth in meters
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// The location of a stop in degrees
+#[derive(Clone, Copy, Debug)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// Great-circle distance in meters using the haversine formula
+    fn distance(&self, other: &Coordinates) -> f64 {
+        let latitude = self.latitude.to_radians();
+        let other_latitude = other.latitude.to_radians();
+        let latitude_delta = other_latitude - latitude;
+        let longitude_delta = (other.longitude - self.longitude).to_radians();
+
+        let haversine = (latitude_delta / 2.0).sin().powi(2)
+            + latitude.cos() * other_latitude.cos() * (longitude_delta / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * haversine.sqrt().asin()
+    }
+}
+
+pub struct FootPathSettings {
+    /// Stops within this distance in meters are connected by a foot-path
+    pub radius: f64,
+    /// Walking speed in meters per second
+    pub walking_speed: f64,
+    /// Longest walk in seconds, also through a chain of close stops
+    pub max_walking_time: u64,
+}
+
+impl Default for FootPathSettings {
+    fn default() -> Self {
+        Self {
+            radius: 300.0,
+            walking_speed: 1.2,
+            max_walking_time: 600,
+        }
+    }
+}
+
+/// Get the coordinates of the stops by stop index. Stops without a location are none. Takes the
+/// lookup of stop indices by id, so it works with the owned and the archived RAPTOR data
+pub async fn get_stop_coordinates(
+    connection: &Connection,
+    stop_count: usize,
+    get_stop_index: impl Fn(&str) -> Option<usize>,
+) -> Result<Vec<Option<Coordinates>>, libsql::Error> {
+    let mut rows = connection
+        .query("SELECT id, latitude, longitude FROM stops;", ())
+        .await?;
+
+    let mut coordinates = vec![None; stop_count];
+    while let Some(row) = rows.next().await? {
+        let stop_id: String = row.get(0 /* id */)?;
+        let latitude: Option<f64> = row.get(1 /* latitude */)?;
+        let longitude: Option<f64> = row.get(2 /* longitude */)?;
+
+        if let (Some(stop_index), Some(latitude), Some(longitude)) =
+            (get_stop_index(&stop_id), latitude, longitude)
+        {
+            coordinates[stop_index] = Some(Coordinates {
+                latitude,
+                longitude,
+            });
+        }
+    }
+
+    Ok(coordinates)
+}
+
+/// Get the stops within the walking radius of a location that is not a stop like the origin or
+/// destination of a journey.
+/// Returns the stops and the walking time to or from them
+pub fn get_nearby_stops(
+    coordinates: &[Option<Coordinates>],
+    location: &Coordinates,
+    settings: &FootPathSettings,
+) -> Vec<(usize, Time)> {
+    coordinates
+        .iter()
+        .enumerate()
+        .filter_map(|(stop_index, stop_coordinates)| {
+            let distance = location.distance(stop_coordinates.as_ref()?);
+            if distance > settings.radius {
+                return None;
+            }
+
+            let walking_time = (distance / settings.walking_speed).round() as u64;
+            if walking_time > settings.max_walking_time {
+                return None;
+            }
+
+            Some((stop_index, Time::from(walking_time)))
+        })
+        .collect()
+}
+
+/// Connects all stops within the walking radius of each other.
+/// Returns the foot-paths as (source, target, walking time) in both directions
+fn connect_close_stops(
+    coordinates: &[Option<Coordinates>],
+    settings: &FootPathSettings,
+) -> Vec<(usize, usize, Time)> {
+    let max_latitude = coordinates
+        .iter()
+        .flatten()
+        .map(|coordinates| coordinates.latitude.abs())
+        .fold(0.0, f64::max)
+        // Longitudes are meaningless at the poles
+        .min(89.0);
+
+    // Stops within the radius are at most in the neighbouring cells of the grid. A degree of
+    // longitude is shortest at the latitude furthest away from the equator
+    let cell_height = (settings.radius / EARTH_RADIUS).to_degrees();
+    let cell_width = cell_height / max_latitude.to_radians().cos();
+    let cell = |coordinates: &Coordinates| {
+        (
+            (coordinates.latitude / cell_height).floor() as i64,
+            (coordinates.longitude / cell_width).floor() as i64,
+        )
+    };
+
+    let mut stops_by_cell: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (stop_index, stop_coordinates) in coordinates.iter().enumerate() {
+        if let Some(stop_coordinates) = stop_coordinates {
+            stops_by_cell
+                .entry(cell(stop_coordinates))
+                .or_default()
+                .push(stop_index);
+        }
+    }
+
+    let mut foot_paths = Vec::new();
+    for (source, source_coordinates) in coordinates.iter().enumerate() {
+        let Some(source_coordinates) = source_coordinates else {
+            continue;
+        };
+
+        let (row, column) = cell(source_coordinates);
+        for neighbour in [
+            (row - 1, column - 1),
+            (row - 1, column),
+            (row - 1, column + 1),
+            (row, column - 1),
+            (row, column),
+            (row, column + 1),
+            (row + 1, column - 1),
+            (row + 1, column),
+            (row + 1, column + 1),
+        ] {
+            for &target in stops_by_cell.get(&neighbour).into_iter().flatten() {
+                if target == source {
+                    continue;
+                }
+
+                let Some(target_coordinates) = &coordinates[target] else {
+                    continue;
+                };
+
+                let distance = source_coordinates.distance(target_coordinates);
+                if distance <= settings.radius {
+                    let walking_time = (distance / settings.walking_speed).round() as u64;
+                    foot_paths.push((source, target, Time::from(walking_time)));
+                }
+            }
+        }
+    }
+
+    foot_paths
+}
+
+/// Dijkstra's algorithm to find the shortest walk from the source to every stop reachable within
+/// the maximum walking time. Walks for wheelchair users only take accessible foot-paths, while
+/// other walks skip the foot-paths only meant for wheelchair users
+fn find_shortest_walks(
+    neighbours: &[Vec<Transfer>],
+    source: usize,
+    wheelchair: bool,
+    max_walking_time: Time,
+    walking_times: &mut HashMap<usize, Time>,
+) {
+    walking_times.clear();
+    walking_times.insert(source, Time::from(0));
+    let mut queue = BinaryHeap::from([Reverse((Time::from(0), source))]);
+
+    while let Some(Reverse((walking_time, current))) = queue.pop() {
+        if walking_times
+            .get(&current)
+            .is_some_and(|&best| best < walking_time)
+        {
+            continue;
+        }
+
+        for transfer in &neighbours[current] {
+            let walkable = if wheelchair {
+                transfer.wheelchair_accessible
+            } else {
+                !transfer.wheelchair_only
+            };
+            if !walkable {
+                continue;
+            }
+
+            let arrival = walking_time + transfer.time;
+            // Walks only get longer from here, so this also bounds the search
+            if arrival > max_walking_time {
+                continue;
+            }
+
+            if walking_times
+                .get(&transfer.target)
+                .is_none_or(|&best| arrival < best)
+            {
+                walking_times.insert(transfer.target, arrival);
+                queue.push(Reverse((arrival, transfer.target)));
+            }
+        }
+    }
+}
+
+/// Replaces the foot-paths from each stop with the shortest walk to every stop reachable by foot, as
+/// RAPTOR requires foot-paths to be transitively closed. If the shortest walk for wheelchair users
+/// differs from the shortest walk for everyone else, both are kept. Walks longer than the maximum walking
+/// time are left out to keep the number of foot-paths from growing with the square of the stops.
+pub(crate) fn close_foot_paths(
+    neighbours: &[Vec<Transfer>],
+    max_walking_time: Time,
+) -> Vec<Vec<Transfer>> {
+    // Reused between stops as most stops only reach a few other stops
+    let mut walking_times: HashMap<usize, Time> = HashMap::new();
+    let mut accessible_walking_times: HashMap<usize, Time> = HashMap::new();
+
+    (0..neighbours.len())
+        .map(|source| {
+            find_shortest_walks(
+                neighbours,
+                source,
+                false,
+                max_walking_time,
+                &mut walking_times,
+            );
+            find_shortest_walks(
+                neighbours,
+                source,
+                true,
+                max_walking_time,
+                &mut accessible_walking_times,
+            );
+
+            let mut stop_transfers = Vec::new();
+            let targets = walking_times
+                .keys()
+                .chain(accessible_walking_times.keys())
+                .copied()
+                .collect::<HashSet<_>>();
+            for target in targets {
+                if target == source {
+                    continue;
+                }
+
+                let time = walking_times.get(&target).copied();
+                let accessible_time = accessible_walking_times.get(&target).copied();
+                if time == accessible_time {
+                    if let Some(time) = time {
+                        stop_transfers.push(Transfer {
+                            target,
+                            time,
+                            wheelchair_accessible: true,
+                            wheelchair_only: false,
+                        });
+                    }
+                    continue;
+                }
+
+                if let Some(time) = time {
+                    stop_transfers.push(Transfer {
+                        target,
+                        time,
+                        wheelchair_accessible: false,
+                        wheelchair_only: false,
+                    });
+                }
+                if let Some(accessible_time) = accessible_time {
+                    stop_transfers.push(Transfer {
+                        target,
+                        time: accessible_time,
+                        wheelchair_accessible: true,
+                        wheelchair_only: true,
+                    });
+                }
+            }
+
+            // Keep the order independent of the hash map iteration order
+            stop_transfers.sort_by_key(|transfer| (transfer.target, transfer.time));
+            stop_transfers
+        })
+        .collect()
+}
+
+/// Narrows a generated walk to the riders that have no declared transfer to its target, as the
+/// transfers of the feed are kept as they are. Returns none if all riders of the walk have one
+fn without_declared(walk: Transfer, declared: &[Transfer]) -> Option<Transfer> {
+    let (mut has_general, mut has_wheelchair) = (false, false);
+    for transfer in declared
+        .iter()
+        .filter(|transfer| transfer.target == walk.target)
+    {
+        has_general |= !transfer.wheelchair_only;
+        has_wheelchair |= transfer.wheelchair_accessible;
+    }
+
+    let needs_general = !walk.wheelchair_only && !has_general;
+    let needs_wheelchair = walk.wheelchair_accessible && !has_wheelchair;
+    match (needs_general, needs_wheelchair) {
+        (false, false) => None,
+        (true, false) => Some(Transfer {
+            wheelchair_accessible: false,
+            ..walk
+        }),
+        (false, true) => Some(Transfer {
+            wheelchair_only: true,
+            ..walk
+        }),
+        (true, true) => Some(walk),
+    }
+}
+
+/// Adds foot-paths between all stops within the walking radius to the foot-paths already in the
+/// stops data. RAPTOR requires foot-paths to be transitively closed, so the shortest walk from every
+/// stop to every stop reachable by foot within the maximum walking time is added. The declared
+/// foot-paths like the transfers of the feed are kept unchanged even if they take longer, and walks
+/// are only generated for the stops they don't lead to.
+/// The pathway stations are the stations with pathways of the stops, as for example returned by
+/// loading the RAPTOR data. Wheelchair users only get between their platforms through the pathways.
+pub fn add_generated_foot_paths(
+    stops_data: &mut StopsData,
+    coordinates: &[Option<Coordinates>],
+    pathway_stations: &[Option<usize>],
+    settings: &FootPathSettings,
+) {
+    let declared_transfers = std::mem::take(&mut stops_data.transfers);
+    let declared_of =
+        |stop: &Stop| &declared_transfers[stop.transfers_index_start..][..stop.transfers_count];
+
+    let mut neighbours: Vec<Vec<Transfer>> = stops_data
+        .stops
+        .iter()
+        .map(|stop| declared_of(stop).to_vec())
+        .collect();
+
+    // Walks between close stops are assumed to be along streets without stairs unless they are
+    // within a station with pathways
+    for (source, target, time) in connect_close_stops(coordinates, settings) {
+        neighbours[source].push(Transfer {
+            target,
+            time,
+            wheelchair_accessible: !is_within_pathway_station(pathway_stations, source, target),
+            wheelchair_only: false,
+        });
+    }
+
+    let closed = close_foot_paths(&neighbours, Time::from(settings.max_walking_time));
+    let mut transfers = Vec::new();
+    for (stop, walks) in stops_data.stops.iter_mut().zip(closed) {
+        let declared = declared_of(stop);
+        let start = transfers.len();
+        transfers.extend_from_slice(declared);
+        transfers.extend(
+            walks
+                .into_iter()
+                .filter_map(|walk| without_declared(walk, declared)),
+        );
+
+        stop.transfers_index_start = start;
+        stop.transfers_count = transfers.len() - start;
+    }
+
+    stops_data.transfers = transfers;
+}
+
+/// Saves the foot-paths of the stops data to the generated_transfers table to inspect them or use
+/// them outside of RAPTOR. Existing generated transfers are replaced.
+pub async fn save_generated_transfers(
+    connection: &Connection,
+    stops_data: &StopsData,
+) -> Result<(), libsql::Error> {
+    let transaction = connection.transaction().await?;
+    transaction
+        .execute_batch(
+            "CREATE TABLE IF NOT EXISTS generated_transfers (
+                from_stop_id TEXT NOT NULL,
+                to_stop_id TEXT NOT NULL,
+                walking_time INTEGER NOT NULL,
+                PRIMARY KEY (from_stop_id, to_stop_id),
+                FOREIGN KEY (from_stop_id) REFERENCES stops(id),
+                FOREIGN KEY (to_stop_id) REFERENCES stops(id)
+            );
+            DELETE FROM generated_transfers;",
+        )
+        .await?;
+
+    for stop in &stops_data.stops {
+        let start = stop.transfers_index_start;
+        let end = start + stop.transfers_count;
+        for transfer in &stops_data.transfers[start..end] {
+            // The table keeps one walk between two stops, which is the walk everyone takes
+            if transfer.wheelchair_only {
+                continue;
+            }
+            let Some(walking_time) = transfer.time.seconds() else {
+                continue;
+            };
+
+            transaction
+                .execute(
+                    "INSERT INTO generated_transfers (from_stop_id, to_stop_id, walking_time)
+                    VALUES (:from_stop_id, :to_stop_id, :walking_time);",
+                    libsql::named_params! {
+                        ":from_stop_id": stop.id.as_str(),
+                        ":to_stop_id": stops_data.stops[transfer.target].id.as_str(),
+                        ":walking_time": walking_time,
+                    },
+                )
+                .await?;
+        }
+    }
+
+    transaction.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::footpaths::{
+        add_generated_foot_paths, save_generated_transfers, Coordinates, FootPathSettings,
+    };
+    use raptor::shared::{Stop, StopsData, Transfer};
+    use raptor::Time;
+
+    /// Stops along a meridian with the given distances in meters from the first stop
+    fn stops_along_meridian(distances: &[f64]) -> (StopsData, Vec<Option<Coordinates>>) {
+        let stops = distances
+            .iter()
+            .enumerate()
+            .map(|(index, _)| Stop {
+                id: index.to_string(),
+                transfers_index_start: 0,
+                stop_routes_index_start: 0,
+                transfers_count: 0,
+                stop_routes_count: 0,
+                minimum_change_time: None,
+            })
+            .collect();
+        let coordinates = distances
+            .iter()
+            .map(|distance| {
+                Some(Coordinates {
+                    latitude: 52.0 + (distance / super::EARTH_RADIUS).to_degrees(),
+                    longitude: 13.0,
+                })
+            })
+            .collect();
+
+        let stops_data = StopsData {
+            transfers: Vec::new(),
+            stops,
+            stop_routes: Vec::new(),
+            stop_route_positions: Vec::new(),
+            default_change_time: Time::from(0),
+        };
+
+        (stops_data, coordinates)
+    }
+
+    fn walks(stops_data: &StopsData, stop: usize) -> Vec<(usize, Time)> {
+        let stop = &stops_data.stops[stop];
+        stops_data.transfers[stop.transfers_index_start..][..stop.transfers_count]
+            .iter()
+            .map(|transfer| (transfer.target, transfer.time))
+            .collect()
+    }
+
+    #[test]
+    fn connects_stops_within_radius_transitively() {
+        // Arrange
+        let (mut stops_data, coordinates) = stops_along_meridian(&[0.0, 300.0, 600.0, 2000.0]);
+        let pathway_stations = [None; 4];
+        let settings = FootPathSettings {
+            radius: 400.0,
+            walking_speed: 1.0,
+            max_walking_time: 3600,
+        };
+
+        // Act
+        add_generated_foot_paths(&mut stops_data, &coordinates, &pathway_stations, &settings);
+
+        // Assert
+        assert_eq!(
+            vec![(1, Time::from(300)), (2, Time::from(600))],
+            walks(&stops_data, 0)
+        );
+        assert_eq!(
+            vec![(0, Time::from(300)), (2, Time::from(300))],
+            walks(&stops_data, 1)
+        );
+        assert!(walks(&stops_data, 3).is_empty());
+    }
+
+    #[test]
+    fn stops_walking_at_max_walking_time() {
+        // Arrange
+        let (mut stops_data, coordinates) = stops_along_meridian(&[0.0, 300.0, 600.0, 900.0]);
+        let pathway_stations = [None; 4];
+        let settings = FootPathSettings {
+            radius: 400.0,
+            walking_speed: 1.0,
+            max_walking_time: 650,
+        };
+
+        // Act
+        add_generated_foot_paths(&mut stops_data, &coordinates, &pathway_stations, &settings);
+
+        // Assert
+        assert_eq!(
+            vec![(1, Time::from(300)), (2, Time::from(600))],
+            walks(&stops_data, 0)
+        );
+        assert_eq!(
+            vec![
+                (0, Time::from(300)),
+                (2, Time::from(300)),
+                (3, Time::from(600))
+            ],
+            walks(&stops_data, 1)
+        );
+    }
+
+    #[test]
+    fn keeps_feed_transfers_longer_than_max_walking_time() {
+        // Arrange
+        let (mut stops_data, coordinates) = stops_along_meridian(&[0.0, 100.0, 5000.0]);
+        let pathway_stations = [None; 3];
+        // Like a transfer of the feed to a stop further away than generated walks lead
+        stops_data.transfers.push(Transfer {
+            target: 2,
+            time: Time::from(900),
+            wheelchair_accessible: true,
+            wheelchair_only: false,
+        });
+        stops_data.stops[0].transfers_count = 1;
+
+        // Act
+        add_generated_foot_paths(
+            &mut stops_data,
+            &coordinates,
+            &pathway_stations,
+            &FootPathSettings::default(),
+        );
+
+        // Assert
+        assert_eq!(
+            vec![(2, Time::from(900)), (1, Time::from(83))],
+            walks(&stops_data, 0)
+        );
+        // Generated walks still end at the maximum walking time
+        assert_eq!(vec![(0, Time::from(83))], walks(&stops_data, 1));
+        assert!(walks(&stops_data, 2).is_empty());
+    }
+
+    #[test]
+    fn generates_inaccessible_walks_within_pathway_stations() {
+        // Arrange
+        // Both platforms are in a station whose only pathway between them is stairs
+        let (mut stops_data, coordinates) = stops_along_meridian(&[0.0, 50.0]);
+        let pathway_stations = [Some(2), Some(2)];
+
+        // Act
+        add_generated_foot_paths(
+            &mut stops_data,
+            &coordinates,
+            &pathway_stations,
+            &FootPathSettings::default(),
+        );
+
+        // Assert
+        let transfers: Vec<(usize, bool, bool)> = stops_data
+            .transfers
+            .iter()
+            .map(|transfer| {
+                (
+                    transfer.target,
+                    transfer.wheelchair_accessible,
+                    transfer.wheelchair_only,
+                )
+            })
+            .collect();
+        assert_eq!(vec![(1, false, false), (0, false, false)], transfers);
+    }
+
+    #[tokio::test]
+    async fn saves_generated_transfers() {
+        // Arrange
+        let (mut stops_data, coordinates) = stops_along_meridian(&[0.0, 100.0]);
+        add_generated_foot_paths(
+            &mut stops_data,
+            &coordinates,
+            &[None; 2],
+            &FootPathSettings::default(),
+        );
+        let database = libsql::Builder::new_local(":memory:")
+            .build()
+            .await
+            .unwrap();
+        let connection = database.connect().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE stops (id TEXT PRIMARY KEY);
+                INSERT INTO stops (id) VALUES ('0'), ('1');",
+            )
+            .await
+            .unwrap();
+
+        // Act
+        save_generated_transfers(&connection, &stops_data)
+            .await
+            .unwrap();
+
+        // Assert
+        let mut rows = connection
+            .query(
+                "SELECT from_stop_id, to_stop_id, walking_time
+                FROM generated_transfers
+                ORDER BY from_stop_id",
+                (),
+            )
+            .await
+            .unwrap();
+        let mut saved = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            saved.push((
+                row.get::<String>(0).unwrap(),
+                row.get::<String>(1).unwrap(),
+                row.get::<u64>(2).unwrap(),
+            ));
+        }
+        assert_eq!(
+            vec![
+                ("0".to_string(), "1".to_string(), 83),
+                ("1".to_string(), "0".to_string(), 83),
+            ],
+            saved
+        );
+    }
+}
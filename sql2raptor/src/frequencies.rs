@@ -0,0 +1,69 @@
+//! Trips in frequencies.txt run repeatedly during a time window. Their stop times in the feed are a
+//! template that only gives the times relative to the departure at the first stop.
+
+use libsql::Connection;
+use std::collections::HashMap;
+
+/// A time window in which a trip runs every headway
+pub(crate) struct Frequency {
+    /// Seconds since the start of the service day of the first departure at the first stop
+    pub(crate) start: u64,
+    /// Seconds since the start of the service day before which the trip departs the first stop
+    pub(crate) end: u64,
+    pub(crate) headway: u64,
+    /// Whether the trips depart exactly at the start and every headway after it. Otherwise only
+    /// the headway is known, but not when exactly trips depart
+    pub(crate) exact_times: bool,
+}
+
+impl Frequency {
+    /// The departures at the first stop of the trips running at exact times
+    pub(crate) fn departures(&self) -> impl Iterator<Item = u64> {
+        // A headway of 0 is invalid and would never end
+        let step = self.headway.max(1) as usize;
+        (self.start..self.end).step_by(step)
+    }
+}
+
+/// Loads the frequencies by the id of the trip they repeat ordered by their start
+pub(crate) async fn get_frequencies(
+    connection: &Connection,
+) -> Result<HashMap<String, Vec<Frequency>>, libsql::Error> {
+    let mut rows = connection
+        .query(
+            "SELECT trip_id, start_time_seconds, end_time_seconds, headway_seconds, exact_times
+            FROM frequencies
+            ORDER BY trip_id, start_time_seconds;",
+            (),
+        )
+        .await?;
+
+    let mut frequencies_by_trip_id: HashMap<String, Vec<Frequency>> = HashMap::new();
+    while let Some(row) = rows.next().await? {
+        let trip_id: String = row.get(0 /* trip_id */)?;
+        let frequency = Frequency {
+            start: row.get(1 /* start_time_seconds */)?,
+            end: row.get(2 /* end_time_seconds */)?,
+            headway: row.get(3 /* headway_seconds */)?,
+            // Empty or 0 means the trips don't run at exact times
+            exact_times: row.get::<Option<u32>>(4 /* exact_times */)? == Some(1),
+        };
+        frequencies_by_trip_id
+            .entry(trip_id)
+            .or_default()
+            .push(frequency);
+    }
+
+    Ok(frequencies_by_trip_id)
+}
+
+/// The frequencies of the trip or none if it runs at the times of its stop times
+pub(crate) fn get_trip_frequencies<'a>(
+    frequencies_by_trip_id: &'a HashMap<String, Vec<Frequency>>,
+    trip_id: &str,
+) -> &'a [Frequency] {
+    frequencies_by_trip_id
+        .get(trip_id)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
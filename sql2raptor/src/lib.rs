@@ -1,56 +1,124 @@
 use libsql::Connection;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::identity;
+use std::fmt::{Display, Formatter};
+use raptor::shared::{Headway, Route, RoutesData, Stop, StopTime, StopsData, Timetable, Transfer};
+use raptor::Time;
+use crate::continuations::{get_in_seat_transfers, link_continuations, InSeatTransfers, PlacedTrip};
+use crate::details::{find_route, get_route_details, GtfsDetails, RouteDetails, TripDetails};
+use crate::footpaths::{add_generated_foot_paths, get_stop_coordinates, FootPathSettings};
+use crate::frequencies::{get_frequencies, get_trip_frequencies, Frequency};
+use crate::pathways::{get_pathway_stations, get_pathway_walks, is_within_pathway_station};
+use time::macros::format_description;
+use time::{Date, Weekday};
 
-use std::mem;
-use raptor::shared::{Route, RoutesData, Stop, StopTime, StopsData, Transfer};
+mod continuations;
+pub mod details;
+pub mod footpaths;
+mod frequencies;
+mod pathways;
+pub mod snapshot;
+
+/// Errors while loading the RAPTOR data from the database
+#[derive(Debug)]
+pub enum LoadError {
+    Database(libsql::Error),
+    /// A stop time of the trip references a stop that is not in the stops table
+    UnknownStop { trip_id: String, stop_id: String },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Database(error) => write!(formatter, "Could not query database: {error}"),
+            LoadError::UnknownStop { trip_id, stop_id } => {
+                write!(formatter, "Trip {trip_id} references unknown stop {stop_id}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Database(error) => Some(error),
+            LoadError::UnknownStop { .. } => None,
+        }
+    }
+}
+
+impl From<libsql::Error> for LoadError {
+    fn from(error: libsql::Error) -> Self {
+        LoadError::Database(error)
+    }
+}
 
 struct Trip {
-    id: String,
+    /// The GTFS route the trip belongs to
+    route_id: String,
+    details: TripDetails,
     stop_times: Vec<StopTime>,
+    /// The service day and block of the trip to find the trip it continues as. None for trips
+    /// repeated by frequencies, as their repetitions share the trip id
+    block: Option<(ServiceDay, Option<String>)>,
+}
+
+/// A trip as it is in the feed before its times are completed
+struct FeedTrip {
+    route_id: String,
+    block_id: Option<String>,
+    details: TripDetails,
+}
+
+/// A stop in the stop sequence of a trip together with the pickup and drop off rules at that stop.
+/// Trips with the same stops but different rules can not share a route as the rules are checked
+/// by the stop position in the route
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct PatternStop {
+    stop_index: usize,
+    can_board: bool,
+    can_exit: bool,
+}
+
+impl Trip {
+    /// The times at each stop in the order trips are sorted by
+    fn times(&self) -> impl Iterator<Item = (Time, Time)> + '_ {
+        self.stop_times
+            .iter()
+            .map(|stop_time| (stop_time.departure_time, stop_time.arrival_time))
+    }
 }
 
 impl Eq for Trip {}
-// Implement ord for trip to sort them by departure of first stop
+// Implement ord for trip to sort them by departure of first stop. Trips departing at the same time
+// are sorted by their times at the following stops, so they are not mistaken for overtaking
 impl PartialEq<Self> for Trip {
     fn eq(&self, other: &Self) -> bool {
-        match (self.stop_times.first(), other.stop_times.first()) {
-            (Some(time), Some(other_time)) => time.departure_time.eq(&other_time.departure_time),
-            (Some(_), None) | (None, Some(_)) => false,
-            (None, None) => true,
-        }
+        self.times().eq(other.times())
     }
 }
 
 impl PartialOrd<Self> for Trip {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (self.stop_times.first(), other.stop_times.first()) {
-            (Some(time), Some(other_time)) => {
-                time.departure_time.partial_cmp(&other_time.departure_time)
-            }
-            (Some(_), None) => Some(Ordering::Greater),
-            (None, Some(_)) => Some(Ordering::Less),
-            (None, None) => Some(Ordering::Equal),
-        }
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Trip {
     fn cmp(&self, other: &Self) -> Ordering {
-        match (self.stop_times.first(), other.stop_times.first()) {
-            (Some(time), Some(other_time)) => time.departure_time.cmp(&other_time.departure_time),
-            (Some(_), None) => Ordering::Greater,
-            (None, Some(_)) => Ordering::Less,
-            (None, None) => Ordering::Equal,
-        }
+        self.times().cmp(other.times())
     }
 }
 
+/// The time it takes to change vehicles at stops that have no minimum change time in the feed
+pub const DEFAULT_CHANGE_TIME_SECONDS: u64 = 2 * 60;
+
 pub struct PartialStop {
     id: String,
     transfers_count: usize,
     transfers_index_start: usize,
+    minimum_change_time: Option<Time>,
 }
 
 /// A quick type to bundle the return from loading tops
@@ -61,90 +129,173 @@ pub struct GetStopsReturn {
     pub index_by_stop_id: HashMap<String, usize>,
 }
 
-pub async fn get_stops(connection: &Connection) -> Result<GetStopsReturn, libsql::Error> {
-    //TODO transfers
-    let mut rows = connection.query("SELECT id FROM stops;", ()).await?;
+/// Get the stop indices a transfer declared on the stop applies to. Transfers declared on a station
+/// apply to all its platforms.
+/// Returns none if the stop is unknown otherwise the stop indices and whether the transfer was
+/// declared on a station
+fn get_transfer_stops<'a>(
+    stop_id: &str,
+    index_by_stop_id: &'a HashMap<String, usize>,
+    child_indices_by_parent_id: &'a HashMap<String, Vec<usize>>,
+) -> Option<(&'a [usize], bool)> {
+    if let Some(child_indices) = child_indices_by_parent_id.get(stop_id) {
+        return Some((child_indices, true));
+    }
+
+    index_by_stop_id
+        .get(stop_id)
+        .map(|stop_index| (std::slice::from_ref(stop_index), false))
+}
 
-    // As we don't know the stop index of every target stop yet, we need to complete transfers later
-    let partial_transfers: Vec<(&String, u64)> = Vec::new();
-    let mut stops = Vec::new();
-    let mut stop_index = 0;
-    let mut current_stop_id: Option<String> = None;
+/// Keeps the transfer time unless the new one is declared as specific or more specific. Transfers
+/// declared on stops take precedence over transfers declared on their stations
+fn declare_transfer_time(
+    declared: &mut Option<(Time, bool)>,
+    time: Time,
+    is_declared_on_station: bool,
+) {
+    let is_overridden = match declared {
+        None => true,
+        Some((_, was_declared_on_station)) => *was_declared_on_station || !is_declared_on_station,
+    };
 
+    if is_overridden {
+        *declared = Some((time, is_declared_on_station));
+    }
+}
+
+pub async fn get_stops(connection: &Connection) -> Result<GetStopsReturn, libsql::Error> {
+    let mut rows = connection
+        .query("SELECT id, parent_station, location_type FROM stops;", ())
+        .await?;
+
+    let mut stop_ids: Vec<String> = Vec::new();
+    // Platforms are the stops trips serve in contrast to stations, entrances and other nodes
+    let mut is_platform: Vec<bool> = Vec::new();
     // For reverse lookup of stop indices when assembling route data
     let mut index_by_stop_id = HashMap::new();
+    // Platforms of a station
+    let mut child_indices_by_parent_id: HashMap<String, Vec<usize>> = HashMap::new();
 
-    let mut transfers_index_start: usize = 0;
-    let mut transfers_count: usize = 0;
-
-    loop {
-        match rows.next().await? {
-            None => {
-                // Process last row if there was one (otherwise query result was empty)
-                if let Some(last_id) = current_stop_id {
-                    // Complete last stop
-                    let last_stop = PartialStop {
-                        id: last_id.clone(),
-                        transfers_count,
-                        transfers_index_start,
-                    };
-
-                    stops.push(last_stop);
-                    index_by_stop_id.insert(last_id, stop_index);
-
-                    // No need to advance stop index or pointers anymore
-                }
+    while let Some(row) = rows.next().await? {
+        let stop_id: String = row.get(0 /* id */)?;
+        let parent_station: Option<String> = row.get(1 /* parent_station */)?;
+        let location_type: Option<u32> = row.get(2 /* location_type */)?;
+        let stop_index = stop_ids.len();
+        // Entrances, generic nodes and boarding areas are location types 2, 3 and 4
+        let is_stop_platform = location_type.unwrap_or(0) == 0;
+
+        // Transfers declared on a station only apply to its platforms
+        if let Some(parent_station) = parent_station.filter(|_| is_stop_platform) {
+            child_indices_by_parent_id
+                .entry(parent_station)
+                .or_default()
+                .push(stop_index);
+        }
 
-                break;
+        index_by_stop_id.insert(stop_id.clone(), stop_index);
+        stop_ids.push(stop_id);
+        is_platform.push(is_stop_platform);
+    }
+
+    // Transfers between specific routes or trips are not supported. Transfer type 3 means a
+    // transfer is not possible and the in-seat transfer types 4 and 5 don't involve walking.
+    let mut rows = connection
+        .query(
+            "SELECT from_stop_id, to_stop_id, type, minimum_transfer_time
+            FROM transfers
+            WHERE type IN (0, 1, 2)
+                AND from_stop_id IS NOT NULL
+                AND to_stop_id IS NOT NULL
+                AND from_route_id IS NULL
+                AND to_route_id IS NULL
+                AND from_trip_id IS NULL
+                AND to_trip_id IS NULL;",
+            (),
+        )
+        .await?;
+
+    // The time and whether it was declared on a station by source and target stop index
+    let mut walking_times: HashMap<(usize, usize), Option<(Time, bool)>> = HashMap::new();
+    let mut change_times: Vec<Option<(Time, bool)>> = vec![None; stop_ids.len()];
+
+    while let Some(row) = rows.next().await? {
+        let from_stop_id: String = row.get(0 /* from_stop_id */)?;
+        let to_stop_id: String = row.get(1 /* to_stop_id */)?;
+        let transfer_type: u32 = row.get(2 /* type */)?;
+        // Only required for type 2. The others are assumed to be possible without extra time
+        let time: Time = row
+            .get::<Option<u64>>(3 /* minimum_transfer_time */)?
+            .unwrap_or(0)
+            .into();
+
+        // Ignore transfers for stops we don't know
+        let from = get_transfer_stops(&from_stop_id, &index_by_stop_id, &child_indices_by_parent_id);
+        let to = get_transfer_stops(&to_stop_id, &index_by_stop_id, &child_indices_by_parent_id);
+        let (Some((sources, is_from_station)), Some((targets, is_to_station))) = (from, to) else {
+            continue;
+        };
+        let is_declared_on_station = is_from_station || is_to_station;
+
+        for &source in sources {
+            for &target in targets {
+                if source != target {
+                    let declared = walking_times.entry((source, target)).or_default();
+                    declare_transfer_time(declared, time, is_declared_on_station);
+                } else if transfer_type == 2 {
+                    // Transfers from a stop to itself that require a minimum time are the time it
+                    // takes to change vehicles at that stop
+                    let declared = &mut change_times[source];
+                    declare_transfer_time(declared, time, is_declared_on_station);
+                }
             }
-            Some(row) => {
-                let new_stop_id = row.get(0)?;
-                //let transfer_target_id =//TODO
-                //let transfer_time=//TODO
-
-                current_stop_id = match current_stop_id {
-                    None => Some(new_stop_id),
-                    Some(old_stop_id) if old_stop_id != new_stop_id => {
-                        // Complete stop
-                        let stop = PartialStop {
-                            id: old_stop_id.clone(),
-                            transfers_count,
-                            transfers_index_start,
-                        };
-
-                        stops.push(stop);
-                        index_by_stop_id.insert(old_stop_id, stop_index);
-                        stop_index += 1;
-
-                        // Advance start pointers
-                        //TODO support transfers
-                        transfers_index_start += transfers_count;
-
-                        // Reset counters
-                        transfers_count = 0;
-
-                        Some(new_stop_id)
-                    }
-                    id => id,
-                };
+        }
+    }
+
+    // Transfers.txt doesn't tell whether the walk has stairs, so it is assumed to be accessible
+    // unless it is within a station whose pathways tell how wheelchair users get around
+    let pathway_stations = get_pathway_stations(connection, &index_by_stop_id).await?;
+    let mut transfers_by_source: Vec<Vec<Transfer>> = vec![Vec::new(); stop_ids.len()];
+    for (&(source, target), declared) in &walking_times {
+        if let Some((time, _)) = *declared {
+            transfers_by_source[source].push(Transfer {
+                target,
+                time,
+                wheelchair_accessible: !is_within_pathway_station(&pathway_stations, source, target),
+                wheelchair_only: false,
+            });
+        }
+    }
 
-                // We complete transfers when we have all target stop indices. At this point we only have
-                // transfer source stop index
-                // partial_transfers.push((transfer_target_id, transfer_time));//TODO
-                // transfers_count += 1; //TODO use
+    // Walks through stations where transfers.txt doesn't declare an accessible transfer
+    let pathway_walks = get_pathway_walks(connection, &index_by_stop_id, &is_platform).await?;
+    for (source, walks) in pathway_walks.into_iter().enumerate() {
+        for walk in walks {
+            let is_declared_accessible = walking_times.contains_key(&(source, walk.target))
+                && !is_within_pathway_station(&pathway_stations, source, walk.target);
+            if !is_declared_accessible {
+                transfers_by_source[source].push(walk);
             }
         }
     }
 
-    let mut transfers = Vec::with_capacity(partial_transfers.len());
-    for (target_stop_id, transfer_time) in partial_transfers.into_iter() {
-        // As we iterate all known stops this would only fail if the transfer references a non-existent stop
-        let target_index = index_by_stop_id.get(target_stop_id).unwrap();
+    let mut transfers = Vec::new();
+    let mut stops = Vec::with_capacity(stop_ids.len());
+    for ((id, mut stop_transfers), change_time) in stop_ids
+        .into_iter()
+        .zip(transfers_by_source)
+        .zip(change_times)
+    {
+        // Keep the order independent of the hash map iteration order
+        stop_transfers.sort_by_key(|transfer| (transfer.target, transfer.time));
 
-        transfers.push(Transfer {
-            time: transfer_time.into(),
-            target: *target_index,
+        stops.push(PartialStop {
+            id,
+            transfers_count: stop_transfers.len(),
+            transfers_index_start: transfers.len(),
+            minimum_change_time: change_time.map(|(time, _)| time),
         });
+        transfers.append(&mut stop_transfers);
     }
 
     Ok(GetStopsReturn {
@@ -155,17 +306,343 @@ pub async fn get_stops(connection: &Connection) -> Result<GetStopsReturn, libsql
 }
 
 /// Just a quick struct to bundle return values from get_routes
+#[derive(Default)]
 pub struct GetRoutesReturn {
-    trips_by_stops: HashMap<Vec<usize>, Vec<Trip>>,
-    trips_count: usize,
+    /// Trips by their GTFS route and stop sequence. Trips of different GTFS routes are kept apart
+    /// even if they serve the same stops, so users can be told which route to take. Ordered, so the
+    /// routes are assembled in the same order on every run
+    trips_by_stops: BTreeMap<(String, Vec<PatternStop>), Vec<Trip>>,
+    /// Template trips of frequencies without exact times with their stops and headway
+    headway_trips: Vec<(Vec<PatternStop>, Trip, Headway)>,
+    in_seat_transfers: InSeatTransfers,
     stop_times_count: usize,
     route_stops_count: usize,
 }
 
+impl GetRoutesReturn {
+    /// Adds the trip to the route with the same stop sequence ordered by departure
+    fn add_trip(&mut self, stop_sequence: Vec<PatternStop>, trip: Trip) {
+        // A trip needs at least two stops to get anywhere. Trips from the previous service day can
+        // be left with less after removing the stops served before midnight
+        if stop_sequence.len() < 2 {
+            return;
+        }
+
+        // Counters to know allocation size for final data structure later
+        self.stop_times_count += trip.stop_times.len();
+        self.route_stops_count += stop_sequence.len();
+
+        // Add trip to routes but insert it ordered by departure (impl Ord for Trip takes care of that)
+        let trips = self
+            .trips_by_stops
+            .entry((trip.route_id.clone(), stop_sequence))
+            .or_default();
+
+        // Trips that depart at the same time and have the same sequence of stops can be a
+        // valid option for the user to choose from as the user might consider factors
+        // unknown to us. Although this is very unlikely it is not impossible.
+        // There could also be trips with the same departure time and sequence of stops
+        // where one trip might arrive earlier because the train or bus is faster. (This too
+        // seems unrealistic but is theoretically not impossible)
+        // The times at the following stops decide the order of such trips.
+        // So get the position where it already exists or gets the position where it should
+        // be inserted
+        let position = trips.binary_search(&trip).unwrap_or_else(identity);
+        trips.insert(position, trip);
+    }
+
+    /// Adds the template trip of a headway. Each headway becomes a route of its own
+    fn add_headway_trip(&mut self, stop_sequence: Vec<PatternStop>, trip: Trip, headway: Headway) {
+        if stop_sequence.len() < 2 {
+            return;
+        }
+
+        self.stop_times_count += trip.stop_times.len();
+        self.route_stops_count += stop_sequence.len();
+        self.headway_trips.push((stop_sequence, trip, headway));
+    }
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The service days that are loaded for a date. GTFS times are relative to the start of their
+/// service day and go past 24 hours for trips running after midnight. So trips of the previous day
+/// can still be running and a journey can continue with trips of the next day.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum ServiceDay {
+    Yesterday,
+    Today,
+    Tomorrow,
+}
+
+impl ServiceDay {
+    const ALL: [ServiceDay; 3] = [ServiceDay::Yesterday, ServiceDay::Today, ServiceDay::Tomorrow];
+
+    /// Returns none if the day is outside the supported range of dates
+    fn date(self, today: &Date) -> Option<Date> {
+        match self {
+            ServiceDay::Yesterday => today.previous_day(),
+            ServiceDay::Today => Some(*today),
+            ServiceDay::Tomorrow => today.next_day(),
+        }
+    }
+
+    /// Shifts seconds since the start of this service day to seconds since the start of today.
+    /// Returns none for times before the start of today
+    fn shift(self, seconds: u64) -> Option<u64> {
+        match self {
+            ServiceDay::Yesterday => seconds.checked_sub(SECONDS_PER_DAY),
+            ServiceDay::Today => Some(seconds),
+            ServiceDay::Tomorrow => Some(seconds + SECONDS_PER_DAY),
+        }
+    }
+}
+
+/// The column of the calendar table that flags if a service runs on the weekday
+fn weekday_column(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Monday => "monday",
+        Weekday::Tuesday => "tuesday",
+        Weekday::Wednesday => "wednesday",
+        Weekday::Thursday => "thursday",
+        Weekday::Friday => "friday",
+        Weekday::Saturday => "saturday",
+        Weekday::Sunday => "sunday",
+    }
+}
+
+/// Dates are stored as in GTFS in the YYYYMMDD format which can be compared as text
+fn format_service_date(service_date: &Date) -> String {
+    let format = format_description!("[year][month][day]");
+    service_date
+        .format(format)
+        .expect("Year, month and day are always available on a date")
+}
+
+/// Loads the routes from the trips that run on the service date as well as the trips of the
+/// previous and next service day. All times are relative to the start of the service date.
 pub async fn get_routes(
     connection: &Connection,
     index_by_stop_id: HashMap<String, usize>,
-) -> Result<GetRoutesReturn, libsql::Error> {
+    service_date: &Date,
+) -> Result<GetRoutesReturn, LoadError> {
+    let mut routes = GetRoutesReturn {
+        in_seat_transfers: get_in_seat_transfers(connection).await?,
+        ..GetRoutesReturn::default()
+    };
+    let frequencies_by_trip_id = get_frequencies(connection).await?;
+
+    for service_day in ServiceDay::ALL {
+        let Some(date) = service_day.date(service_date) else {
+            continue;
+        };
+
+        add_service_day_trips(
+            connection,
+            &index_by_stop_id,
+            &frequencies_by_trip_id,
+            &date,
+            service_day,
+            &mut routes,
+        )
+        .await?;
+    }
+
+    Ok(routes)
+}
+
+/// A stop of a trip as it is in the feed. Stops that are not timepoints may have no times
+struct FeedStopTime {
+    stop: PatternStop,
+    arrival_time: Option<u64>,
+    departure_time: Option<u64>,
+    /// Whether the trip and the stop are both known to be wheelchair accessible
+    wheelchair_accessible: bool,
+}
+
+/// Fills in the times of stops without times by interpolating linearly between the closest stops
+/// before and after them with times. The stops in between are assumed to be evenly spaced as the
+/// feed does not tell how far apart they are. Stops before the first or after the last stop with
+/// times can not be interpolated and keep having no times
+fn interpolate_times(stop_times: &mut [FeedStopTime]) {
+    let mut previous_timed: Option<(usize, u64)> = None;
+    for index in 0..stop_times.len() {
+        let Some(arrival_time) = stop_times[index].arrival_time else {
+            continue;
+        };
+
+        if let Some((previous_index, previous_departure)) = previous_timed {
+            let steps = (index - previous_index) as u64;
+            // Saturating in case the feed has times going backwards
+            let duration = arrival_time.saturating_sub(previous_departure);
+            for (step, stop_time) in stop_times[previous_index + 1..index].iter_mut().enumerate() {
+                let time = previous_departure + duration * (step as u64 + 1) / steps;
+                stop_time.arrival_time = Some(time);
+                stop_time.departure_time = Some(time);
+            }
+        }
+
+        previous_timed = stop_times[index]
+            .departure_time
+            .map(|departure_time| (index, departure_time));
+    }
+}
+
+/// The stops of the trip with the times mapped by the function. Stops without times or whose
+/// times can't be mapped are left out
+fn map_trip_times(
+    feed_stop_times: &[FeedStopTime],
+    map_time: impl Fn(u64) -> Option<u64>,
+) -> (Vec<PatternStop>, Vec<StopTime>) {
+    let mut stop_sequence = Vec::with_capacity(feed_stop_times.len());
+    let mut stop_times = Vec::with_capacity(feed_stop_times.len());
+    for feed_stop_time in feed_stop_times {
+        let arrival_time = feed_stop_time.arrival_time.and_then(&map_time);
+        let departure_time = feed_stop_time.departure_time.and_then(&map_time);
+        let (Some(arrival_time), Some(departure_time)) = (arrival_time, departure_time) else {
+            continue;
+        };
+
+        let stop = feed_stop_time.stop;
+        stop_times.push(StopTime {
+            arrival_time: arrival_time.into(),
+            departure_time: departure_time.into(),
+            can_board: stop.can_board,
+            can_exit: stop.can_exit,
+            wheelchair_accessible: feed_stop_time.wheelchair_accessible,
+        });
+        stop_sequence.push(stop);
+    }
+
+    (stop_sequence, stop_times)
+}
+
+/// Completes the trip by filling in missing times and shifting them to the service day. Trips with
+/// frequencies are repeated for each of their departures
+fn add_feed_trip(
+    FeedTrip {
+        route_id,
+        block_id,
+        details,
+    }: FeedTrip,
+    mut feed_stop_times: Vec<FeedStopTime>,
+    frequencies: &[Frequency],
+    service_day: ServiceDay,
+    routes: &mut GetRoutesReturn,
+) {
+    interpolate_times(&mut feed_stop_times);
+
+    if frequencies.is_empty() {
+        // Stops served before midnight by trips of the previous day are in the past. As the stops
+        // are ordered by sequence, these are the first stops of the trip. Stops that are still
+        // without times can not be used either
+        let (stop_sequence, stop_times) =
+            map_trip_times(&feed_stop_times, |time| service_day.shift(time));
+        let trip = Trip {
+            route_id,
+            details,
+            stop_times,
+            block: Some((service_day, block_id)),
+        };
+        routes.add_trip(stop_sequence, trip);
+        return;
+    }
+
+    // The times of the trip are only a template relative to the departure at the first stop
+    let Some(template_departure) = feed_stop_times.first().and_then(|stop| stop.departure_time)
+    else {
+        return;
+    };
+    let relative = |time: u64| time.checked_sub(template_departure);
+
+    for frequency in frequencies {
+        if frequency.exact_times {
+            for departure in frequency.departures() {
+                let (stop_sequence, stop_times) = map_trip_times(&feed_stop_times, |time| {
+                    service_day.shift(relative(time)? + departure)
+                });
+                let trip = Trip {
+                    route_id: route_id.clone(),
+                    details: details.clone(),
+                    stop_times,
+                    block: None,
+                };
+                routes.add_trip(stop_sequence, trip);
+            }
+
+            continue;
+        }
+
+        // Trips of the previous day that departed before midnight are left out
+        let Some(end) = service_day.shift(frequency.end) else {
+            continue;
+        };
+        let start = service_day.shift(frequency.start).unwrap_or(0);
+        let headway = Headway {
+            start: start.into(),
+            end: end.into(),
+            headway: frequency.headway.into(),
+        };
+        let (stop_sequence, stop_times) = map_trip_times(&feed_stop_times, relative);
+        let trip = Trip {
+            route_id: route_id.clone(),
+            details: details.clone(),
+            stop_times,
+            block: None,
+        };
+        routes.add_headway_trip(stop_sequence, trip, headway);
+    }
+}
+
+/// Adds the trips running on the date with their times shifted according to the service day
+async fn add_service_day_trips(
+    connection: &Connection,
+    index_by_stop_id: &HashMap<String, usize>,
+    frequencies_by_trip_id: &HashMap<String, Vec<Frequency>>,
+    date: &Date,
+    service_day: ServiceDay,
+    routes: &mut GetRoutesReturn,
+) -> Result<(), LoadError> {
+    // A service runs on the date if the calendar includes the date and weekday or it was added
+    // (exception type 1) for that date but not if it was removed (exception type 2) for that date.
+    // The weekday is inserted into the query as column names can not be parameters
+    let query = format!(
+        "WITH active_services AS (
+                SELECT service_id
+                FROM calendar
+                WHERE {weekday} = 1 AND start_date <= :date AND end_date >= :date
+                UNION
+                SELECT service_id
+                FROM calendar_dates
+                WHERE date = :date AND exception_type = 1
+                EXCEPT
+                SELECT service_id
+                FROM calendar_dates
+                WHERE date = :date AND exception_type = 2
+            )
+            SELECT
+                stop_times.trip_id,
+                stop_times.stop_id,
+                stop_times.arrival_time_seconds,
+                stop_times.departure_time_seconds,
+                stop_times.pickup_type,
+                stop_times.drop_off_type,
+                trips.route_id,
+                trips.headsign,
+                trips.short_name,
+                trips.block_id,
+                -- Stops without information inherit it from their station
+                trips.wheelchair_accessible = 1
+                    AND COALESCE(NULLIF(stops.wheelchair_boarding, 0), stations.wheelchair_boarding) = 1
+            FROM stop_times
+            JOIN trips ON trips.id = stop_times.trip_id
+            LEFT JOIN stops ON stops.id = stop_times.stop_id
+            LEFT JOIN stops AS stations ON stations.id = stops.parent_station
+            WHERE trips.service_id IN active_services
+            ORDER BY stop_times.trip_id, stop_times.stop_sequence",
+        weekday = weekday_column(date.weekday()),
+    );
+
     // We determine routes ourselves by defining each trip with unique sequence of stops as a route
     let mut rows = connection.query(
         // We need the trip id to reconstruct the route and trip although the RAPTOR algorithm
@@ -174,107 +651,105 @@ pub async fn get_routes(
         // identifying a trip but are not guaranteed to not have collisions so we need to keep
         // track of the trip id.
         // We also need the trip id to group the stop ids as trips
-        "SELECT
-                trip_id,
+        &query,
+        libsql::named_params! {":date": format_service_date(date)}).await?;
+
+    let mut current_trip: Option<(FeedTrip, Vec<FeedStopTime>)> = None;
+    while let Some(row) = rows.next().await? {
+        let next_trip_id: String = row.get(0 /* trip_id */)?;
+        let stop_id: String = row.get(1 /* stop_id */)?;
+        let Some(stop_index) = index_by_stop_id.get(&stop_id) else {
+            return Err(LoadError::UnknownStop {
+                trip_id: next_trip_id,
                 stop_id,
-                arrival_time_seconds,
-                departure_time_seconds
-            FROM stop_times
-            ORDER BY trip_id, departure_time_seconds",
-        ()).await?;
-
-    // Trips by stop id sequence
-    let mut trips_by_stops: HashMap<Vec<usize>, Vec<Trip>> = HashMap::new();
-
-    let mut current_stop_sequence = Vec::new();
-    let mut current_trip: Option<Trip> = None;
-    // Counters to know allocation size for final data structure later
-    let mut trips_count: usize = 0;
-    let mut stop_times_count: usize = 0;
-    let mut route_stops_count: usize = 0;
-    loop {
-        match rows.next().await? {
-            None => {
-                // Is last row?
-                if let Some(last_trip) = current_trip {
-                    // Complete last trip
-                    trips_count += 1;
-                    let stop_sequence = mem::take(&mut current_stop_sequence);
-                    route_stops_count += stop_sequence.len();
-
-                    // Add trip to routes but insert it ordered by departure (impl Ord for Trip takes care of that)
-                    let trips = trips_by_stops.entry(stop_sequence).or_default();
-
-                    let position = trips.binary_search(&last_trip).unwrap_or_else(identity);
-                    trips.insert(position, last_trip);
-                }
+            });
+        };
+        // Type 1 means no pickup or drop off is available. Regular (0 or empty) as well as
+        // having to phone or coordinate with the driver (2 and 3) still allows it
+        let can_board = row.get::<Option<u32>>(4 /* pickup_type */)? != Some(1);
+        let can_exit = row.get::<Option<u32>>(5 /* drop_off_type */)? != Some(1);
+        let arrival_time = row.get::<Option<u64>>(2 /* arrival_time_seconds */)?;
+        let departure_time = row.get::<Option<u64>>(3 /* departure_time_seconds */)?;
+        // Empty, 0 or 2 mean the trip or stop is not known to be accessible
+        let wheelchair_accessible = row.get::<Option<u32>>(10 /* wheelchair_accessible */)? == Some(1);
 
-                break;
-            }
-            Some(row) => {
-                let next_trip_id: String = row.get(0 /* trip_id */)?;
-                let stop_id: String = row.get(1 /* stop_id */)?;
-                // Assume we have all stops that can be referenced or this would reference a non-existent
-                // stop which is undefined behavior but would at least make this route unusable for end users
-                let stop_index = index_by_stop_id.get(&stop_id).unwrap();
-                let stop_time = StopTime {
-                    //TODO check if we did not accidentally swap arrival and departure
-                    arrival_time: row.get::<u64>(3 /* departure_time_seconds */)?.into(),
-                    departure_time: row.get::<u64>(2 /* arrival_time_seconds */)?.into(),
-                };
+        let stop_time = FeedStopTime {
+            stop: PatternStop {
+                stop_index: *stop_index,
+                can_board,
+                can_exit,
+            },
+            // Feeds may only set one of the times when the vehicle doesn't wait at the stop
+            arrival_time: arrival_time.or(departure_time),
+            departure_time: departure_time.or(arrival_time),
+            wheelchair_accessible,
+        };
 
-                current_trip = match current_trip {
-                    None => Some(Trip {
+        match &mut current_trip {
+            // Here we are still on the same trip
+            Some((trip, stop_times)) if trip.details.id == next_trip_id => {
+                stop_times.push(stop_time)
+            }
+            _ => {
+                // Complete current trip and continue with new trip moving forward
+                let new_trip = FeedTrip {
+                    route_id: row.get(6 /* route_id */)?,
+                    block_id: row.get(9 /* block_id */)?,
+                    details: TripDetails {
                         id: next_trip_id,
-                        stop_times: Vec::from([stop_time]),
-                    }),
-                    Some(completed_trip) if completed_trip.id != next_trip_id => {
-                        // Complete current trip
-                        trips_count += 1;
-                        let stop_sequence = mem::take(&mut current_stop_sequence);
-                        route_stops_count += stop_sequence.len();
-
-                        // Add trip to routes but insert it ordered by departure (impl Ord for Trip takes care of that)
-                        let trips = trips_by_stops.entry(stop_sequence).or_default();
-
-                        // Trips that depart at the same time and have the same sequence of stops can be a
-                        // valid option for the user to choose from as the user might consider factors
-                        // unknown to us. Although this is very unlikely it is not impossible.
-                        // There could also be trips with the same departure time and sequence of stops
-                        // where one trip might arrive earlier because the train or bus is faster. (This too
-                        // seems unrealistic but is theoretically not impossible)
-                        // So get the position where it already exists or gets the position where it should
-                        // be inserted
-                        let position = trips
-                            .binary_search(&completed_trip)
-                            .unwrap_or_else(identity);
-                        trips.insert(position, completed_trip);
-
-                        // Continue with new trip moving forward
-                        Some(Trip {
-                            id: next_trip_id,
-                            stop_times: Vec::from([stop_time]),
-                        })
-                    }
-                    // Here we are still on the same trip
-                    Some(mut trip) => {
-                        trip.stop_times.push(stop_time);
-                        Some(trip)
-                    }
+                        headsign: row.get(7 /* headsign */)?,
+                        short_name: row.get(8 /* short_name */)?,
+                    },
                 };
-
-                stop_times_count += 1;
-                current_stop_sequence.push(*stop_index);
+                if let Some((trip, stop_times)) = current_trip.replace((new_trip, Vec::from([stop_time]))) {
+                    let frequencies = get_trip_frequencies(frequencies_by_trip_id, &trip.details.id);
+                    add_feed_trip(trip, stop_times, frequencies, service_day, routes);
+                }
             }
         }
     }
 
-    Ok(GetRoutesReturn {
-        trips_by_stops,
-        trips_count,
-        stop_times_count,
-        route_stops_count,
-    })
+    // Complete last trip
+    if let Some((trip, stop_times)) = current_trip {
+        let frequencies = get_trip_frequencies(frequencies_by_trip_id, &trip.details.id);
+        add_feed_trip(trip, stop_times, frequencies, service_day, routes);
+    }
+
+    Ok(())
+}
+
+/// Whether the later trip never departs or arrives at a stop before the earlier trip
+fn is_not_overtaking(earlier: &Trip, later: &Trip) -> bool {
+    earlier
+        .stop_times
+        .iter()
+        .zip(&later.stop_times)
+        .all(|(earlier, later)| {
+            earlier.arrival_time <= later.arrival_time
+                && earlier.departure_time <= later.departure_time
+        })
+}
+
+/// RAPTOR assumes trips of a route never overtake each other, so the earliest trip at one stop is
+/// the earliest at all following stops. An express and a local trip with the same stops break that
+/// assumption. This splits the trips ordered by departure into groups without overtaking, each
+/// becoming its own route. Trips join the first group whose last trip they don't overtake
+fn split_overtaking_trips(trips_ordered: Vec<Trip>) -> Vec<Vec<Trip>> {
+    let mut groups: Vec<Vec<Trip>> = Vec::new();
+    for trip in trips_ordered {
+        let group = groups.iter_mut().find(|group| {
+            group
+                .last()
+                .is_some_and(|last| is_not_overtaking(last, &trip))
+        });
+
+        match group {
+            Some(group) => group.push(trip),
+            None => groups.push(Vec::from([trip])),
+        }
+    }
+
+    groups
 }
 
 /// Assembles the data from the previous two steps of getting stops and route data into the final
@@ -282,32 +757,29 @@ pub async fn get_routes(
 ///
 /// # Arguments
 ///
-/// * `GetRoutesReturn {trips_by_stops, trips_count, stop_times_count, route_stops_count}`:
-/// * `partial_stops`:
-/// * `transfers`:
-///
-/// returns: (RoutesData, StopsData, Vec<String, Global>)
-///
-/// # Examples
-///
-/// ```
+/// * `GetRoutesReturn {trips_by_stops, headway_trips, in_seat_transfers, stop_times_count, route_stops_count}`:
+/// * `partial_stops`: The stops from [get_stops]
+/// * `transfers`: The foot-paths from [get_stops] ordered by the stop they leave from
+/// * `routes_by_id`: The GTFS routes the trips belong to
 ///
-/// ```
+/// returns: (RoutesData, StopsData, GtfsDetails, overtaking splits)
 pub fn assemble_raptor_data(
     GetRoutesReturn {
         trips_by_stops,
-        trips_count,
+        headway_trips,
+        in_seat_transfers,
         stop_times_count,
         route_stops_count,
     }: GetRoutesReturn,
     partial_stops: Vec<PartialStop>,
     transfers: Vec<Transfer>,
-) -> (RoutesData, StopsData, Vec<String>) {
+    routes_by_id: &HashMap<String, RouteDetails>,
+) -> (RoutesData, StopsData, GtfsDetails, usize) {
     // Final assembly RoutesData
 
-    // Trip ids where the index refers to the number of the block that represents a trip contained in
-    // stop_times. Not relevant for RAPTOR but needed to reconstruct journey
-    let mut trip_ids: Vec<String> = Vec::with_capacity(trips_count);
+    // Route and trip details indexed like the routes and the trips in stop_times. Not relevant for
+    // RAPTOR but needed to tell users which vehicle to take
+    let mut details = GtfsDetails::default();
 
     // Arrays as described in RAPTOR paper Appendix A Data Structures
     let mut stop_times: Vec<StopTime> = Vec::with_capacity(stop_times_count);
@@ -320,41 +792,82 @@ pub fn assemble_raptor_data(
     let mut stop_times_start_index = 0;
 
     // For later final assembly StopsData
-    let mut route_index = 0;
-    let mut route_indices_by_stop_index: HashMap<usize, Vec<usize>> = HashMap::new();
+    // Routes serving a stop together with the position of the stop on the route
+    let mut route_positions_by_stop_index: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
     // To know allocation size later
     let mut stop_routes_count = 0;
 
+    // Trips with the same stops that overtake each other need to be on separate routes
+    let mut overtaking_splits = 0;
+    // Where the trips ended up to link them to the trips they continue as
+    let mut placed_trips = HashMap::new();
+
+    let mut route_groups = Vec::with_capacity(trips_by_stops.len() + headway_trips.len());
+    for ((route_id, pattern), trips_ordered) in trips_by_stops {
+        let trip_groups = split_overtaking_trips(trips_ordered);
+        overtaking_splits += trip_groups.len() - 1;
+        for trips_ordered in trip_groups {
+            route_groups.push((route_id.clone(), pattern.clone(), trips_ordered, None));
+        }
+    }
+    // Each headway becomes a route with the template trip
+    for (pattern, trip, headway) in headway_trips {
+        route_groups.push((trip.route_id.clone(), pattern, Vec::from([trip]), Some(headway)));
+    }
+
     // Go through each route
-    for (mut stop_indices, trips_ordered) in trips_by_stops.into_iter() {
-        let number_of_stops = stop_indices.len();
+    for (route_id, pattern, trips_ordered, headway) in route_groups {
+        let route_details = find_route(routes_by_id, &route_id);
+        let route_index = routes.len();
+        let number_of_stops = pattern.len();
+        // The pickup and drop off rules are already part of the stop times
+        let mut stop_indices: Vec<usize> = pattern.iter().map(|stop| stop.stop_index).collect();
 
-        let length = stop_indices.len();
-        stop_routes_count += length;
+        stop_routes_count += number_of_stops;
         // Need to find out what routes arrive at what stop later for StopsData
-        for index in 0..length {
-            let stop_index = stop_indices[index];
+        for (position, &stop_index) in stop_indices.iter().enumerate() {
             // Add for StopsData construction later
-            let routes = route_indices_by_stop_index
-                .entry(stop_index.clone())
+            let routes = route_positions_by_stop_index
+                .entry(stop_index)
                 .or_default();
-            routes.push(route_index);
+            routes.push((route_index, position));
         }
 
+        let (first_stop, last_stop) = (stop_indices[0], stop_indices[number_of_stops - 1]);
         // Route Stops
         route_stops.append(&mut stop_indices);
 
         let number_of_trips = trips_ordered.len();
+        let mut trip_details = Vec::with_capacity(number_of_trips);
         // Stop Times
-        for Trip {
-            stop_times: mut trip_stop_times,
-            id,
-        } in trips_ordered.into_iter()
+        for (
+            trip_number,
+            Trip {
+                stop_times: mut trip_stop_times,
+                details,
+                block,
+                ..
+            },
+        ) in trips_ordered.into_iter().enumerate()
         {
+            if let Some((service_day, block_id)) = block {
+                let placed_trip = PlacedTrip {
+                    route: route_index,
+                    trip_number,
+                    block_id,
+                    first_stop,
+                    last_stop,
+                    departure: trip_stop_times[0].departure_time,
+                    arrival: trip_stop_times[number_of_stops - 1].arrival_time,
+                };
+                placed_trips.insert((service_day, details.id.clone()), placed_trip);
+            }
+
             stop_times.append(&mut trip_stop_times);
 
-            trip_ids.push(id);
+            trip_details.push(details);
         }
+        details.add_route(route_details, trip_details);
 
         // Complete route
         routes.push(Route {
@@ -362,8 +875,8 @@ pub fn assemble_raptor_data(
             number_of_stops,
             route_stops_start_index,
             stop_times_start_index,
+            headway,
         });
-        route_index += 1;
 
         // Advance pointers
         route_stops_start_index += number_of_stops;
@@ -374,30 +887,37 @@ pub fn assemble_raptor_data(
         stop_times,
         routes,
         route_stops,
+        continuations: link_continuations(&placed_trips, &in_seat_transfers),
     };
 
     // Final assembly StopsData
     let mut stop_routes = Vec::with_capacity(stop_routes_count);
+    let mut stop_route_positions = Vec::with_capacity(stop_routes_count);
     let stops_length = partial_stops.len();
     let mut stops = Vec::with_capacity(stops_length);
-    let mut stop_index: usize = 0;
-
     let mut stop_routes_index_start = 0;
 
-    for PartialStop {
-        id,
-        transfers_count,
-        transfers_index_start,
-    } in partial_stops.into_iter()
+    for (
+        stop_index,
+        PartialStop {
+            id,
+            transfers_count,
+            transfers_index_start,
+            minimum_change_time,
+        },
+    ) in partial_stops.into_iter().enumerate()
     {
         // There indeed exist stops where no one stops in GTFS
         // Maybe they are stations that group stops but the ones I have encountered so far aren't
-        let route_indices = route_indices_by_stop_index.remove(&stop_index);
+        let route_positions = route_positions_by_stop_index.remove(&stop_index);
 
-        let stop_routes_count = match route_indices {
-            Some(mut route_indices) => {
-                let count = route_indices.len();
-                stop_routes.append(&mut route_indices);
+        let stop_routes_count = match route_positions {
+            Some(route_positions) => {
+                let count = route_positions.len();
+                for (route_index, position) in route_positions {
+                    stop_routes.push(route_index);
+                    stop_route_positions.push(position);
+                }
                 count
             }
             None => 0,
@@ -410,9 +930,9 @@ pub fn assemble_raptor_data(
             stop_routes_index_start,
             transfers_count,
             stop_routes_count,
+            minimum_change_time,
         });
 
-        stop_index += 1;
         // Advance pointer
         stop_routes_index_start += stop_routes_count;
     }
@@ -421,32 +941,803 @@ pub fn assemble_raptor_data(
         transfers,
         stops,
         stop_routes,
+        stop_route_positions,
+        default_change_time: DEFAULT_CHANGE_TIME_SECONDS.into(),
     };
 
-    (routes_data, stops_data, trip_ids)
+    (routes_data, stops_data, details, overtaking_splits)
 }
 
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct RaptorDataSet {
     pub index_by_stop_id: HashMap<String, usize>,
-    pub routes_data: RoutesData,
-    pub stops_data: StopsData,
+    pub timetable: Timetable,
+    /// The GTFS routes and trips behind the indices of the timetable
+    pub details: GtfsDetails,
+    /// How many times trips with the same stops had to be split into another route because they
+    /// overtake each other. Tells how far the feed is from having FIFO routes
+    pub overtaking_splits: usize,
 }
-pub async fn setup_raptor(connection: &libsql::Connection) -> Result<RaptorDataSet, libsql::Error> {
+/// Loads the RAPTOR data for the trips that run around the service date. Times are seconds since
+/// the start of the service date, so trips of the previous day that run past midnight are included
+/// and journeys can continue with trips of the next day.
+pub async fn setup_raptor(
+    connection: &libsql::Connection,
+    service_date: &Date,
+) -> Result<RaptorDataSet, LoadError> {
     let GetStopsReturn {
         transfers,
         stops: partial_stops,
         index_by_stop_id,
-    } = get_stops(&connection).await?;
+    } = get_stops(connection).await?;
 
     let step_2_result = get_routes(
-        &connection,
+        connection,
         //TODO remove debug clone clown
         index_by_stop_id.clone(),
+        service_date,
     ).await?;
+    let routes_by_id = get_route_details(connection).await?;
+
+    let (routes_data, stops_data, details, overtaking_splits) =
+        assemble_raptor_data(step_2_result, partial_stops, transfers, &routes_by_id);
+
+    Ok(RaptorDataSet {
+        index_by_stop_id,
+        timetable: Timetable { routes_data, stops_data },
+        details,
+        overtaking_splits,
+    })
+}
+
+/// Loads the RAPTOR data like [setup_raptor] and adds the foot-paths generated between close stops,
+/// so the data is the same as in the snapshots the sql2raptor binary writes
+pub async fn setup_raptor_with_foot_paths(
+    connection: &libsql::Connection,
+    service_date: &Date,
+    settings: &FootPathSettings,
+) -> Result<RaptorDataSet, LoadError> {
+    let mut data = setup_raptor(connection, service_date).await?;
+    let stop_coordinates = get_stop_coordinates(connection, data.index_by_stop_id.len(), |stop_id| data.index_by_stop_id.get(stop_id).copied()).await?;
+    let pathway_stations = get_pathway_stations(connection, &data.index_by_stop_id).await?;
+    add_generated_foot_paths(&mut data.timetable.stops_data, &stop_coordinates, &pathway_stations, settings);
+    Ok(data)
+}
+#[cfg(test)]
+mod tests {
+    use crate::{get_stops, setup_raptor, LoadError, RaptorDataSet};
+    use raptor::Time;
+    use time::macros::date;
+
+    /// Creates an in memory database with the tables needed to load the RAPTOR data filled with the
+    /// given data
+    async fn database(data: &str) -> libsql::Connection {
+        let database = libsql::Builder::new_local(":memory:").build().await.unwrap();
+        let connection = database.connect().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE stops (
+                    id TEXT PRIMARY KEY,
+                    parent_station TEXT,
+                    location_type INTEGER,
+                    wheelchair_boarding INTEGER
+                );
+                CREATE TABLE transfers (
+                    from_stop_id TEXT,
+                    to_stop_id TEXT,
+                    from_route_id TEXT,
+                    to_route_id TEXT,
+                    from_trip_id TEXT,
+                    to_trip_id TEXT,
+                    type INTEGER,
+                    minimum_transfer_time INTEGER
+                );
+                CREATE TABLE routes (
+                    id TEXT PRIMARY KEY,
+                    short_name TEXT,
+                    long_name TEXT,
+                    type INTEGER NOT NULL
+                );
+                -- Most tests don't care which route a trip belongs to
+                CREATE TABLE trips (
+                    id TEXT PRIMARY KEY,
+                    route_id TEXT NOT NULL DEFAULT 'route',
+                    service_id TEXT NOT NULL,
+                    headsign TEXT,
+                    short_name TEXT,
+                    block_id TEXT,
+                    wheelchair_accessible INTEGER
+                );
+                CREATE TABLE stop_times (
+                    trip_id TEXT NOT NULL,
+                    stop_id TEXT,
+                    stop_sequence INTEGER,
+                    arrival_time_seconds INTEGER,
+                    departure_time_seconds INTEGER,
+                    pickup_type INTEGER,
+                    drop_off_type INTEGER
+                );
+                CREATE TABLE calendar (
+                    service_id TEXT PRIMARY KEY NOT NULL,
+                    monday BOOLEAN NOT NULL,
+                    tuesday BOOLEAN NOT NULL,
+                    wednesday BOOLEAN NOT NULL,
+                    thursday BOOLEAN NOT NULL,
+                    friday BOOLEAN NOT NULL,
+                    saturday BOOLEAN NOT NULL,
+                    sunday BOOLEAN NOT NULL,
+                    start_date DATE NOT NULL,
+                    end_date DATE NOT NULL
+                );
+                CREATE TABLE frequencies (
+                    trip_id TEXT NOT NULL,
+                    start_time_seconds INTEGER NOT NULL,
+                    end_time_seconds INTEGER NOT NULL,
+                    headway_seconds INTEGER NOT NULL,
+                    exact_times INTEGER
+                );
+                CREATE TABLE calendar_dates (
+                    service_id TEXT NOT NULL,
+                    date DATE NOT NULL,
+                    exception_type INTEGER NOT NULL
+                );
+                CREATE TABLE pathways (
+                    id TEXT PRIMARY KEY,
+                    from_stop_id TEXT NOT NULL,
+                    to_stop_id TEXT NOT NULL,
+                    mode INTEGER NOT NULL,
+                    is_bidirectional BOOLEAN NOT NULL,
+                    length REAL,
+                    traversal_time INTEGER
+                );",
+            )
+            .await
+            .unwrap();
+        connection.execute_batch(data).await.unwrap();
+
+        connection
+    }
+
+    /// Creates a database with two trips between the same stops. One runs on weekdays and one on
+    /// Sundays. A night trip on Sundays runs past midnight
+    async fn calendar_database() -> libsql::Connection {
+        database(
+            "INSERT INTO stops (id) VALUES ('a'), ('b'), ('c');
+                INSERT INTO trips (id, service_id) VALUES
+                    ('weekday', 'weekdays'),
+                    ('sunday', 'sundays'),
+                    ('night', 'sundays');
+                INSERT INTO stop_times VALUES
+                    ('weekday', 'a', 1, 100, 100, NULL, NULL),
+                    ('weekday', 'b', 2, 200, 200, NULL, NULL),
+                    ('sunday', 'a', 1, 300, 300, NULL, NULL),
+                    ('sunday', 'b', 2, 400, 400, NULL, NULL),
+                    ('night', 'a', 1, 85800, 85800, NULL, NULL),
+                    ('night', 'b', 2, 87000, 87000, NULL, NULL),
+                    ('night', 'c', 3, 87600, 87600, NULL, NULL);
+                INSERT INTO calendar VALUES
+                    ('weekdays', 1, 1, 1, 1, 1, 0, 0, '20240101', '20241231'),
+                    ('sundays', 0, 0, 0, 0, 0, 0, 1, '20240101', '20241231');
+                -- Christmas day runs the Sunday service
+                INSERT INTO calendar_dates VALUES
+                    ('weekdays', '20241225', 2),
+                    ('sundays', '20241225', 1);",
+        )
+        .await
+    }
+
+    /// The departures of all trips at their first stop in ascending order
+    fn first_departures(data: &RaptorDataSet) -> Vec<Time> {
+        let routes_data = &data.timetable.routes_data;
+        let mut departures: Vec<Time> = routes_data
+            .routes
+            .iter()
+            .flat_map(|route| {
+                (0..route.number_of_trips).map(|trip_number| {
+                    let index = route.stop_times_start_index + trip_number * route.number_of_stops;
+                    routes_data.stop_times[index].departure_time
+                })
+            })
+            .collect();
+        departures.sort();
+        departures
+    }
+
+    #[tokio::test]
+    async fn loads_trips_running_on_weekday() {
+        // Arrange
+        let connection = calendar_database().await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 12 - 24)).await.unwrap();
+
+        // Assert
+        // Christmas day is the next day and runs the Sunday service
+        assert_eq!(
+            vec![Time::from(100), Time::from(86_700), Time::from(172_200)],
+            first_departures(&data)
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_calendar_date_exceptions() {
+        // Arrange
+        let connection = calendar_database().await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 12 - 25)).await.unwrap();
+
+        // Assert
+        // The weekday trip of the next day is still included
+        assert_eq!(
+            vec![Time::from(300), Time::from(85_800), Time::from(86_500)],
+            first_departures(&data)
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_dates_outside_calendar() {
+        // Arrange
+        let connection = calendar_database().await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2025 - 01 - 07)).await.unwrap();
+
+        // Assert
+        assert!(data.timetable.routes_data.routes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn continues_previous_day_trips_past_midnight() {
+        // Arrange
+        let connection = calendar_database().await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 12 - 23)).await.unwrap();
+
+        // Assert
+        // The night trip from Sunday can only be boarded after midnight at its second stop
+        assert_eq!(
+            vec![Time::from(100), Time::from(600), Time::from(86_500)],
+            first_departures(&data)
+        );
+        let night_route = data
+            .timetable
+            .routes_data
+            .routes
+            .iter()
+            .find(|route| route.number_of_stops == 2 && route.number_of_trips == 1)
+            .unwrap();
+        let night_stops = &data.timetable.routes_data.route_stops
+            [night_route.route_stops_start_index..][..night_route.number_of_stops];
+        assert_eq!(
+            vec![data.index_by_stop_id["b"], data.index_by_stop_id["c"]],
+            night_stops
+        );
+    }
+
+    #[tokio::test]
+    async fn orders_stops_by_sequence_and_interpolates_missing_times() {
+        // Arrange
+        // Inserted out of order with two stops departing at the same time, a stop that is not a
+        // timepoint in between and one after the last timepoint
+        let connection = database(
+            "INSERT INTO stops (id) VALUES ('a'), ('b'), ('c'), ('d'), ('e');
+                INSERT INTO trips (id, service_id) VALUES ('trip', 'daily');
+                INSERT INTO stop_times VALUES
+                    ('trip', 'd', 4, 300, 320, NULL, NULL),
+                    ('trip', 'b', 2, 100, 160, NULL, NULL),
+                    ('trip', 'e', 5, NULL, NULL, NULL, NULL),
+                    ('trip', 'c', 3, NULL, NULL, NULL, NULL),
+                    ('trip', 'a', 1, NULL, 100, NULL, NULL);
+                INSERT INTO calendar VALUES
+                    ('daily', 1, 1, 1, 1, 1, 1, 1, '20240101', '20241231');",
+        )
+        .await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 09 - 10)).await.unwrap();
+
+        // Assert
+        let routes_data = &data.timetable.routes_data;
+        let index = |id: &str| data.index_by_stop_id[id];
+        // The trip of the next day is on the same route
+        let route = &routes_data.routes[0];
+        assert_eq!(1, routes_data.routes.len());
+        assert_eq!(
+            vec![index("a"), index("b"), index("c"), index("d")],
+            routes_data.route_stops[route.route_stops_start_index..][..route.number_of_stops]
+        );
+        let trip =
+            &routes_data.stop_times[route.stop_times_start_index..][..route.number_of_stops];
+        let times: Vec<(Time, Time)> = trip
+            .iter()
+            .map(|stop_time| (stop_time.arrival_time, stop_time.departure_time))
+            .collect();
+        assert_eq!(
+            vec![
+                (Time::from(100), Time::from(100)),
+                (Time::from(100), Time::from(160)),
+                (Time::from(230), Time::from(230)),
+                (Time::from(300), Time::from(320)),
+            ],
+            times
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_trips_departing_together_on_one_route() {
+        // Arrange
+        // Both trips depart at the same time but the slow one arrives later
+        let connection = database(
+            "INSERT INTO stops (id) VALUES ('a'), ('b');
+                INSERT INTO trips (id, service_id) VALUES
+                    ('fast', 'daily'),
+                    ('slow', 'daily');
+                INSERT INTO stop_times VALUES
+                    ('fast', 'a', 1, 100, 100, NULL, NULL),
+                    ('fast', 'b', 2, 200, 200, NULL, NULL),
+                    ('slow', 'a', 1, 100, 100, NULL, NULL),
+                    ('slow', 'b', 2, 300, 300, NULL, NULL);
+                INSERT INTO calendar VALUES
+                    ('daily', 1, 1, 1, 1, 1, 1, 1, '20240101', '20241231');",
+        )
+        .await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 09 - 10)).await.unwrap();
+
+        // Assert
+        assert_eq!(0, data.overtaking_splits);
+        let routes_data = &data.timetable.routes_data;
+        assert_eq!(1, routes_data.routes.len());
+        let arrivals: Vec<Time> = routes_data
+            .stop_times
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|stop_time| stop_time.arrival_time)
+            .collect();
+        assert_eq!(
+            vec![
+                Time::from(200),
+                Time::from(300),
+                Time::from(86_600),
+                Time::from(86_700)
+            ],
+            arrivals
+        );
+    }
+
+    #[tokio::test]
+    async fn splits_overtaking_trips_into_routes() {
+        // Arrange
+        // The express departs after the local trip but arrives before it
+        let connection = database(
+            "INSERT INTO stops (id) VALUES ('a'), ('b');
+                INSERT INTO trips (id, service_id) VALUES
+                    ('local', 'daily'),
+                    ('express', 'daily'),
+                    ('later local', 'daily');
+                INSERT INTO stop_times VALUES
+                    ('local', 'a', 1, 100, 100, NULL, NULL),
+                    ('local', 'b', 2, 400, 400, NULL, NULL),
+                    ('express', 'a', 1, 150, 150, NULL, NULL),
+                    ('express', 'b', 2, 250, 250, NULL, NULL),
+                    ('later local', 'a', 1, 200, 200, NULL, NULL),
+                    ('later local', 'b', 2, 500, 500, NULL, NULL);
+                INSERT INTO calendar VALUES
+                    ('daily', 1, 1, 1, 1, 1, 1, 1, '20240101', '20241231');",
+        )
+        .await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 09 - 10)).await.unwrap();
+
+        // Assert
+        // The trips of the next day don't overtake the trips of the service date
+        assert_eq!(1, data.overtaking_splits);
+        let routes_data = &data.timetable.routes_data;
+        let mut first_departures: Vec<Vec<Time>> = routes_data
+            .routes
+            .iter()
+            .map(|route| {
+                (0..route.number_of_trips)
+                    .map(|trip_number| {
+                        let index =
+                            route.stop_times_start_index + trip_number * route.number_of_stops;
+                        routes_data.stop_times[index].departure_time
+                    })
+                    .collect()
+            })
+            .collect();
+        first_departures.sort();
+        assert_eq!(
+            vec![
+                vec![
+                    Time::from(100),
+                    Time::from(200),
+                    Time::from(86_500),
+                    Time::from(86_600)
+                ],
+                vec![Time::from(150), Time::from(86_550)],
+            ],
+            first_departures
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_route_and_trip_details() {
+        // Arrange
+        // Both routes serve the same stops
+        let connection = database(
+            "INSERT INTO stops (id) VALUES ('a'), ('b');
+                INSERT INTO routes VALUES ('bus', '42', 'Ring', 3), ('tram', NULL, 'Harbour', 0);
+                INSERT INTO trips VALUES
+                    ('bus trip', 'bus', 'daily', 'Central', NULL, NULL, NULL),
+                    ('tram trip', 'tram', 'daily', NULL, '7', NULL, NULL);
+                INSERT INTO stop_times VALUES
+                    ('bus trip', 'a', 1, 100, 100, NULL, NULL),
+                    ('bus trip', 'b', 2, 200, 200, NULL, NULL),
+                    ('tram trip', 'a', 1, 150, 150, NULL, NULL),
+                    ('tram trip', 'b', 2, 250, 250, NULL, NULL);
+                INSERT INTO calendar VALUES
+                    ('daily', 1, 1, 1, 1, 1, 1, 1, '20240101', '20241231');",
+        )
+        .await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 09 - 10)).await.unwrap();
+
+        // Assert
+        assert_eq!(2, data.timetable.routes_data.routes.len());
+        let bus = data
+            .details
+            .routes
+            .iter()
+            .position(|route| route.id == "bus")
+            .unwrap();
+        assert_eq!(Some("42"), data.details.get_route(bus).short_name.as_deref());
+        assert_eq!(Some(3), data.details.get_route(bus).route_type);
+        // The trip of the service date comes before the trip of the next day
+        let trip = data.details.get_trip(bus, 0);
+        assert_eq!("bus trip", trip.id);
+        assert_eq!(Some("Central"), trip.headsign.as_deref());
+        let tram = 1 - bus;
+        assert_eq!(Some("7"), data.details.get_trip(tram, 0).short_name.as_deref());
+    }
+
+    #[tokio::test]
+    async fn links_trips_continuing_with_the_same_vehicle() {
+        // Arrange
+        // The ring continues as the express in their block. The express can't continue as the late
+        // trip as it ends somewhere else and riders of the local must get off before the ring. The
+        // feeder declares an in-seat transfer to the ring
+        let connection = database(
+            "INSERT INTO stops (id) VALUES ('a'), ('b'), ('c');
+                INSERT INTO trips (id, service_id, block_id) VALUES
+                    ('ring', 'daily', 'block'),
+                    ('express', 'daily', 'block'),
+                    ('late', 'daily', 'block'),
+                    ('local', 'daily', 'block'),
+                    ('feeder', 'daily', NULL);
+                INSERT INTO stop_times VALUES
+                    ('local', 'b', 1, 0, 0, NULL, NULL),
+                    ('local', 'a', 2, 50, 50, NULL, NULL),
+                    ('ring', 'a', 1, 100, 100, NULL, NULL),
+                    ('ring', 'b', 2, 200, 200, NULL, NULL),
+                    ('express', 'b', 1, 300, 300, NULL, NULL),
+                    ('express', 'c', 2, 400, 400, NULL, NULL),
+                    ('late', 'a', 1, 500, 500, NULL, NULL),
+                    ('late', 'b', 2, 600, 600, NULL, NULL),
+                    ('feeder', 'c', 1, 0, 0, NULL, NULL),
+                    ('feeder', 'a', 2, 90, 90, NULL, NULL);
+                INSERT INTO transfers (from_trip_id, to_trip_id, type) VALUES
+                    ('feeder', 'ring', 4),
+                    ('local', 'ring', 5);
+                INSERT INTO calendar VALUES
+                    ('daily', 1, 1, 1, 1, 1, 1, 1, '20240101', '20241231');",
+        )
+        .await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 09 - 10)).await.unwrap();
 
-    //TODO check if trip ids are needed
-    let (routes_data, stops_data, _trip_ids) =
-        assemble_raptor_data(step_2_result, partial_stops, transfers);
+        // Assert
+        let trip_id = |route: usize, trip_number: usize| {
+            data.details.get_trip(route, trip_number).id.as_str()
+        };
+        let continuations = &data.timetable.routes_data.continuations;
+        let mut linked: Vec<(&str, &str)> = continuations
+            .iter()
+            .map(|continuation| {
+                (
+                    trip_id(continuation.from_route, continuation.from_trip_number),
+                    trip_id(continuation.to_route, continuation.to_trip_number),
+                )
+            })
+            .collect();
+        // Today's and tomorrow's trips continue as the trips of the same day
+        assert_eq!(4, linked.len());
+        linked.sort();
+        linked.dedup();
+        assert_eq!(vec![("feeder", "ring"), ("ring", "express")], linked);
+    }
+
+    #[tokio::test]
+    async fn expands_frequencies() {
+        // Arrange
+        // The times of trips with frequencies are templates that only count relative to the first
+        // departure
+        let connection = database(
+            "INSERT INTO stops (id) VALUES ('a'), ('b'), ('c'), ('d');
+                INSERT INTO trips (id, service_id) VALUES ('exact', 'daily'), ('headway', 'daily');
+                INSERT INTO stop_times VALUES
+                    ('exact', 'a', 1, 36000, 36000, NULL, NULL),
+                    ('exact', 'b', 2, 36300, 36300, NULL, NULL),
+                    ('headway', 'c', 1, 50000, 50000, NULL, NULL),
+                    ('headway', 'd', 2, 50300, 50300, NULL, NULL);
+                INSERT INTO frequencies VALUES
+                    ('exact', 28800, 30600, 600, 1),
+                    ('headway', 36000, 43200, 900, 0);
+                INSERT INTO calendar VALUES
+                    ('daily', 1, 1, 1, 1, 1, 1, 1, '20240101', '20241231');",
+        )
+        .await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 09 - 10)).await.unwrap();
+
+        // Assert
+        let routes_data = &data.timetable.routes_data;
+        let (headway_routes, scheduled_routes): (Vec<_>, Vec<_>) = routes_data
+            .routes
+            .iter()
+            .copied()
+            .partition(|route| route.headway.is_some());
+        // The trips of the next day are on the same route
+        assert_eq!(1, scheduled_routes.len());
+        assert_eq!(
+            vec![
+                Time::from(28_800),
+                Time::from(29_400),
+                Time::from(30_000),
+                Time::from(115_200),
+                Time::from(115_800),
+                Time::from(116_400)
+            ],
+            // The templates of the headways depart at 0
+            first_departures(&data)[2..]
+        );
+        // The headway of the next day is a route of its own
+        assert_eq!(2, headway_routes.len());
+        let route = headway_routes
+            .iter()
+            .find(|route| route.headway.unwrap().start == Time::from(36_000))
+            .unwrap();
+        let headway = route.headway.unwrap();
+        assert_eq!(Time::from(43_200), headway.end);
+        assert_eq!(Time::from(900), headway.headway);
+        let template =
+            &routes_data.stop_times[route.stop_times_start_index..][..route.number_of_stops];
+        assert_eq!(Time::from(0), template[0].departure_time);
+        assert_eq!(Time::from(300), template[1].arrival_time);
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_stop() {
+        // Arrange
+        let connection = database(
+            "INSERT INTO stops (id) VALUES ('a');
+                INSERT INTO trips (id, service_id) VALUES ('trip', 'daily');
+                INSERT INTO stop_times VALUES
+                    ('trip', 'a', 1, 100, 100, NULL, NULL),
+                    ('trip', 'missing', 2, 200, 200, NULL, NULL);
+                INSERT INTO calendar VALUES
+                    ('daily', 1, 1, 1, 1, 1, 1, 1, '20240101', '20241231');",
+        )
+        .await;
+
+        // Act
+        let result = setup_raptor(&connection, &date!(2024 - 09 - 10)).await;
+
+        // Assert
+        assert!(matches!(
+            result,
+            Err(LoadError::UnknownStop { trip_id, stop_id }) if trip_id == "trip" && stop_id == "missing"
+        ));
+    }
+
+    #[tokio::test]
+    async fn expands_station_transfers_to_platforms() {
+        // Arrange
+        let connection = database(
+            "INSERT INTO stops (id, parent_station) VALUES
+                ('station', NULL),
+                ('platform 1', 'station'),
+                ('platform 2', 'station'),
+                ('bus stop', NULL);
+            INSERT INTO stops (id, parent_station, location_type) VALUES
+                ('entrance', 'station', 2);
+            INSERT INTO transfers (from_stop_id, to_stop_id, type, minimum_transfer_time) VALUES
+                ('station', 'station', 2, 180),
+                ('platform 1', 'platform 2', 2, 60),
+                ('station', 'bus stop', 0, 300),
+                ('bus stop', 'bus stop', 2, 30),
+                ('bus stop', 'platform 1', 1, NULL),
+                ('bus stop', 'platform 2', 3, NULL);",
+        )
+        .await;
+
+        // Act
+        let stops = get_stops(&connection).await.unwrap();
+
+        // Assert
+        let index = |id: &str| stops.index_by_stop_id[id];
+        let walks = |id: &str| {
+            let stop = &stops.stops[index(id)];
+            stops.transfers[stop.transfers_index_start..][..stop.transfers_count]
+                .iter()
+                .map(|transfer| (transfer.target, transfer.time))
+                .collect::<Vec<(usize, Time)>>()
+        };
+
+        // Transfers declared on the platforms take precedence over the ones on the station
+        assert_eq!(
+            vec![(index("platform 2"), Time::from(60)), (index("bus stop"), Time::from(300))],
+            walks("platform 1")
+        );
+        assert_eq!(
+            vec![(index("platform 1"), Time::from(180)), (index("bus stop"), Time::from(300))],
+            walks("platform 2")
+        );
+        // Transfers that are not possible are no foot-paths
+        assert_eq!(vec![(index("platform 1"), Time::from(0))], walks("bus stop"));
+        assert_eq!(
+            Some(Time::from(180)),
+            stops.stops[index("platform 1")].minimum_change_time
+        );
+        assert_eq!(
+            Some(Time::from(30)),
+            stops.stops[index("bus stop")].minimum_change_time
+        );
+        assert!(walks("station").is_empty());
+        // Station transfers don't apply to entrances
+        assert!(walks("entrance").is_empty());
+        assert_eq!(None, stops.stops[index("entrance")].minimum_change_time);
+    }
 
-    Ok(RaptorDataSet { index_by_stop_id, routes_data, stops_data })
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn walks_pathways_between_platforms_for_wheelchair_users() {
+        // Arrange
+        // The stairs down to the concourse are faster than the elevator
+        let connection = database(
+            "INSERT INTO stops (id, parent_station, location_type) VALUES
+                ('station', NULL, 1),
+                ('platform 1', 'station', 0),
+                ('platform 2', 'station', NULL),
+                ('concourse', 'station', 3);
+            INSERT INTO pathways VALUES
+                ('stairs', 'platform 1', 'concourse', 2, 1, NULL, 30),
+                ('walkway', 'concourse', 'platform 2', 1, 1, 36, NULL),
+                ('elevator', 'platform 1', 'platform 2', 5, 0, NULL, 120);",
+        )
+        .await;
+
+        // Act
+        let stops = get_stops(&connection).await.unwrap();
+
+        // Assert
+        let index = |id: &str| stops.index_by_stop_id[id];
+        let walks = |id: &str| {
+            let stop = &stops.stops[index(id)];
+            stops.transfers[stop.transfers_index_start..][..stop.transfers_count]
+                .iter()
+                .map(|transfer| {
+                    (
+                        transfer.target,
+                        transfer.time,
+                        transfer.wheelchair_accessible,
+                        transfer.wheelchair_only,
+                    )
+                })
+                .collect::<Vec<(usize, Time, bool, bool)>>()
+        };
+
+        // Stairs are left out, so only wheelchair users take the elevator
+        assert_eq!(
+            vec![(index("platform 2"), Time::from(120), true, true)],
+            walks("platform 1")
+        );
+        // The elevator only goes one way
+        assert!(walks("platform 2").is_empty());
+        // Walks only connect platforms
+        assert!(walks("concourse").is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_declared_transfers_over_stairs_from_wheelchair_users() {
+        // Arrange
+        // The transfer of the feed doesn't tell that the only way between the platforms are stairs
+        let connection = database(
+            "INSERT INTO stops (id, parent_station, location_type) VALUES
+                ('station', NULL, 1),
+                ('platform 1', 'station', 0),
+                ('platform 2', 'station', 0),
+                ('bus stop', NULL, 0);
+            INSERT INTO transfers (from_stop_id, to_stop_id, type, minimum_transfer_time) VALUES
+                ('platform 1', 'platform 2', 2, 60),
+                ('platform 1', 'bus stop', 2, 120);
+            INSERT INTO pathways VALUES
+                ('stairs', 'platform 1', 'platform 2', 2, 1, NULL, 30);",
+        )
+        .await;
+
+        // Act
+        let stops = get_stops(&connection).await.unwrap();
+
+        // Assert
+        let index = |id: &str| stops.index_by_stop_id[id];
+        let stop = &stops.stops[index("platform 1")];
+        let walks: Vec<(usize, Time, bool, bool)> = stops.transfers
+            [stop.transfers_index_start..][..stop.transfers_count]
+            .iter()
+            .map(|transfer| {
+                (
+                    transfer.target,
+                    transfer.time,
+                    transfer.wheelchair_accessible,
+                    transfer.wheelchair_only,
+                )
+            })
+            .collect();
+        // Only walks leaving the station are still assumed to be accessible
+        assert_eq!(
+            vec![
+                (index("platform 2"), Time::from(60), false, false),
+                (index("bus stop"), Time::from(120), true, false),
+            ],
+            walks
+        );
+    }
+
+    #[tokio::test]
+    async fn marks_accessible_stop_times() {
+        // Arrange
+        // The platform inherits being accessible from the station
+        let connection = database(
+            "INSERT INTO stops (id, parent_station, wheelchair_boarding) VALUES
+                ('station', NULL, 1),
+                ('platform', 'station', 0),
+                ('stairs only', NULL, 2);
+            INSERT INTO trips (id, service_id, wheelchair_accessible) VALUES
+                ('low floor', 'daily', 1),
+                ('high floor', 'daily', 2);
+            INSERT INTO stop_times VALUES
+                ('low floor', 'platform', 1, 100, 100, NULL, NULL),
+                ('low floor', 'stairs only', 2, 200, 200, NULL, NULL),
+                ('high floor', 'platform', 1, 300, 300, NULL, NULL),
+                ('high floor', 'stairs only', 2, 400, 400, NULL, NULL);
+            INSERT INTO calendar VALUES
+                ('daily', 1, 1, 1, 1, 1, 1, 1, '20240101', '20241231');",
+        )
+        .await;
+
+        // Act
+        let data = setup_raptor(&connection, &date!(2024 - 09 - 10)).await.unwrap();
+
+        // Assert
+        let stop_times = &data.timetable.routes_data.stop_times;
+        let accessible = |time: u64| {
+            stop_times
+                .iter()
+                .find(|stop_time| stop_time.departure_time == Time::from(time))
+                .unwrap()
+                .wheelchair_accessible
+        };
+        assert!(accessible(100));
+        assert!(!accessible(200));
+        assert!(!accessible(300));
+        assert!(!accessible(400));
+    }
+}
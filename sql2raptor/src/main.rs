@@ -0,0 +1,87 @@
+//! Writes a snapshot of the RAPTOR data for a service date, so the api and benchmark can map it
+//! instead of loading it from SQLite. The snapshot includes the foot-paths generated between close
+//! stops, which can also be saved to the generated_transfers table of the database.
+//!
+//! Usage: `sql2raptor <database> <service date as YYYY-MM-DD> <snapshot directory>
+//! [--save-generated-transfers]`
+
+use sql2raptor::footpaths::{save_generated_transfers, FootPathSettings};
+use sql2raptor::setup_raptor_with_foot_paths;
+use sql2raptor::snapshot::{snapshot_path, write_snapshot};
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use time::macros::format_description;
+use time::Date;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let arguments: Vec<String> = env::args().collect();
+    let (database_path, service_date, directory, save_transfers) = match arguments.as_slice() {
+        [_, database_path, service_date, directory] => {
+            (database_path, service_date, directory, false)
+        }
+        [_, database_path, service_date, directory, flag] if flag == SAVE_TRANSFERS_FLAG => {
+            (database_path, service_date, directory, true)
+        }
+        _ => {
+            eprintln!(
+                "Usage: sql2raptor <database> <service date as YYYY-MM-DD> <snapshot directory> \
+                [{SAVE_TRANSFERS_FLAG}]"
+            );
+            std::process::exit(2);
+        }
+    };
+
+    let Ok(service_date) = Date::parse(service_date, format_description!("[year]-[month]-[day]"))
+    else {
+        eprintln!("Service date {service_date} should be formatted as YYYY-MM-DD");
+        std::process::exit(2);
+    };
+
+    match write(
+        database_path,
+        &service_date,
+        Path::new(directory),
+        save_transfers,
+    )
+    .await
+    {
+        Ok(path) => println!("Wrote snapshot to {}", path.display()),
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Also saves the generated foot-paths to the generated_transfers table of the database
+const SAVE_TRANSFERS_FLAG: &str = "--save-generated-transfers";
+
+/// Loads the RAPTOR data from the database, generates the foot-paths between close stops and writes
+/// the snapshot into the directory
+async fn write(
+    database_path: &str,
+    service_date: &Date,
+    directory: &Path,
+    save_transfers: bool,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let database = libsql::Builder::new_local(database_path).build().await?;
+    let connection = database.connect()?;
+    // Many feeds have no or only few transfers, so connect stops that are close to each other
+    let data =
+        setup_raptor_with_foot_paths(&connection, service_date, &FootPathSettings::default())
+            .await?;
+    println!(
+        "Split trips with the same stops {} times into another route as they overtake each other",
+        data.overtaking_splits
+    );
+
+    if save_transfers {
+        save_generated_transfers(&connection, &data.timetable.stops_data).await?;
+    }
+
+    let path = snapshot_path(directory, service_date);
+    write_snapshot(&path, service_date, &data)?;
+    Ok(path)
+}
@@ -0,0 +1,164 @@
+//! Pathways connect the platforms, entrances and other nodes inside stations by walkways, stairs,
+//! elevators and the like. They become foot-paths between the platforms of a station for wheelchair
+//! users, who need the ways without stairs. Everyone else keeps walking the transfers of the feed.
+//! In stations with pathways, other walks between platforms are not taken by wheelchair users as
+//! only the pathways tell whether they have stairs.
+
+use crate::footpaths::{close_foot_paths, FootPathSettings};
+use libsql::Connection;
+use raptor::shared::Transfer;
+use raptor::Time;
+use std::collections::HashMap;
+
+/// GTFS pathway mode for stairs
+const STAIRS: u32 = 2;
+/// GTFS pathway mode for escalators
+const ESCALATOR: u32 = 4;
+/// GTFS location type for boarding areas, which belong to a platform instead of a station
+const BOARDING_AREA: u32 = 4;
+
+/// Get the station of the stops in stations with pathways by stop index. Stops outside of stations
+/// or in stations without pathways are none
+pub(crate) async fn get_pathway_stations(
+    connection: &Connection,
+    index_by_stop_id: &HashMap<String, usize>,
+) -> Result<Vec<Option<usize>>, libsql::Error> {
+    let mut rows = connection
+        .query("SELECT id, parent_station, location_type FROM stops;", ())
+        .await?;
+
+    let mut parents: Vec<Option<(usize, bool)>> = vec![None; index_by_stop_id.len()];
+    while let Some(row) = rows.next().await? {
+        let stop_id: String = row.get(0 /* id */)?;
+        let parent_station: Option<String> = row.get(1 /* parent_station */)?;
+        let location_type: Option<u32> = row.get(2 /* location_type */)?;
+        let (Some(&stop), Some(&parent)) = (
+            index_by_stop_id.get(&stop_id),
+            parent_station.and_then(|parent| index_by_stop_id.get(&parent)),
+        ) else {
+            continue;
+        };
+
+        parents[stop] = Some((parent, location_type == Some(BOARDING_AREA)));
+    }
+
+    let stations: Vec<Option<usize>> = parents
+        .iter()
+        .map(|parent| match *parent {
+            // The platform of a boarding area belongs to the station
+            Some((platform, true)) => parents[platform].map(|(station, _)| station),
+            Some((station, false)) => Some(station),
+            None => None,
+        })
+        .collect();
+
+    let mut rows = connection
+        .query("SELECT from_stop_id, to_stop_id FROM pathways;", ())
+        .await?;
+
+    let mut has_pathways = vec![false; stations.len()];
+    while let Some(row) = rows.next().await? {
+        for column in [0 /* from_stop_id */, 1 /* to_stop_id */] {
+            let stop_id: String = row.get(column)?;
+            if let Some(station) = index_by_stop_id
+                .get(&stop_id)
+                .and_then(|&stop| stations[stop])
+            {
+                has_pathways[station] = true;
+            }
+        }
+    }
+
+    Ok(stations
+        .into_iter()
+        .map(|station| station.filter(|&station| has_pathways[station]))
+        .collect())
+}
+
+/// Whether both stops are in the same station with pathways, so the walk between them is only
+/// accessible through the pathways
+pub(crate) fn is_within_pathway_station(
+    pathway_stations: &[Option<usize>],
+    source: usize,
+    target: usize,
+) -> bool {
+    pathway_stations[source].is_some() && pathway_stations[source] == pathway_stations[target]
+}
+
+/// Get the shortest walks through the pathways between the platforms of stations by the stop index
+/// of the platform they start at. Walks only lead through other nodes of the stations but start and
+/// end at platforms as only these are served by trips. Stairs and escalators are left out, so the
+/// walks are only taken by wheelchair users
+pub(crate) async fn get_pathway_walks(
+    connection: &Connection,
+    index_by_stop_id: &HashMap<String, usize>,
+    is_platform: &[bool],
+) -> Result<Vec<Vec<Transfer>>, libsql::Error> {
+    let mut rows = connection
+        .query(
+            "SELECT from_stop_id, to_stop_id, mode, is_bidirectional, length, traversal_time
+            FROM pathways;",
+            (),
+        )
+        .await?;
+
+    let settings = FootPathSettings::default();
+    let mut neighbours: Vec<Vec<Transfer>> = vec![Vec::new(); is_platform.len()];
+    let mut has_pathways = false;
+    while let Some(row) = rows.next().await? {
+        let from_stop_id: String = row.get(0 /* from_stop_id */)?;
+        let to_stop_id: String = row.get(1 /* to_stop_id */)?;
+        let (Some(&from), Some(&to)) = (
+            index_by_stop_id.get(&from_stop_id),
+            index_by_stop_id.get(&to_stop_id),
+        ) else {
+            continue;
+        };
+
+        let mode: u32 = row.get(2 /* mode */)?;
+        if mode == STAIRS || mode == ESCALATOR {
+            continue;
+        }
+
+        let is_bidirectional: bool = row.get(3 /* is_bidirectional */)?;
+        let length: Option<f64> = row.get(4 /* length */)?;
+        let traversal_time: Option<u64> = row.get(5 /* traversal_time */)?;
+        // Pathways without a time or length like fare gates are assumed to be passed right away
+        let time = traversal_time
+            .or_else(|| length.map(|length| (length / settings.walking_speed).round() as u64))
+            .unwrap_or(0);
+        let walk = |target| Transfer {
+            target,
+            time: Time::from(time),
+            wheelchair_accessible: true,
+            wheelchair_only: true,
+        };
+
+        neighbours[from].push(walk(to));
+        if is_bidirectional {
+            neighbours[to].push(walk(from));
+        }
+        has_pathways = true;
+    }
+
+    if !has_pathways {
+        return Ok(neighbours);
+    }
+
+    let walks = close_foot_paths(&neighbours, Time::from(settings.max_walking_time))
+        .into_iter()
+        .enumerate()
+        .map(|(source, walks)| {
+            if !is_platform[source] {
+                return Vec::new();
+            }
+
+            walks
+                .into_iter()
+                .filter(|walk| is_platform[walk.target])
+                .collect()
+        })
+        .collect();
+
+    Ok(walks)
+}
@@ -0,0 +1,336 @@
+//! Snapshots store the RAPTOR data of a service date in a file, so it doesn't need to be assembled
+//! from SQLite on every start. The file is memory-mapped and checked before it is used.
+//! The algorithms run in place on the archived timetable in the mapped file, so it is neither
+//! copied nor deserialized.
+//!
+//! A snapshot starts with a header followed by the data archived with rkyv:
+//!
+//! | Bytes  | Content                                         |
+//! |--------|-------------------------------------------------|
+//! | 0..8   | Magic bytes to recognize snapshots              |
+//! | 8..12  | Format version                                  |
+//! | 12..16 | Service date as julian day                      |
+//! | 16..24 | Length of the archived data                     |
+//! | 24..28 | CRC-32 checksum of the archived data            |
+//! | 28..32 | Reserved to keep the archived data aligned      |
+//!
+//! All numbers are little endian.
+
+use crate::{ArchivedRaptorDataSet, RaptorDataSet};
+use memmap2::Mmap;
+use rkyv::rancor;
+use rkyv::util::AlignedVec;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use time::Date;
+
+const MAGIC: [u8; 8] = *b"RAPTORSN";
+/// Increase when the RAPTOR data structures change, as older snapshots can not be read anymore
+pub const SNAPSHOT_VERSION: u32 = 7;
+const HEADER_LENGTH: usize = 32;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    /// The file is too short or doesn't start with the magic bytes
+    NotASnapshot,
+    /// The snapshot was written by a different version of the format
+    UnsupportedVersion {
+        version: u32,
+    },
+    /// The snapshot contains the data for another service date
+    WrongServiceDate {
+        service_date: Option<Date>,
+    },
+    /// The archived data is truncated or was changed after writing
+    ChecksumMismatch,
+    /// The archived data does not match the RAPTOR data structures
+    Invalid(rancor::Error),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(formatter, "Could not access snapshot: {error}"),
+            SnapshotError::NotASnapshot => write!(formatter, "File is not a snapshot"),
+            SnapshotError::UnsupportedVersion { version } => write!(
+                formatter,
+                "Snapshot version {version} is not supported. Expected version {SNAPSHOT_VERSION}"
+            ),
+            SnapshotError::WrongServiceDate {
+                service_date: Some(service_date),
+            } => write!(formatter, "Snapshot is for service date {service_date}"),
+            SnapshotError::WrongServiceDate { service_date: None } => {
+                write!(formatter, "Snapshot has an invalid service date")
+            }
+            SnapshotError::ChecksumMismatch => write!(formatter, "Snapshot is corrupt"),
+            SnapshotError::Invalid(error) => write!(formatter, "Snapshot data is invalid: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl From<rancor::Error> for SnapshotError {
+    fn from(error: rancor::Error) -> Self {
+        SnapshotError::Invalid(error)
+    }
+}
+
+/// The path of the snapshot for the service date in the directory
+pub fn snapshot_path(directory: &Path, service_date: &Date) -> PathBuf {
+    directory.join(format!("{service_date}.raptor"))
+}
+
+/// Writes the RAPTOR data loaded for the service date to a snapshot file
+pub fn write_snapshot(
+    path: &Path,
+    service_date: &Date,
+    data: &RaptorDataSet,
+) -> Result<(), SnapshotError> {
+    let archived = rkyv::to_bytes::<rancor::Error>(data)?;
+
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+    header.extend_from_slice(&service_date.to_julian_day().to_le_bytes());
+    header.extend_from_slice(&(archived.len() as u64).to_le_bytes());
+    header.extend_from_slice(&crc32fast::hash(&archived).to_le_bytes());
+    header.resize(HEADER_LENGTH, 0);
+
+    let mut file = File::create(path)?;
+    file.write_all(&header)?;
+    file.write_all(&archived)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Checks the header and returns the archived data after it
+fn check_snapshot<'a>(bytes: &'a [u8], service_date: &Date) -> Result<&'a [u8], SnapshotError> {
+    if bytes.len() < HEADER_LENGTH || bytes[0..8] != MAGIC {
+        return Err(SnapshotError::NotASnapshot);
+    }
+
+    let header = &bytes[..HEADER_LENGTH];
+    let read_u32 = |start: usize| u32::from_le_bytes(header[start..start + 4].try_into().unwrap());
+
+    let version = read_u32(8);
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion { version });
+    }
+
+    let julian_day = read_u32(12) as i32;
+    if julian_day != service_date.to_julian_day() {
+        return Err(SnapshotError::WrongServiceDate {
+            service_date: Date::from_julian_day(julian_day).ok(),
+        });
+    }
+
+    let length = u64::from_le_bytes(header[16..24].try_into().unwrap());
+    let archived = &bytes[HEADER_LENGTH..];
+    if archived.len() as u64 != length || crc32fast::hash(archived) != read_u32(24) {
+        return Err(SnapshotError::ChecksumMismatch);
+    }
+
+    Ok(archived)
+}
+
+/// Where the archived data of a snapshot is kept
+enum SnapshotBytes {
+    /// The snapshot file mapped into memory, including the header
+    Mapped(Mmap),
+    /// Data archived in memory without a header
+    InMemory(AlignedVec),
+}
+
+/// RAPTOR data in its archived form that was checked to be intact. Queries run in place on
+/// [ArchivedRaptorDataSet::timetable]
+pub struct Snapshot {
+    bytes: SnapshotBytes,
+}
+
+impl Snapshot {
+    /// Maps the snapshot file for the service date into memory. Refuses snapshots of another
+    /// format version or service date and snapshots that don't match their checksum
+    pub fn open(path: &Path, service_date: &Date) -> Result<Snapshot, SnapshotError> {
+        let file = File::open(path)?;
+        // SAFETY: Snapshots are written once and not changed while they are used. Changes would
+        // also be noticed by the checksum and validation, unless they happen after those checks
+        let map = unsafe { Mmap::map(&file)? };
+        let archived = check_snapshot(&map, service_date)?;
+        // Validate once, so the data can be accessed without checks later
+        rkyv::access::<ArchivedRaptorDataSet, rancor::Error>(archived)?;
+        Ok(Snapshot {
+            bytes: SnapshotBytes::Mapped(map),
+        })
+    }
+
+    /// Archives the RAPTOR data in memory, so it is queried like a mapped snapshot when there is no
+    /// snapshot file
+    pub fn archive(data: &RaptorDataSet) -> Result<Snapshot, SnapshotError> {
+        let archived = rkyv::to_bytes::<rancor::Error>(data)?;
+        Ok(Snapshot {
+            bytes: SnapshotBytes::InMemory(archived),
+        })
+    }
+
+    /// The archived RAPTOR data to query and to look up stops by their id in place
+    pub fn archived(&self) -> &ArchivedRaptorDataSet {
+        let archived = match &self.bytes {
+            SnapshotBytes::Mapped(map) => &map[HEADER_LENGTH..],
+            SnapshotBytes::InMemory(archived) => archived.as_slice(),
+        };
+        // SAFETY: Mapped data was validated when the snapshot was opened and the map is read only.
+        // Data archived in memory was just serialized from valid data and is not changed
+        unsafe { rkyv::access_unchecked::<ArchivedRaptorDataSet>(archived) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::details::{GtfsDetails, RouteDetails, TripDetails};
+    use crate::snapshot::{write_snapshot, Snapshot, SnapshotError};
+    use crate::RaptorDataSet;
+    use raptor::shared::{Route, RoutesData, Stop, StopTime, StopsData, Timetable, TimetableView};
+    use raptor::Time;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use time::macros::date;
+
+    fn data() -> RaptorDataSet {
+        let stop = |id: &str| Stop {
+            id: id.to_string(),
+            transfers_index_start: 0,
+            stop_routes_index_start: 0,
+            transfers_count: 0,
+            stop_routes_count: 1,
+            minimum_change_time: None,
+        };
+        let stop_time = |time: u64| StopTime {
+            departure_time: Time::from(time),
+            arrival_time: Time::from(time),
+            can_board: true,
+            can_exit: true,
+            wheelchair_accessible: true,
+        };
+
+        RaptorDataSet {
+            index_by_stop_id: HashMap::from([("a".to_string(), 0), ("b".to_string(), 1)]),
+            timetable: Timetable {
+                routes_data: RoutesData {
+                    stop_times: vec![stop_time(100), stop_time(200)],
+                    routes: vec![Route {
+                        number_of_trips: 1,
+                        number_of_stops: 2,
+                        route_stops_start_index: 0,
+                        stop_times_start_index: 0,
+                        headway: None,
+                    }],
+                    route_stops: vec![0, 1],
+                    continuations: Vec::new(),
+                },
+                stops_data: StopsData {
+                    transfers: Vec::new(),
+                    stops: vec![stop("a"), stop("b")],
+                    stop_routes: vec![0, 0],
+                    stop_route_positions: vec![0, 1],
+                    default_change_time: Time::from(0),
+                },
+            },
+            details: GtfsDetails {
+                routes: vec![RouteDetails {
+                    id: "route".to_string(),
+                    short_name: Some("42".to_string()),
+                    long_name: None,
+                    route_type: Some(3),
+                }],
+                trips: vec![vec![TripDetails {
+                    id: "trip".to_string(),
+                    headsign: Some("Central".to_string()),
+                    short_name: None,
+                }]],
+            },
+            overtaking_splits: 0,
+        }
+    }
+
+    /// A path in the temporary directory that is unique for the test
+    fn temporary_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sql2raptor-{}-{name}.raptor", std::process::id()))
+    }
+
+    #[test]
+    fn reads_written_snapshot() {
+        // Arrange
+        let path = temporary_path("read");
+        write_snapshot(&path, &date!(2024 - 09 - 10), &data()).unwrap();
+
+        // Act
+        let snapshot = Snapshot::open(&path, &date!(2024 - 09 - 10)).unwrap();
+        let data = snapshot.archived();
+
+        // Assert
+        assert_eq!(
+            Some(1),
+            data.index_by_stop_id
+                .get("b")
+                .map(|index| index.to_native())
+        );
+        assert_eq!(
+            Some("Central"),
+            data.details
+                .get_trip(0, 0)
+                .headsign
+                .as_ref()
+                .map(|headsign| headsign.as_str())
+        );
+        assert_eq!(Time::from(200), data.timetable.stop_time(1).arrival_time);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn refuses_corrupt_snapshot() {
+        // Arrange
+        let path = temporary_path("corrupt");
+        write_snapshot(&path, &date!(2024 - 09 - 10), &data()).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        fs::write(&path, bytes).unwrap();
+
+        // Act
+        let result = Snapshot::open(&path, &date!(2024 - 09 - 10));
+
+        // Assert
+        assert!(matches!(result, Err(SnapshotError::ChecksumMismatch)));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn refuses_snapshot_of_other_date() {
+        // Arrange
+        let path = temporary_path("date");
+        write_snapshot(&path, &date!(2024 - 09 - 10), &data()).unwrap();
+
+        // Act
+        let result = Snapshot::open(&path, &date!(2024 - 09 - 11));
+
+        // Assert
+        assert!(matches!(
+            result,
+            Err(SnapshotError::WrongServiceDate {
+                service_date: Some(service_date)
+            }) if service_date == date!(2024 - 09 - 10)
+        ));
+        fs::remove_file(path).unwrap();
+    }
+}